@@ -2,9 +2,11 @@ use crate::ConfigOverride;
 use anchor_client::Cluster;
 use anchor_syn::idl::Idl;
 use anyhow::{anyhow, Error, Result};
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
+use solana_sdk::signature::{Keypair, Signer};
 use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::prelude::*;
@@ -15,7 +17,9 @@ use std::str::FromStr;
 #[derive(Debug, Default)]
 pub struct Config {
     pub provider: ProviderConfig,
-    pub clusters: ClustersConfig,
+    pub programs: ProgramsConfig,
+    pub scripts: ScriptsConfig,
+    pub build: Option<BuildConfig>,
     pub test: Option<Test>,
 }
 
@@ -25,7 +29,19 @@ pub struct ProviderConfig {
     pub wallet: WalletPath,
 }
 
-pub type ClustersConfig = BTreeMap<Cluster, BTreeMap<String, ProgramDeployment>>;
+// Maps each cluster to the addresses of the workspace programs deployed to
+// it, keyed by lib name. This is the source of truth for program addresses.
+pub type ProgramsConfig = BTreeMap<Cluster, BTreeMap<String, ProgramDeployment>>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildConfig {
+    // Docker image used for verifiable builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docker_image: Option<String>,
+}
+
+// Maps script names to shell commands.
+pub type ScriptsConfig = BTreeMap<String, String>;
 
 impl Config {
     pub fn discover(
@@ -92,6 +108,53 @@ impl Config {
         solana_sdk::signature::read_keypair_file(&self.provider.wallet.to_string())
             .map_err(|_| anyhow!("Unable to read keypair file"))
     }
+
+    // Returns the shell command for the given script name. Anything that's
+    // not the name of a script is assumed to be a command itself.
+    pub fn script<'a>(&'a self, script: &'a str) -> &'a str {
+        self.scripts
+            .get(script)
+            .map(|s| s.as_str())
+            .unwrap_or(script)
+    }
+
+    // Returns the test suite with the given name.
+    pub fn test_suite(&self, name: &str) -> Result<&TestSuite> {
+        self.test
+            .as_ref()
+            .and_then(|test| test.suites.get(name))
+            .ok_or_else(|| anyhow!("Test suite not found: {}", name))
+    }
+
+    // Returns all test suites, in name order.
+    pub fn test_suites(&self) -> Vec<(&String, &TestSuite)> {
+        self.test
+            .as_ref()
+            .map(|test| test.suites.iter().collect())
+            .unwrap_or_default()
+    }
+
+    // Returns the docker image used for verifiable builds, defaulting to the
+    // image matching the CLI version.
+    pub fn docker_image(&self) -> String {
+        self.build
+            .as_ref()
+            .and_then(|build| build.docker_image.clone())
+            .unwrap_or_else(|| format!("projectserum/build:v{}", crate::DOCKER_BUILDER_VERSION))
+    }
+
+    // Returns the address of the program on the configured cluster, from
+    // `[programs.<cluster>]` if given, otherwise from its keypair.
+    pub fn program_id(&self, program: &Program) -> Result<Pubkey> {
+        match self
+            .programs
+            .get(&self.provider.cluster)
+            .and_then(|programs| programs.get(&program.lib_name))
+        {
+            Some(deployment) => Ok(deployment.program_id),
+            None => Ok(program.keypair()?.pubkey()),
+        }
+    }
 }
 
 // Pubkey serializes as a byte array so use this type a hack to serialize
@@ -99,7 +162,12 @@ impl Config {
 #[derive(Debug, Serialize, Deserialize)]
 struct _Config {
     provider: Provider,
+    build: Option<BuildConfig>,
     test: Option<Test>,
+    scripts: Option<ScriptsConfig>,
+    programs: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    // Deprecated alias for `programs`.
+    #[serde(skip_serializing)]
     clusters: Option<BTreeMap<String, BTreeMap<String, String>>>,
 }
 
@@ -111,8 +179,8 @@ struct Provider {
 
 impl ToString for Config {
     fn to_string(&self) -> String {
-        let clusters = {
-            let c = ser_clusters(&self.clusters);
+        let programs = {
+            let c = ser_programs(&self.programs);
             if c.len() == 0 {
                 None
             } else {
@@ -124,8 +192,14 @@ impl ToString for Config {
                 cluster: format!("{}", self.provider.cluster),
                 wallet: self.provider.wallet.to_string(),
             },
+            build: self.build.clone(),
             test: self.test.clone(),
-            clusters,
+            scripts: match self.scripts.is_empty() {
+                true => None,
+                false => Some(self.scripts.clone()),
+            },
+            programs,
+            clusters: None,
         };
 
         toml::to_string(&cfg).expect("Must be well formed")
@@ -138,23 +212,31 @@ impl FromStr for Config {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let cfg: _Config = toml::from_str(s)
             .map_err(|e| anyhow::format_err!("Unable to deserialize config: {}", e.to_string()))?;
+        if let Some(test) = cfg.test.as_ref() {
+            test.validate()?;
+        }
         Ok(Config {
             provider: ProviderConfig {
                 cluster: cfg.provider.cluster.parse()?,
                 wallet: shellexpand::tilde(&cfg.provider.wallet).parse()?,
             },
+            scripts: cfg.scripts.unwrap_or_default(),
+            build: cfg.build,
             test: cfg.test,
-            clusters: cfg
-                .clusters
-                .map_or(Ok(BTreeMap::new()), |c| deser_clusters(c))?,
+            programs: {
+                // Entries in `programs` take precedence over `clusters`.
+                let mut programs = cfg.clusters.unwrap_or_default();
+                for (cluster, p) in cfg.programs.unwrap_or_default() {
+                    programs.entry(cluster).or_default().extend(p);
+                }
+                deser_programs(programs)?
+            },
         })
     }
 }
 
-fn ser_clusters(
-    clusters: &BTreeMap<Cluster, BTreeMap<String, ProgramDeployment>>,
-) -> BTreeMap<String, BTreeMap<String, String>> {
-    clusters
+fn ser_programs(programs: &ProgramsConfig) -> BTreeMap<String, BTreeMap<String, String>> {
+    programs
         .iter()
         .map(|(cluster, programs)| {
             let cluster = cluster.to_string();
@@ -167,10 +249,8 @@ fn ser_clusters(
         .collect::<BTreeMap<String, BTreeMap<String, String>>>()
 }
 
-fn deser_clusters(
-    clusters: BTreeMap<String, BTreeMap<String, String>>,
-) -> Result<BTreeMap<Cluster, BTreeMap<String, ProgramDeployment>>> {
-    clusters
+fn deser_programs(programs: BTreeMap<String, BTreeMap<String, String>>) -> Result<ProgramsConfig> {
+    programs
         .iter()
         .map(|(cluster, programs)| {
             let cluster: Cluster = cluster.parse()?;
@@ -188,12 +268,282 @@ fn deser_clusters(
                 .collect::<Result<BTreeMap<String, ProgramDeployment>>>()?;
             Ok((cluster, programs))
         })
-        .collect::<Result<BTreeMap<Cluster, BTreeMap<String, ProgramDeployment>>>>()
+        .collect::<Result<ProgramsConfig>>()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Test {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub genesis: Vec<GenesisEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validator: Option<Validator>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub suites: BTreeMap<String, TestSuite>,
+}
+
+impl Test {
+    fn validate(&self) -> Result<()> {
+        if let Some(validator) = self.validator.as_ref() {
+            validator
+                .validate()
+                .map_err(|e| anyhow!("Invalid [test.validator]: {}", e))?;
+        }
+        for (name, suite) in &self.suites {
+            if suite.script.trim().is_empty() {
+                return Err(anyhow!("Test suite {} must have a script", name));
+            }
+            if let Some(validator) = suite.validator.as_ref() {
+                validator
+                    .validate()
+                    .map_err(|e| anyhow!("Invalid validator for test suite {}: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A named set of tests, run against its own local validator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestSuite {
+    // Name of a `[scripts]` entry or a shell command running the tests.
+    pub script: String,
+    // Programs to embed in the genesis for this suite only, in addition to
+    // `[test] genesis`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genesis: Vec<GenesisEntry>,
+    // True if the suite should run against the configured cluster instead
+    // of a local validator.
+    #[serde(default)]
+    pub skip_local_validator: bool,
+    // Replaces `[test.validator]` for this suite.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validator: Option<Validator>,
+}
+
+// Settings for the local validator started by `anchor test`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Validator {
+    // Ledger directory, cleared before every run. Directories that aren't
+    // empty or a ledger, i.e., don't contain a `genesis.bin`, are never
+    // cleared. Defaults to `.anchor/test-ledger`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<String>,
+    // JSON RPC port. The websocket port is the next port.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slots_per_epoch: Option<u64>,
+    // Slot to warp the ledger to on startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warp_slot: Option<u64>,
+    // Milliseconds to wait for the validator to start. Defaults to 5000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_wait: Option<u64>,
+    // True if the workspace programs should be deployed with the
+    // upgradeable loader after startup, rather than embedded immutably in
+    // the genesis.
+    #[serde(default)]
+    pub upgradeable: bool,
+    // Cluster to clone accounts from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clone: Vec<CloneEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub account: Vec<AccountEntry>,
+}
+
+// Minimum slots per epoch accepted by the runtime.
+const MINIMUM_SLOTS_PER_EPOCH: u64 = 32;
+
+impl Validator {
+    pub fn validate(&self) -> Result<()> {
+        if self.rpc_port == Some(0) {
+            return Err(anyhow!("rpc_port must be non-zero"));
+        }
+        if let Some(slots_per_epoch) = self.slots_per_epoch {
+            if slots_per_epoch < MINIMUM_SLOTS_PER_EPOCH {
+                return Err(anyhow!(
+                    "slots_per_epoch must be at least {}",
+                    MINIMUM_SLOTS_PER_EPOCH
+                ));
+            }
+        }
+        if self.startup_wait == Some(0) {
+            return Err(anyhow!("startup_wait must be non-zero"));
+        }
+        if let Some(url) = self.url.as_ref() {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow!("url must be an http or https url: {}", url));
+            }
+        }
+        if !self.clone.is_empty() && self.url.is_none() {
+            return Err(anyhow!("url is required to clone accounts"));
+        }
+        for entry in &self.clone {
+            parse_address(&entry.address)?;
+        }
+        for entry in &self.account {
+            entry.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn rpc_port(&self) -> u16 {
+        self.rpc_port.unwrap_or(8899)
+    }
+
+    pub fn startup_wait(&self) -> u64 {
+        self.startup_wait.unwrap_or(5000)
+    }
+
+    pub fn ledger(&self) -> &str {
+        self.ledger.as_deref().unwrap_or(".anchor/test-ledger")
+    }
+}
+
+// An account cloned from `url` on startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneEntry {
+    // Base58 pubkey string.
+    pub address: String,
+}
+
+// An account loaded into the validator on startup, either from a JSON
+// fixture file, in the format of `solana account --output json`, or from the
+// inline fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountEntry {
+    // Base58 pubkey string.
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lamports: Option<u64>,
+    // Base58 pubkey string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    // Base64 encoded account data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default)]
+    pub executable: bool,
+}
+
+impl AccountEntry {
+    fn validate(&self) -> Result<()> {
+        parse_address(&self.address)?;
+        let inline = self.lamports.is_some() || self.owner.is_some() || self.data.is_some();
+        match (&self.filename, inline) {
+            (Some(_), true) => Err(anyhow!(
+                "Account {} must have either a filename or inline fields, not both",
+                self.address
+            )),
+            (Some(_), false) => Ok(()),
+            (None, _) => {
+                if self.lamports.is_none() {
+                    return Err(anyhow!("Account {} must have lamports", self.address));
+                }
+                let owner = self
+                    .owner
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Account {} must have an owner", self.address))?;
+                parse_address(owner)?;
+                if let Some(data) = self.data.as_ref() {
+                    base64::decode(data)
+                        .map_err(|e| anyhow!("Invalid data for account {}: {}", self.address, e))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Returns the fixture for the account, reading it from `filename`, if given.
+    pub fn fixture(&self) -> Result<AccountFixture> {
+        let fixture = match self.filename.as_ref() {
+            Some(filename) => AccountFixture::from_file(filename)?,
+            None => AccountFixture {
+                pubkey: self.address.clone(),
+                account: FixtureAccount {
+                    lamports: self.lamports.unwrap_or_default(),
+                    data: (self.data.clone().unwrap_or_default(), "base64".to_string()),
+                    owner: self.owner.clone().unwrap_or_default(),
+                    executable: self.executable,
+                    rent_epoch: 0,
+                },
+            },
+        };
+        if fixture.pubkey != self.address {
+            return Err(anyhow!(
+                "Account fixture {} doesn't match address {}",
+                fixture.pubkey,
+                self.address
+            ));
+        }
+        Ok(fixture)
+    }
+}
+
+// A snapshot of an account, as written by `anchor account dump`. This is the
+// same format as `solana account --output json`, and so can be loaded with
+// `solana-test-validator --account`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountFixture {
+    // Base58 pubkey string.
+    pub pubkey: String,
+    pub account: FixtureAccount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureAccount {
+    pub lamports: u64,
+    // Account data and its encoding, which is always "base64".
+    pub data: (String, String),
+    // Base58 pubkey string.
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+}
+
+impl AccountFixture {
+    pub fn new(address: &Pubkey, account: &Account) -> Self {
+        Self {
+            pubkey: address.to_string(),
+            account: FixtureAccount {
+                lamports: account.lamports,
+                data: (base64::encode(&account.data), "base64".to_string()),
+                owner: account.owner.to_string(),
+                executable: account.executable,
+                rent_epoch: account.rent_epoch,
+            },
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self> {
+        let bytes = fs::read(filename)
+            .map_err(|e| anyhow!("Unable to read account fixture {}: {}", filename, e))?;
+        let fixture: AccountFixture = serde_json::from_slice(&bytes)
+            .map_err(|e| anyhow!("Invalid account fixture {}: {}", filename, e))?;
+        parse_address(&fixture.pubkey)?;
+        parse_address(&fixture.account.owner)?;
+        if fixture.account.data.1 != "base64" {
+            return Err(anyhow!(
+                "Unsupported data encoding in account fixture {}: {}",
+                filename,
+                fixture.account.data.1
+            ));
+        }
+        base64::decode(&fixture.account.data.0)
+            .map_err(|e| anyhow!("Invalid data in account fixture {}: {}", filename, e))?;
+        Ok(fixture)
+    }
+}
+
+fn parse_address(address: &str) -> Result<Pubkey> {
+    address
+        .parse()
+        .map_err(|_| anyhow!("Invalid address: {}", address))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -251,13 +601,38 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn anchor_keypair_path(&self) -> PathBuf {
+    pub fn keypair_path(&self) -> PathBuf {
         std::env::current_dir()
             .expect("Must have current dir")
-            .join(format!(
-                "target/deploy/anchor-{}-keypair.json",
-                self.lib_name
-            ))
+            .join(format!("target/deploy/{}-keypair.json", self.lib_name))
+    }
+
+    // Returns the program's keypair. It's never generated here, so that
+    // reading the config doesn't write to disk. See `generate_keypair`.
+    pub fn keypair(&self) -> Result<Keypair> {
+        let path = self.keypair_path();
+        if !path.exists() {
+            return Err(anyhow!(
+                "Keypair {} not found, run `anchor build` or `anchor keys sync` to generate it",
+                path.display()
+            ));
+        }
+        solana_sdk::signature::read_keypair_file(&path)
+            .map_err(|_| anyhow!("Unable to read keypair file {}", path.display()))
+    }
+
+    // Generates and persists a new keypair for the program, if it doesn't
+    // have one yet.
+    pub fn generate_keypair(&self) -> Result<()> {
+        let path = self.keypair_path();
+        if path.exists() {
+            return Ok(());
+        }
+        let keypair = Keypair::generate(&mut OsRng);
+        fs::create_dir_all(path.parent().expect("Must have parent dir"))?;
+        solana_sdk::signature::write_keypair_file(&keypair, &path)
+            .map_err(|_| anyhow!("Unable to write keypair file {}", path.display()))?;
+        Ok(())
     }
 
     pub fn binary_path(&self) -> PathBuf {
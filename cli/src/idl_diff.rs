@@ -0,0 +1,676 @@
+//! Structural diff of two IDLs, classifying each change as breaking or
+//! compatible for existing clients.
+//!
+//! Since Borsh encodings are positional, any change to the layout of
+//! instruction args, accounts, types or events is breaking, whereas adding
+//! new instructions, types, errors, constants or instruction return values,
+//! or appending enum variants, is compatible.
+
+use anchor_syn::idl::{
+    EnumFields, EnumVariant, Idl, IdlAccount, IdlAccountItem, IdlConst, IdlErrorCode, IdlEvent,
+    IdlEventField, IdlField, IdlIx, IdlType, IdlTypeDef, IdlTypeDefTy,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    // Path to the changed node, e.g., `instructions.initialize.args.amount`.
+    pub path: String,
+    pub kind: ChangeKind,
+    // True if existing clients may break.
+    pub breaking: bool,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+// Returns the changes from `old` to `new`. Metadata is ignored.
+pub fn diff(old: &Idl, new: &Idl) -> Vec<Change> {
+    let mut d = Diff::default();
+    if old.name != new.name {
+        d.changed(
+            "name",
+            true,
+            format!("changed from {} to {}", old.name, new.name),
+        );
+    }
+    d.ixs("instructions", &old.instructions, &new.instructions);
+    match (&old.state, &new.state) {
+        (None, None) => {}
+        (None, Some(_)) => d.added("state", false),
+        (Some(_), None) => d.removed("state"),
+        (Some(old), Some(new)) => {
+            d.type_def("state.struct", &old.strct, &new.strct);
+            d.ixs("state.methods", &old.methods, &new.methods);
+        }
+    }
+    d.type_defs("accounts", &old.accounts, &new.accounts);
+    d.type_defs("types", &old.types, &new.types);
+    d.events(
+        "events",
+        old.events.as_deref().unwrap_or(&[]),
+        new.events.as_deref().unwrap_or(&[]),
+    );
+    d.errors(
+        "errors",
+        old.errors.as_deref().unwrap_or(&[]),
+        new.errors.as_deref().unwrap_or(&[]),
+    );
+    d.constants("constants", &old.constants, &new.constants);
+    d.changes
+}
+
+#[derive(Default)]
+struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    fn push(&mut self, path: &str, kind: ChangeKind, breaking: bool, description: String) {
+        self.changes.push(Change {
+            path: path.to_string(),
+            kind,
+            breaking,
+            description,
+        });
+    }
+
+    fn added(&mut self, path: &str, breaking: bool) {
+        self.push(path, ChangeKind::Added, breaking, "added".to_string());
+    }
+
+    // Removals are always breaking.
+    fn removed(&mut self, path: &str) {
+        self.push(path, ChangeKind::Removed, true, "removed".to_string());
+    }
+
+    fn changed(&mut self, path: &str, breaking: bool, description: String) {
+        self.push(path, ChangeKind::Changed, breaking, description);
+    }
+
+    // Diffs two lists of named nodes, matching them by name. Borsh encodings
+    // are positional, so a change in the order of the common nodes is
+    // reported as well, if `ordered`. Additions are breaking if
+    // `added_breaking`, e.g., new fields, but not new instructions.
+    #[allow(clippy::too_many_arguments)]
+    fn named<T>(
+        &mut self,
+        path: &str,
+        old: &[T],
+        new: &[T],
+        ordered: bool,
+        added_breaking: bool,
+        name: impl Fn(&T) -> String,
+        mut item: impl FnMut(&mut Self, &str, &T, &T),
+    ) {
+        for o in old {
+            let item_path = format!("{}.{}", path, name(o));
+            match new.iter().find(|n| name(n) == name(o)) {
+                None => self.removed(&item_path),
+                Some(n) => item(self, &item_path, o, n),
+            }
+        }
+        for n in new {
+            if !old.iter().any(|o| name(o) == name(n)) {
+                self.added(&format!("{}.{}", path, name(n)), added_breaking);
+            }
+        }
+        if ordered {
+            let common = |a: &[T], b: &[T]| -> Vec<String> {
+                a.iter()
+                    .map(&name)
+                    .filter(|x| b.iter().any(|y| &name(y) == x))
+                    .collect()
+            };
+            let old_order = common(old, new);
+            let new_order = common(new, old);
+            if old_order != new_order {
+                self.changed(
+                    path,
+                    true,
+                    format!(
+                        "order changed from [{}] to [{}]",
+                        old_order.join(", "),
+                        new_order.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    fn ixs(&mut self, path: &str, old: &[IdlIx], new: &[IdlIx]) {
+        self.named(
+            path,
+            old,
+            new,
+            false,
+            false,
+            |ix| ix.name.clone(),
+            |d, path, old, new| {
+                d.accounts(&format!("{}.accounts", path), &old.accounts, &new.accounts);
+                d.fields(&format!("{}.args", path), &old.args, &new.args);
+                d.returns(&format!("{}.returns", path), &old.returns, &new.returns);
+            },
+        );
+    }
+
+    // Returning a value from an instruction that didn't is compatible, since
+    // callers are free to ignore it.
+    fn returns(&mut self, path: &str, old: &Option<IdlType>, new: &Option<IdlType>) {
+        match (old, new) {
+            (None, None) => {}
+            (None, Some(_)) => self.added(path, false),
+            (Some(_), None) => self.removed(path),
+            (Some(old), Some(new)) => self.ty(path, old, new),
+        }
+    }
+
+    fn accounts(&mut self, path: &str, old: &[IdlAccountItem], new: &[IdlAccountItem]) {
+        self.named(
+            path,
+            old,
+            new,
+            true,
+            true,
+            |acc| match acc {
+                IdlAccountItem::IdlAccount(acc) => acc.name.clone(),
+                IdlAccountItem::IdlAccounts(accs) => accs.name.clone(),
+            },
+            |d, path, old, new| match (old, new) {
+                (IdlAccountItem::IdlAccount(old), IdlAccountItem::IdlAccount(new)) => {
+                    if old.is_mut != new.is_mut {
+                        // Only requiring a writable account is breaking.
+                        d.changed(
+                            path,
+                            new.is_mut,
+                            format!("isMut changed from {} to {}", old.is_mut, new.is_mut),
+                        );
+                    }
+                    if old.is_signer != new.is_signer {
+                        // Only requiring a signer is breaking.
+                        d.changed(
+                            path,
+                            new.is_signer,
+                            format!(
+                                "isSigner changed from {} to {}",
+                                old.is_signer, new.is_signer
+                            ),
+                        );
+                    }
+                    d.account_constraints(path, old, new);
+                }
+                (IdlAccountItem::IdlAccounts(old), IdlAccountItem::IdlAccounts(new)) => {
+                    d.accounts(path, &old.accounts, &new.accounts)
+                }
+                (IdlAccountItem::IdlAccount(_), IdlAccountItem::IdlAccounts(_)) => d.changed(
+                    path,
+                    true,
+                    "changed from an account to accounts".to_string(),
+                ),
+                (IdlAccountItem::IdlAccounts(_), IdlAccountItem::IdlAccount(_)) => d.changed(
+                    path,
+                    true,
+                    "changed from accounts to an account".to_string(),
+                ),
+            },
+        );
+    }
+
+    fn fields(&mut self, path: &str, old: &[IdlField], new: &[IdlField]) {
+        self.named(
+            path,
+            old,
+            new,
+            true,
+            true,
+            |f| f.name.clone(),
+            |d, path, old, new| d.ty(path, &old.ty, &new.ty),
+        );
+    }
+
+    fn ty(&mut self, path: &str, old: &IdlType, new: &IdlType) {
+        if old != new {
+            self.changed(
+                path,
+                true,
+                format!("type changed from {} to {}", type_str(old), type_str(new)),
+            );
+        }
+    }
+
+    fn type_defs(&mut self, path: &str, old: &[IdlTypeDef], new: &[IdlTypeDef]) {
+        self.named(
+            path,
+            old,
+            new,
+            false,
+            false,
+            |t| t.name.clone(),
+            |d, path, old, new| d.type_def(path, old, new),
+        );
+    }
+
+    fn type_def(&mut self, path: &str, old: &IdlTypeDef, new: &IdlTypeDef) {
+        if old.generics != new.generics {
+            self.changed(
+                path,
+                true,
+                format!(
+                    "generics changed from <{}> to <{}>",
+                    old.generics.join(", "),
+                    new.generics.join(", ")
+                ),
+            );
+        }
+        if old.serialization != new.serialization || old.repr != new.repr {
+            self.changed(
+                path,
+                true,
+                format!(
+                    "serialization changed from {} to {}",
+                    serialization(old),
+                    serialization(new)
+                ),
+            );
+        } else if let (Some(old), Some(new)) = (&old.layout, &new.layout) {
+            // Fields are compared below, but a change to one field can move
+            // the others of a zero copy type.
+            if old != new {
+                self.changed(
+                    path,
+                    true,
+                    format!("layout changed from {} to {} bytes", old.size, new.size),
+                );
+            }
+        }
+        match (&old.ty, &new.ty) {
+            (IdlTypeDefTy::Struct { fields: old }, IdlTypeDefTy::Struct { fields: new }) => {
+                self.enum_fields(&format!("{}.fields", path), old, new)
+            }
+            (IdlTypeDefTy::Enum { variants: old }, IdlTypeDefTy::Enum { variants: new }) => {
+                self.variants(&format!("{}.variants", path), old, new)
+            }
+            (IdlTypeDefTy::Struct { .. }, IdlTypeDefTy::Enum { .. }) => {
+                self.changed(path, true, "changed from a struct to an enum".to_string())
+            }
+            (IdlTypeDefTy::Enum { .. }, IdlTypeDefTy::Struct { .. }) => {
+                self.changed(path, true, "changed from an enum to a struct".to_string())
+            }
+        }
+    }
+
+    // Added constraints are breaking, since transactions valid before may no
+    // longer be, and removed ones are compatible. Changes to the address of
+    // a program derived account, or to how an account is created, are always
+    // breaking.
+    fn account_constraints(&mut self, path: &str, old: &IdlAccount, new: &IdlAccount) {
+        for relation in new.relations.iter().filter(|r| !old.relations.contains(r)) {
+            self.changed(path, true, format!("relation to {} added", relation));
+        }
+        for relation in old.relations.iter().filter(|r| !new.relations.contains(r)) {
+            self.changed(path, false, format!("relation to {} removed", relation));
+        }
+        for c in new
+            .constraints
+            .iter()
+            .filter(|c| !old.constraints.contains(c))
+        {
+            self.changed(path, true, format!("constraint `{}` added", c));
+        }
+        for c in old
+            .constraints
+            .iter()
+            .filter(|c| !new.constraints.contains(c))
+        {
+            self.changed(path, false, format!("constraint `{}` removed", c));
+        }
+        if old.owner != new.owner {
+            self.changed(
+                path,
+                new.owner.is_some(),
+                format!(
+                    "owner changed from {} to {}",
+                    old.owner.as_deref().unwrap_or("any"),
+                    new.owner.as_deref().unwrap_or("any")
+                ),
+            );
+        }
+        if old.state != new.state {
+            self.changed(
+                path,
+                new.state.is_some(),
+                format!(
+                    "state changed from {} to {}",
+                    old.state.as_deref().unwrap_or("none"),
+                    new.state.as_deref().unwrap_or("none")
+                ),
+            );
+        }
+        if old.is_executable != new.is_executable {
+            self.changed(
+                path,
+                new.is_executable,
+                format!(
+                    "isExecutable changed from {} to {}",
+                    old.is_executable, new.is_executable
+                ),
+            );
+        }
+        if old.pda != new.pda {
+            self.changed(path, true, "pda changed".to_string());
+        }
+        if old.init != new.init {
+            self.changed(path, true, "init changed".to_string());
+        }
+    }
+
+    // Borsh encodes variants by index, so only appending variants is
+    // compatible. Inserting one before an existing variant shifts the index of
+    // all variants after it.
+    fn variants(&mut self, path: &str, old: &[EnumVariant], new: &[EnumVariant]) {
+        let start = self.changes.len();
+        self.named(
+            path,
+            old,
+            new,
+            true,
+            false,
+            |v| v.name.clone(),
+            |d, path, old, new| d.enum_fields(path, &old.fields, &new.fields),
+        );
+        let last_existing = new
+            .iter()
+            .rposition(|n| old.iter().any(|o| o.name == n.name))
+            .unwrap_or(0);
+        let inserted: Vec<String> = new[..last_existing]
+            .iter()
+            .filter(|n| !old.iter().any(|o| o.name == n.name))
+            .map(|n| format!("{}.{}", path, n.name))
+            .collect();
+        for change in &mut self.changes[start..] {
+            if change.kind == ChangeKind::Added && inserted.contains(&change.path) {
+                change.breaking = true;
+                change.description = "inserted before existing variants".to_string();
+            }
+        }
+    }
+
+    // Diffs the fields of a struct or enum variant.
+    fn enum_fields(&mut self, path: &str, old: &Option<EnumFields>, new: &Option<EnumFields>) {
+        match (old, new) {
+            (None, None) => {}
+            (Some(EnumFields::Named(old)), Some(EnumFields::Named(new))) => {
+                self.fields(path, old, new)
+            }
+            (Some(EnumFields::Tuple(old)), Some(EnumFields::Tuple(new))) => {
+                if old != new {
+                    self.changed(
+                        path,
+                        true,
+                        format!(
+                            "fields changed from ({}) to ({})",
+                            types_str(old),
+                            types_str(new)
+                        ),
+                    );
+                }
+            }
+            _ => self.changed(path, true, "fields changed".to_string()),
+        }
+    }
+
+    fn events(&mut self, path: &str, old: &[IdlEvent], new: &[IdlEvent]) {
+        self.named(
+            path,
+            old,
+            new,
+            false,
+            false,
+            |e| e.name.clone(),
+            |d, path, old, new| {
+                d.event_fields(&format!("{}.fields", path), &old.fields, &new.fields)
+            },
+        );
+    }
+
+    fn event_fields(&mut self, path: &str, old: &[IdlEventField], new: &[IdlEventField]) {
+        self.named(
+            path,
+            old,
+            new,
+            true,
+            true,
+            |f| f.name.clone(),
+            |d, path, old, new| {
+                d.ty(path, &old.ty, &new.ty);
+                if old.index != new.index {
+                    d.changed(
+                        path,
+                        false,
+                        format!("index changed from {} to {}", old.index, new.index),
+                    );
+                }
+            },
+        );
+    }
+
+    // Errors are matched by name, so that renumbering is detected.
+    fn errors(&mut self, path: &str, old: &[IdlErrorCode], new: &[IdlErrorCode]) {
+        self.named(
+            path,
+            old,
+            new,
+            false,
+            false,
+            |e| e.name.clone(),
+            |d, path, old, new| {
+                if old.code != new.code {
+                    d.changed(
+                        path,
+                        true,
+                        format!("code changed from {} to {}", old.code, new.code),
+                    );
+                }
+                if old.msg != new.msg {
+                    d.changed(
+                        path,
+                        false,
+                        format!(
+                            "msg changed from {:?} to {:?}",
+                            old.msg.as_deref().unwrap_or(""),
+                            new.msg.as_deref().unwrap_or("")
+                        ),
+                    );
+                }
+            },
+        );
+    }
+
+    // Clients may rely on the values of constants, e.g., to derive addresses
+    // from seeds, so any change to them is breaking.
+    fn constants(&mut self, path: &str, old: &[IdlConst], new: &[IdlConst]) {
+        self.named(
+            path,
+            old,
+            new,
+            false,
+            false,
+            |c| c.name.clone(),
+            |d, path, old, new| {
+                if old.ty != new.ty {
+                    d.changed(
+                        path,
+                        true,
+                        format!(
+                            "type changed from {} to {}",
+                            type_str(&old.ty),
+                            type_str(&new.ty)
+                        ),
+                    );
+                }
+                if old.value != new.value {
+                    d.changed(
+                        path,
+                        true,
+                        format!("value changed from {} to {}", old.value, new.value),
+                    );
+                }
+            },
+        );
+    }
+}
+
+// Formats the type as in the IDL JSON, e.g., `u64` or `{"vec":"u8"}`.
+fn type_str(ty: &IdlType) -> String {
+    match serde_json::to_value(ty).expect("Must serialize") {
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+fn types_str(tys: &[IdlType]) -> String {
+    tys.iter().map(type_str).collect::<Vec<_>>().join(", ")
+}
+
+// E.g., "borsh" or "bytemuck (packed)".
+fn serialization(ty_def: &IdlTypeDef) -> String {
+    let serialization = format!("{:?}", ty_def.serialization).to_lowercase();
+    match ty_def.repr {
+        None => serialization,
+        Some(repr) => format!("{} ({:?})", serialization, repr).to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn idl(instructions: serde_json::Value, variants: &[&str]) -> Idl {
+        let variants: Vec<_> = variants.iter().map(|v| json!({ "name": v })).collect();
+        serde_json::from_value(json!({
+            "version": "0.0.0",
+            "spec": anchor_syn::idl::IDL_SPEC,
+            "name": "test",
+            "instructions": instructions,
+            "types": [{
+                "name": "Side",
+                "type": { "kind": "enum", "variants": variants },
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn variants_diff(old: &[&str], new: &[&str]) -> Vec<(String, ChangeKind, bool)> {
+        diff(&idl(json!([]), old), &idl(json!([]), new))
+            .into_iter()
+            .map(|c| (c.path, c.kind, c.breaking))
+            .collect()
+    }
+
+    #[test]
+    fn appended_variant() {
+        assert_eq!(
+            variants_diff(&["A", "B"], &["A", "B", "X"]),
+            vec![(
+                "types.Side.variants.X".to_string(),
+                ChangeKind::Added,
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn inserted_variant() {
+        assert_eq!(
+            variants_diff(&["A", "B"], &["A", "X", "B"]),
+            vec![("types.Side.variants.X".to_string(), ChangeKind::Added, true)]
+        );
+        assert_eq!(
+            variants_diff(&["A", "B"], &["X", "A", "B", "Y"]),
+            vec![
+                ("types.Side.variants.X".to_string(), ChangeKind::Added, true),
+                (
+                    "types.Side.variants.Y".to_string(),
+                    ChangeKind::Added,
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_and_reordered_variants() {
+        assert_eq!(
+            variants_diff(&["A", "B"], &["A"]),
+            vec![(
+                "types.Side.variants.B".to_string(),
+                ChangeKind::Removed,
+                true
+            )]
+        );
+        assert_eq!(
+            variants_diff(&["A", "B"], &["B", "A"]),
+            vec![("types.Side.variants".to_string(), ChangeKind::Changed, true)]
+        );
+    }
+
+    #[test]
+    fn instructions() {
+        let ix =
+            |args: serde_json::Value| json!({ "name": "initialize", "accounts": [], "args": args });
+        let old = idl(json!([ix(json!([{ "name": "a", "type": "u64" }]))]), &[]);
+
+        // New instructions are compatible.
+        let new = idl(
+            json!([
+                ix(json!([{ "name": "a", "type": "u64" }])),
+                { "name": "close", "accounts": [], "args": [] },
+            ]),
+            &[],
+        );
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "instructions.close");
+        assert!(!changes[0].breaking);
+
+        // New and changed args aren't.
+        let new = idl(
+            json!([ix(json!([
+                { "name": "a", "type": "u32" },
+                { "name": "b", "type": "u8" },
+            ]))]),
+            &[],
+        );
+        let changes: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .map(|c| (c.path, c.kind, c.breaking))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "instructions.initialize.args.a".to_string(),
+                    ChangeKind::Changed,
+                    true
+                ),
+                (
+                    "instructions.initialize.args.b".to_string(),
+                    ChangeKind::Added,
+                    true
+                ),
+            ]
+        );
+    }
+}
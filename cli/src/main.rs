@@ -1,10 +1,15 @@
 //! CLI for workspace management of anchor programs.
 
-use crate::config::{read_all_programs, Config, Program, ProgramWorkspace, WalletPath};
+use crate::config::{
+    read_all_programs, AccountFixture, Config, Program, ProgramWorkspace, TestSuite, Validator,
+    WalletPath,
+};
 use anchor_client::Cluster;
 use anchor_lang::idl::{IdlAccount, IdlInstruction};
 use anchor_lang::{AccountDeserialize, AnchorDeserialize, AnchorSerialize};
-use anchor_syn::idl::Idl;
+use anchor_syn::idl::{
+    EnumFields, Idl, IdlAccountItem, IdlField, IdlFragment, IdlIx, IdlTypeDef, IdlTypeDefTy,
+};
 use anyhow::{anyhow, Context, Result};
 use clap::Clap;
 use flate2::read::ZlibDecoder;
@@ -18,6 +23,7 @@ use solana_program::instruction::{AccountMeta, Instruction};
 use solana_sdk::account_utils::StateMut;
 use solana_sdk::bpf_loader_upgradeable::UpgradeableLoaderState;
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hasher;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signature::Signer;
@@ -31,6 +37,7 @@ use std::process::{Child, Stdio};
 use std::string::ToString;
 
 mod config;
+mod idl_diff;
 mod template;
 
 // Version of the docker image.
@@ -78,7 +85,19 @@ pub enum Command {
     /// containing the program's Cargo.toml.
     Verify {
         /// The deployed program to compare against.
-        program_id: Pubkey,
+        program_id: Option<Pubkey>,
+        /// Program binary, e.g., dumped with `solana program dump`, to check
+        /// against the build manifest in `target/verifiable` offline, instead
+        /// of building and fetching the deployed program.
+        #[clap(long)]
+        dump: Option<String>,
+        /// Program binary file to compare against, instead of a deployed
+        /// program.
+        #[clap(long)]
+        binary: Option<String>,
+        /// Buffer account to compare against, instead of a deployed program.
+        #[clap(long)]
+        buffer: Option<Pubkey>,
     },
     /// Runs integration tests against a localnetwork.
     Test {
@@ -97,6 +116,14 @@ pub enum Command {
         /// Use this flag if you want to use yarn as your package manager.
         #[clap(long)]
         yarn: bool,
+        /// Runs only the given `[test.suites]` entry from Anchor.toml.
+        #[clap(long)]
+        suite: Option<String>,
+        /// Runs the workspace's Rust tests with `cargo test`, using the
+        /// `anchor-test` harness, instead of the JavaScript tests.
+        #[clap(long)]
+        rust: bool,
+        /// Test file to run or, with `--rust`, the `cargo test` name filter.
         file: Option<String>,
     },
     /// Creates a new program.
@@ -106,8 +133,12 @@ pub enum Command {
         #[clap(subcommand)]
         subcmd: IdlCommand,
     },
-    /// Deploys each program in the workspace.
+    /// Deploys each program in the workspace, to the addresses given in
+    /// `[programs.<cluster>]` or else by the program keypairs in
+    /// `target/deploy`. Programs that are already deployed are upgraded in
+    /// place.
     Deploy {
+        /// Deploys only the given program.
         #[clap(short, long)]
         program_name: Option<String>,
     },
@@ -145,6 +176,19 @@ pub enum Command {
     /// Starts a node shell with an Anchor client setup according to the local
     /// config.
     Shell,
+    /// Runs the script defined in the `[scripts]` section of Anchor.toml,
+    /// e.g., `anchor run lint`.
+    Run { script: String },
+    /// Account commands.
+    Account {
+        #[clap(subcommand)]
+        subcmd: AccountCommand,
+    },
+    /// Program keypair commands.
+    Keys {
+        #[clap(subcommand)]
+        subcmd: KeysCommand,
+    },
 }
 
 #[derive(Debug, Clap)]
@@ -154,6 +198,8 @@ pub enum IdlCommand {
         program_id: Pubkey,
         #[clap(short, long)]
         filepath: String,
+        #[clap(flatten)]
+        write_opts: IdlWriteOpts,
     },
     /// Writes an IDL into a buffer account. This can be used with SetBuffer
     /// to perform an upgrade.
@@ -161,6 +207,11 @@ pub enum IdlCommand {
         program_id: Pubkey,
         #[clap(short, long)]
         filepath: String,
+        /// Existing buffer to resume writing into, instead of creating one.
+        #[clap(long)]
+        buffer: Option<Pubkey>,
+        #[clap(flatten)]
+        write_opts: IdlWriteOpts,
     },
     /// Sets a new IDL buffer for the program.
     SetBuffer {
@@ -175,6 +226,18 @@ pub enum IdlCommand {
         program_id: Pubkey,
         #[clap(short, long)]
         filepath: String,
+        /// Existing buffer to resume writing into, instead of creating one.
+        #[clap(long)]
+        buffer: Option<Pubkey>,
+        #[clap(flatten)]
+        write_opts: IdlWriteOpts,
+    },
+    /// Closes an IDL buffer, returning its rent to the configured wallet.
+    /// The configured wallet must be the buffer's authority.
+    Close {
+        buffer: Pubkey,
+        #[clap(short, long)]
+        program_id: Pubkey,
     },
     /// Sets a new authority on the IDL account.
     SetAuthority {
@@ -209,6 +272,17 @@ pub enum IdlCommand {
         #[clap(short, long)]
         out: Option<String>,
     },
+    /// Builds an IDL from compiled type information, by running the
+    /// program's tests with the `idl-build` feature, rather than parsing
+    /// its source.
+    Build {
+        /// Path to the program's crate (current directory if not specified).
+        #[clap(short, long)]
+        program: Option<String>,
+        /// Output file for the idl (stdout if not specified).
+        #[clap(short, long)]
+        out: Option<String>,
+    },
     /// Fetches an IDL for the given address from a cluster.
     /// The address can be a program, IDL account, or IDL buffer.
     Fetch {
@@ -217,6 +291,83 @@ pub enum IdlCommand {
         #[clap(short, long)]
         out: Option<String>,
     },
+    /// Compares two IDLs, classifying each change as breaking or compatible
+    /// for existing clients. Exits with an error if any change is breaking.
+    Diff {
+        /// The old IDL, either a file or an address to fetch it from.
+        old: String,
+        /// The new IDL, either a file or an address to fetch it from.
+        new: String,
+    },
+    /// Validates an IDL file against the JSON Schema of the current IDL
+    /// spec. Exits with an error if it's invalid.
+    Validate {
+        /// Path to the IDL.
+        file: String,
+    },
+}
+
+// Options for commands writing an IDL on chain.
+#[derive(Debug, Clap)]
+pub struct IdlWriteOpts {
+    /// Max number of compressed IDL bytes written per transaction.
+    #[clap(long, default_value = "1000")]
+    chunk_size: usize,
+    /// Prints the number of transactions and the rent needed, without
+    /// sending anything.
+    #[clap(long)]
+    dry_run: bool,
+    /// Strips doc comments from the IDL, to save rent.
+    #[clap(long)]
+    no_docs: bool,
+}
+
+impl IdlWriteOpts {
+    fn validate(&self) -> Result<()> {
+        if self.chunk_size == 0 || self.chunk_size > MAX_IDL_WRITE_SIZE {
+            return Err(anyhow!(
+                "chunk size must be between 1 and {}",
+                MAX_IDL_WRITE_SIZE
+            ));
+        }
+        Ok(())
+    }
+
+    fn read_idl(&self, idl_filepath: &str) -> Result<Idl> {
+        let bytes = std::fs::read(idl_filepath)?;
+        let mut idl = anchor_syn::idl::migrate(serde_json::from_slice(&bytes)?)?;
+        if self.no_docs {
+            strip_docs(&mut idl);
+        }
+        Ok(idl)
+    }
+}
+
+// Max number of IDL bytes that fit into a single write transaction.
+const MAX_IDL_WRITE_SIZE: usize = 1000;
+
+#[derive(Debug, Clap)]
+pub enum AccountCommand {
+    /// Dumps an account from the configured cluster into a JSON fixture,
+    /// which can be loaded into the local validator with
+    /// `[[test.validator.account]]`.
+    Dump {
+        address: Pubkey,
+        /// Output file for the fixture (stdout if not specified).
+        #[clap(short, long)]
+        out: Option<String>,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum KeysCommand {
+    /// Lists the address of each program in the workspace, for the
+    /// configured cluster.
+    List,
+    /// Syncs the program addresses in `[programs.<cluster>]` and the
+    /// programs' `declare_id!` with the program keypairs in `target/deploy`.
+    /// Addresses already in Anchor.toml take precedence.
+    Sync,
 }
 
 #[derive(Debug, Clap)]
@@ -231,7 +382,12 @@ fn main() -> Result<()> {
         Command::Init { name, typescript } => init(&opts.cfg_override, name, typescript),
         Command::New { name } => new(&opts.cfg_override, name),
         Command::Build { idl, verifiable } => build(&opts.cfg_override, idl, verifiable),
-        Command::Verify { program_id } => verify(&opts.cfg_override, program_id),
+        Command::Verify {
+            program_id,
+            dump,
+            binary,
+            buffer,
+        } => verify(&opts.cfg_override, program_id, dump, binary, buffer),
         Command::Deploy { program_name } => deploy(&opts.cfg_override, program_name),
         Command::Upgrade {
             program_id,
@@ -248,19 +404,28 @@ fn main() -> Result<()> {
             skip_local_validator,
             skip_build,
             yarn,
+            suite,
+            rust,
             file,
-        } => test(
-            &opts.cfg_override,
-            skip_deploy,
-            skip_local_validator,
-            skip_build,
-            yarn,
-            file,
-        ),
+        } => match rust {
+            true => test_rust(&opts.cfg_override, skip_build, file),
+            false => test(
+                &opts.cfg_override,
+                skip_deploy,
+                skip_local_validator,
+                skip_build,
+                yarn,
+                suite,
+                file,
+            ),
+        },
         #[cfg(feature = "dev")]
         Command::Airdrop => airdrop(cfg_override),
         Command::Cluster { subcmd } => cluster(subcmd),
         Command::Shell => shell(&opts.cfg_override),
+        Command::Run { script } => run(&opts.cfg_override, script),
+        Command::Account { subcmd } => account(&opts.cfg_override, subcmd),
+        Command::Keys { subcmd } => keys(&opts.cfg_override, subcmd),
     }
 }
 
@@ -292,6 +457,7 @@ fn init(cfg_override: &ConfigOverride, name: String, typescript: bool) -> Result
     fs::create_dir("programs")?;
 
     new_program(&name)?;
+    generate_program_keypairs()?;
 
     // Build the test suite.
     fs::create_dir("tests")?;
@@ -331,6 +497,7 @@ fn new(cfg_override: &ConfigOverride, name: String) -> Result<()> {
             Some(parent) => {
                 std::env::set_current_dir(&parent)?;
                 new_program(&name)?;
+                generate_program_keypairs()?;
                 println!("Created new program.");
             }
         };
@@ -351,6 +518,16 @@ fn new_program(name: &str) -> Result<()> {
     Ok(())
 }
 
+// Generates the keypairs of the workspace programs that don't have one yet.
+// This is the only place they're generated, so that commands only reading the
+// program ids don't write to disk.
+fn generate_program_keypairs() -> Result<()> {
+    for program in read_all_programs()? {
+        program.generate_keypair()?;
+    }
+    Ok(())
+}
+
 fn build(cfg_override: &ConfigOverride, idl: Option<String>, verifiable: bool) -> Result<()> {
     let (cfg, path, cargo) = Config::discover(cfg_override)?.expect("Not in workspace.");
     let idl_out = match idl {
@@ -366,16 +543,17 @@ fn build(cfg_override: &ConfigOverride, idl: Option<String>, verifiable: bool) -
     };
     match cargo {
         None => build_all(&cfg, path, idl_out, verifiable)?,
-        Some(ct) => build_cwd(path.as_path(), ct, idl_out, verifiable)?,
+        Some(ct) => build_cwd(&cfg, path.as_path(), ct, idl_out, verifiable)?,
     };
 
     set_workspace_dir_or_exit();
+    generate_program_keypairs()?;
 
     Ok(())
 }
 
 fn build_all(
-    _cfg: &Config,
+    cfg: &Config,
     cfg_path: PathBuf,
     idl_out: Option<PathBuf>,
     verifiable: bool,
@@ -388,6 +566,7 @@ fn build_all(
             for f in files {
                 let p = f?.path();
                 build_cwd(
+                    cfg,
                     cfg_path.as_path(),
                     p.join("Cargo.toml"),
                     idl_out.clone(),
@@ -403,6 +582,7 @@ fn build_all(
 
 // Runs the build command outside of a workspace.
 fn build_cwd(
+    cfg: &Config,
     cfg_path: &Path,
     cargo_toml: PathBuf,
     idl_out: Option<PathBuf>,
@@ -414,88 +594,214 @@ fn build_cwd(
     };
     match verifiable {
         false => _build_cwd(idl_out),
-        true => build_cwd_verifiable(cfg_path.parent().unwrap()),
+        true => build_cwd_verifiable(cfg, cfg_path.parent().unwrap()),
     }
 }
 
 // Builds an anchor program in a docker image and copies the build artifacts
-// into the `target/` directory.
-fn build_cwd_verifiable(workspace_dir: &Path) -> Result<()> {
+// into the `target/` directory, along with a manifest describing the build.
+fn build_cwd_verifiable(cfg: &Config, workspace_dir: &Path) -> Result<()> {
     // Docker vars.
     let container_name = "anchor-program";
-    let image_name = format!("projectserum/build:v{}", DOCKER_BUILDER_VERSION);
-    let volume_mount = format!(
-        "{}:/workdir",
-        workspace_dir.canonicalize()?.display().to_string()
-    );
+    let image_name = cfg.docker_image();
+    let workspace_dir = workspace_dir.canonicalize()?;
+    let volume_mount = format!("{}:/workdir", workspace_dir.display());
 
     // Create output dirs.
     fs::create_dir_all(workspace_dir.join("target/deploy"))?;
     fs::create_dir_all(workspace_dir.join("target/idl"))?;
+    fs::create_dir_all(workspace_dir.join("target/verifiable"))?;
 
-    // Build the program in docker.
-    let exit = std::process::Command::new("docker")
-        .args(&[
-            "run",
-            "--name",
-            &container_name,
-            "-v",
-            &volume_mount,
+    let idl = extract_idl("src/lib.rs")?;
+
+    // Hash the sources before the build writes anything.
+    let source_hash = source_hash(&workspace_dir)?;
+
+    // Build the program in docker, always removing the container after.
+    let build = docker_build(
+        container_name,
+        &image_name,
+        &volume_mount,
+        &workspace_dir,
+        &idl.name,
+    );
+    let rm = docker(&["rm", container_name]);
+    build.and(rm)?;
+
+    // Record the build.
+    let binary = fs::read(
+        workspace_dir
+            .join("target/deploy")
+            .join(format!("{}.so", idl.name)),
+    )?;
+    let manifest = BuildManifest {
+        program: idl.name.clone(),
+        docker_image: image_name.clone(),
+        docker_image_digest: docker_output(&[
+            "image",
+            "inspect",
+            "--format",
+            "{{.Id}}",
             &image_name,
-            "anchor",
-            "build",
-        ])
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
-    if !exit.status.success() {
-        println!("Error building program");
-        return Ok(());
-    }
+        ])?,
+        anchor_version: docker_output(&["run", "--rm", &image_name, "anchor", "--version"])?,
+        rustc_version: docker_output(&["run", "--rm", &image_name, "rustc", "--version"])?,
+        solana_version: docker_output(&["run", "--rm", &image_name, "solana", "--version"])?,
+        source_hash,
+        binary_sha256: sha256_hex(&binary),
+        binary_len: binary.len(),
+    };
+    let manifest_path = BuildManifest::path(&workspace_dir, &idl.name);
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    println!("Build manifest: {}", manifest_path.display());
 
-    let idl = extract_idl("src/lib.rs")?;
+    Ok(())
+}
+
+// Builds the program in a new container and copies out its binary and IDL.
+fn docker_build(
+    container_name: &str,
+    image_name: &str,
+    volume_mount: &str,
+    workspace_dir: &Path,
+    program_name: &str,
+) -> Result<()> {
+    docker(&[
+        "run",
+        "--name",
+        container_name,
+        "-v",
+        volume_mount,
+        image_name,
+        "anchor",
+        "build",
+    ])?;
 
     // Copy the binary out of the docker image.
-    let out_file = format!("../../target/deploy/{}.so", idl.name);
-    let bin_artifact = format!("{}:/workdir/target/deploy/{}.so", container_name, idl.name);
-    let exit = std::process::Command::new("docker")
-        .args(&["cp", &bin_artifact, &out_file])
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
-    if !exit.status.success() {
-        return Ok(());
-    }
+    let out_file = workspace_dir
+        .join("target/deploy")
+        .join(format!("{}.so", program_name));
+    let bin_artifact = format!(
+        "{}:/workdir/target/deploy/{}.so",
+        container_name, program_name
+    );
+    docker(&["cp", &bin_artifact, &out_file.display().to_string()])?;
 
     // Copy the idl out of the docker image.
-    let out_file = format!("../../target/idl/{}.json", idl.name);
-    let idl_artifact = format!("{}:/workdir/target/idl/{}.json", container_name, idl.name);
+    let out_file = workspace_dir
+        .join("target/idl")
+        .join(format!("{}.json", program_name));
+    let idl_artifact = format!(
+        "{}:/workdir/target/idl/{}.json",
+        container_name, program_name
+    );
+    docker(&["cp", &idl_artifact, &out_file.display().to_string()])
+}
+
+// Runs a docker command, failing on a non-zero exit.
+fn docker(args: &[&str]) -> Result<()> {
     let exit = std::process::Command::new("docker")
-        .args(&["cp", &idl_artifact, &out_file])
+        .args(args)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .output()
         .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
     if !exit.status.success() {
-        return Ok(());
+        return Err(anyhow!(
+            "`docker {}` failed: {}",
+            args.join(" "),
+            exit.status
+        ));
     }
+    Ok(())
+}
 
-    // Remove the docker image.
+// Runs a docker command, returning its trimmed stdout.
+fn docker_output(args: &[&str]) -> Result<String> {
     let exit = std::process::Command::new("docker")
-        .args(&["rm", &container_name])
-        .stdout(Stdio::inherit())
+        .args(args)
         .stderr(Stdio::inherit())
         .output()
         .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
     if !exit.status.success() {
-        std::process::exit(exit.status.code().unwrap_or(1));
+        return Err(anyhow!(
+            "`docker {}` failed: {}",
+            args.join(" "),
+            exit.status
+        ));
+    }
+    Ok(String::from_utf8(exit.stdout)?.trim().to_string())
+}
+
+// Manifest written to `target/verifiable/<program>.json` by verifiable
+// builds.
+#[derive(Debug, Serialize, Deserialize)]
+struct BuildManifest {
+    program: String,
+    docker_image: String,
+    docker_image_digest: String,
+    anchor_version: String,
+    rustc_version: String,
+    solana_version: String,
+    // SHA-256 of the workspace sources, see `source_hash`.
+    source_hash: String,
+    // SHA-256 of the program binary.
+    binary_sha256: String,
+    binary_len: usize,
+}
+
+impl BuildManifest {
+    fn path(workspace_dir: &Path, program_name: &str) -> PathBuf {
+        workspace_dir
+            .join("target/verifiable")
+            .join(program_name)
+            .with_extension("json")
+    }
+}
+
+// Directories excluded from the source hash.
+const SOURCE_HASH_EXCLUDE: &[&str] = &[".anchor", ".git", "node_modules", "target"];
+
+// Returns the SHA-256 over the relative path and contents of every file in
+// the workspace, in path order.
+fn source_hash(workspace_dir: &Path) -> Result<String> {
+    let mut files = Vec::new();
+    source_files(workspace_dir, workspace_dir, &mut files)?;
+    files.sort();
+    let mut hasher = Hasher::default();
+    for file in files {
+        let contents = fs::read(workspace_dir.join(&file))?;
+        hasher.hash(&(file.len() as u64).to_le_bytes());
+        hasher.hash(file.as_bytes());
+        hasher.hash(&(contents.len() as u64).to_le_bytes());
+        hasher.hash(&contents);
     }
+    Ok(hex_encode(hasher.result().as_ref()))
+}
 
+fn source_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if !SOURCE_HASH_EXCLUDE.contains(&name) {
+                source_files(root, &path, files)?;
+            }
+        } else {
+            files.push(path.strip_prefix(root)?.display().to_string());
+        }
+    }
     Ok(())
 }
 
+fn sha256_hex(data: &[u8]) -> String {
+    hex_encode(solana_sdk::hash::hash(data).as_ref())
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 fn _build_cwd(idl_out: Option<PathBuf>) -> Result<()> {
     let exit = std::process::Command::new("cargo")
         .arg("build-bpf")
@@ -518,10 +824,20 @@ fn _build_cwd(idl_out: Option<PathBuf>) -> Result<()> {
     write_idl(&idl, OutFile::File(out))
 }
 
-fn verify(cfg_override: &ConfigOverride, program_id: Pubkey) -> Result<()> {
+fn verify(
+    cfg_override: &ConfigOverride,
+    program_id: Option<Pubkey>,
+    dump: Option<String>,
+    binary: Option<String>,
+    buffer: Option<Pubkey>,
+) -> Result<()> {
+    if let Some(dump) = dump {
+        return verify_dump(cfg_override, dump);
+    }
     let (cfg, _path, cargo) = Config::discover(cfg_override)?.expect("Not in workspace.");
     let cargo = cargo.ok_or(anyhow!("Must be inside program subdirectory."))?;
     let program_dir = cargo.parent().unwrap();
+    let client = RpcClient::new(cfg.provider.cluster.url().to_string());
 
     // Build the program we want to verify.
     let cur_dir = std::env::current_dir()?;
@@ -529,76 +845,182 @@ fn verify(cfg_override: &ConfigOverride, program_id: Pubkey) -> Result<()> {
     std::env::set_current_dir(&cur_dir)?;
 
     let local_idl = extract_idl("src/lib.rs")?;
+    let local_bin = fs::read(
+        program_dir
+            .join("../../target/deploy/")
+            .join(format!("{}.so", local_idl.name)),
+    )?;
+
+    // Get the binary to verify against.
+    let (target, deployed_bin, is_buffer) = match (program_id, binary, buffer) {
+        (Some(program_id), None, None) => {
+            let (bin, is_buffer) = fetch_bin(&client, &program_id)?;
+            (program_id.to_string(), bin, is_buffer)
+        }
+        (None, Some(binary), None) => {
+            let bin = fs::read(&binary)?;
+            (binary, bin, false)
+        }
+        (None, None, Some(buffer)) => match fetch_bin(&client, &buffer)? {
+            (bin, true) => (buffer.to_string(), bin, true),
+            (_, false) => return Err(anyhow!("{} is not a buffer account", buffer)),
+        },
+        _ => {
+            return Err(anyhow!(
+                "Exactly one of a program id, --binary or --buffer must be given"
+            ))
+        }
+    };
 
-    // Verify binary.
-    let bin_path = program_dir
-        .join("../../target/deploy/")
-        .join(format!("{}.so", local_idl.name));
-    let is_buffer = verify_bin(program_id, &bin_path, cfg.provider.cluster.url())?;
+    let mut verified = verify_bin(&local_bin, &deployed_bin);
 
-    // Verify IDL (only if it's not a buffer account).
-    if !is_buffer {
+    // Verify IDL (only if it's a deployed program).
+    if let (Some(program_id), false) = (program_id, is_buffer) {
         std::env::set_current_dir(program_dir)?;
         let deployed_idl = fetch_idl(cfg_override, program_id)?;
-        if local_idl != deployed_idl {
+        let changes = idl_diff::diff(&deployed_idl, &local_idl);
+        if !changes.is_empty() {
             println!("Error: IDLs don't match");
-            std::process::exit(1);
+            for change in changes {
+                println!("  {}", change);
+            }
+            verified = false;
         }
     }
 
-    println!("{} is verified.", program_id);
+    if !verified {
+        std::process::exit(1);
+    }
+
+    println!("{} is verified.", target);
 
     Ok(())
 }
 
-fn verify_bin(program_id: Pubkey, bin_path: &Path, cluster: &str) -> Result<bool> {
-    let client = RpcClient::new(cluster.to_string());
+// Verifies a program binary against the manifest of the last verifiable build,
+// without connecting to a cluster.
+fn verify_dump(cfg_override: &ConfigOverride, dump: String) -> Result<()> {
+    let (_cfg, cfg_path, cargo) = Config::discover(cfg_override)?.expect("Not in workspace.");
+    cargo.ok_or(anyhow!("Must be inside program subdirectory."))?;
+    let workspace_dir = cfg_path.parent().unwrap();
 
-    // Get the deployed build artifacts.
-    let (deployed_bin, is_buffer) = {
-        let account = client
-            .get_account_with_commitment(&program_id, CommitmentConfig::default())?
-            .value
-            .map_or(Err(anyhow!("Account not found")), Ok)?;
-        match account.state()? {
-            UpgradeableLoaderState::Program {
-                programdata_address,
-            } => (
-                client
-                    .get_account_with_commitment(&programdata_address, CommitmentConfig::default())?
-                    .value
-                    .map_or(Err(anyhow!("Account not found")), Ok)?
-                    .data[UpgradeableLoaderState::programdata_data_offset().unwrap_or(0)..]
-                    .to_vec(),
-                false,
-            ),
-            UpgradeableLoaderState::Buffer { .. } => {
-                let offset = UpgradeableLoaderState::buffer_data_offset().unwrap_or(0);
-                (account.data[offset..].to_vec(), true)
-            }
-            _ => return Err(anyhow!("Invalid program id")),
-        }
-    };
-    let mut local_bin = {
-        let mut f = File::open(bin_path)?;
-        let mut contents = vec![];
-        f.read_to_end(&mut contents)?;
-        contents
-    };
+    let idl = extract_idl("src/lib.rs")?;
+    let manifest_path = BuildManifest::path(workspace_dir, &idl.name);
+    let manifest: BuildManifest =
+        serde_json::from_slice(&fs::read(&manifest_path).map_err(|e| {
+            anyhow!(
+                "Unable to read {}, run `anchor build --verifiable`: {}",
+                manifest_path.display(),
+                e
+            )
+        })?)?;
+
+    println!("Build manifest: {}", manifest_path.display());
+    println!(
+        "Docker image: {} ({})",
+        manifest.docker_image, manifest.docker_image_digest
+    );
+    println!("Anchor: {}", manifest.anchor_version);
+    println!("Rust: {}", manifest.rustc_version);
+    println!("Solana: {}", manifest.solana_version);
+
+    let mut verified = true;
+
+    if source_hash(workspace_dir)? != manifest.source_hash {
+        println!("Error: Sources have changed since the build");
+        verified = false;
+    }
 
-    // The deployed program probably has zero bytes appended. The default is
-    // 2x the binary size in case of an upgrade.
-    if local_bin.len() < deployed_bin.len() {
-        local_bin.append(&mut vec![0; deployed_bin.len() - local_bin.len()]);
+    // Binaries dumped from program data accounts are zero padded.
+    let dumped_bin = fs::read(&dump)?;
+    let is_padding = dumped_bin
+        .get(manifest.binary_len..)
+        .map_or(false, |padding| padding.iter().all(|b| *b == 0));
+    if !is_padding || sha256_hex(&dumped_bin[..manifest.binary_len]) != manifest.binary_sha256 {
+        println!("Error: Binary doesn't match the build manifest");
+        verified = false;
     }
 
-    // Finally, check the bytes.
-    if local_bin != deployed_bin {
-        println!("Error: Binaries don't match");
+    if !verified {
         std::process::exit(1);
     }
 
-    Ok(is_buffer)
+    println!("{} is verified.", dump);
+
+    Ok(())
+}
+
+// Fetches the binary in the given program or buffer account, returning true
+// if it's a buffer.
+fn fetch_bin(client: &RpcClient, address: &Pubkey) -> Result<(Vec<u8>, bool)> {
+    let account = client
+        .get_account_with_commitment(address, CommitmentConfig::default())?
+        .value
+        .map_or(Err(anyhow!("Account not found")), Ok)?;
+    match account.state()? {
+        UpgradeableLoaderState::Program {
+            programdata_address,
+        } => Ok((
+            client
+                .get_account_with_commitment(&programdata_address, CommitmentConfig::default())?
+                .value
+                .map_or(Err(anyhow!("Account not found")), Ok)?
+                .data[UpgradeableLoaderState::programdata_data_offset().unwrap_or(0)..]
+                .to_vec(),
+            false,
+        )),
+        UpgradeableLoaderState::Buffer { .. } => {
+            let offset = UpgradeableLoaderState::buffer_data_offset().unwrap_or(0);
+            Ok((account.data[offset..].to_vec(), true))
+        }
+        _ => Err(anyhow!("Invalid program id")),
+    }
+}
+
+// Compares the local binary to the deployed one, printing a report explaining
+// any mismatch.
+fn verify_bin(local_bin: &[u8], deployed_bin: &[u8]) -> bool {
+    // The deployed program probably has zero bytes appended, since program
+    // data accounts are allocated with room for upgrades. The default is 2x
+    // the binary size.
+    let padding = deployed_bin
+        .get(local_bin.len()..)
+        .filter(|padding| padding.iter().all(|b| *b == 0))
+        .map_or(0, |padding| padding.len());
+    if local_bin == &deployed_bin[..deployed_bin.len() - padding] {
+        return true;
+    }
+
+    let trailing_zeros = deployed_bin.iter().rev().take_while(|b| **b == 0).count();
+    println!("Error: Binaries don't match");
+    println!("  Local length:    {}", local_bin.len());
+    println!(
+        "  Deployed length: {} ({} bytes of trailing zero padding)",
+        deployed_bin.len(),
+        trailing_zeros
+    );
+    match local_bin
+        .iter()
+        .zip(deployed_bin.iter())
+        .position(|(l, d)| l != d)
+    {
+        Some(offset) => println!(
+            "  First difference at offset {} (0x{:x}): local 0x{:02x}, deployed 0x{:02x}",
+            offset, offset, local_bin[offset], deployed_bin[offset]
+        ),
+        // One binary is a prefix of the other.
+        None => match local_bin.len() < deployed_bin.len() {
+            true => println!(
+                "  The local binary is truncated at offset {}",
+                local_bin.len()
+            ),
+            false => println!(
+                "  The deployed binary is truncated at offset {}",
+                deployed_bin.len()
+            ),
+        },
+    }
+    false
 }
 
 // Fetches an IDL for the given program_id.
@@ -614,11 +1036,23 @@ fn fetch_idl(cfg_override: &ConfigOverride, idl_addr: Pubkey) -> Result<Idl> {
         .map_or(Err(anyhow!("Account not found")), Ok)?;
 
     if account.executable {
-        let idl_addr = IdlAccount::address(&idl_addr);
-        account = client
+        // While the IDL account is being resized, the IDL is in the resize
+        // account.
+        let program_id = idl_addr;
+        let idl_addr = IdlAccount::address(&program_id);
+        account = match client
             .get_account_with_commitment(&idl_addr, CommitmentConfig::processed())?
             .value
-            .map_or(Err(anyhow!("Account not found")), Ok)?;
+        {
+            Some(account) => account,
+            None => client
+                .get_account_with_commitment(
+                    &IdlAccount::resize_address(&program_id),
+                    CommitmentConfig::processed(),
+                )?
+                .value
+                .map_or(Err(anyhow!("Account not found")), Ok)?,
+        };
     }
 
     // Cut off account discriminator.
@@ -628,7 +1062,7 @@ fn fetch_idl(cfg_override: &ConfigOverride, idl_addr: Pubkey) -> Result<Idl> {
     let mut z = ZlibDecoder::new(&idl_account.data[..]);
     let mut s = Vec::new();
     z.read_to_end(&mut s)?;
-    serde_json::from_slice(&s[..]).map_err(Into::into)
+    anchor_syn::idl::migrate(serde_json::from_slice(&s[..])?)
 }
 
 fn extract_idl(file: &str) -> Result<Idl> {
@@ -641,18 +1075,24 @@ fn idl(cfg_override: &ConfigOverride, subcmd: IdlCommand) -> Result<()> {
         IdlCommand::Init {
             program_id,
             filepath,
-        } => idl_init(cfg_override, program_id, filepath),
+            write_opts,
+        } => idl_init(cfg_override, program_id, filepath, write_opts),
         IdlCommand::WriteBuffer {
             program_id,
             filepath,
-        } => idl_write_buffer(cfg_override, program_id, filepath).map(|_| ()),
+            buffer,
+            write_opts,
+        } => idl_write_buffer(cfg_override, program_id, filepath, buffer, write_opts),
         IdlCommand::SetBuffer { program_id, buffer } => {
             idl_set_buffer(cfg_override, program_id, buffer)
         }
         IdlCommand::Upgrade {
             program_id,
             filepath,
-        } => idl_upgrade(cfg_override, program_id, filepath),
+            buffer,
+            write_opts,
+        } => idl_upgrade(cfg_override, program_id, filepath, buffer, write_opts),
+        IdlCommand::Close { buffer, program_id } => idl_close(cfg_override, program_id, buffer),
         IdlCommand::SetAuthority {
             program_id,
             address,
@@ -661,18 +1101,49 @@ fn idl(cfg_override: &ConfigOverride, subcmd: IdlCommand) -> Result<()> {
         IdlCommand::EraseAuthority { program_id } => idl_erase_authority(cfg_override, program_id),
         IdlCommand::Authority { program_id } => idl_authority(cfg_override, program_id),
         IdlCommand::Parse { file, out } => idl_parse(file, out),
+        IdlCommand::Build { program, out } => idl_build(program, out),
         IdlCommand::Fetch { address, out } => idl_fetch(cfg_override, address, out),
+        IdlCommand::Diff { old, new } => idl_diff(cfg_override, old, new),
+        IdlCommand::Validate { file } => idl_validate(file),
     }
 }
 
-fn idl_init(cfg_override: &ConfigOverride, program_id: Pubkey, idl_filepath: String) -> Result<()> {
+fn idl_init(
+    cfg_override: &ConfigOverride,
+    program_id: Pubkey,
+    idl_filepath: String,
+    write_opts: IdlWriteOpts,
+) -> Result<()> {
+    write_opts.validate()?;
     with_workspace(cfg_override, |cfg, _path, _cargo| {
         let keypair = cfg.provider.wallet.to_string();
 
-        let bytes = std::fs::read(idl_filepath)?;
-        let idl: Idl = serde_json::from_reader(&*bytes)?;
+        let idl = write_opts.read_idl(&idl_filepath)?;
+
+        if write_opts.dry_run {
+            let client = RpcClient::new(cfg.provider.cluster.url().to_string());
+            let idl_address = IdlAccount::address(&program_id);
+            let idl_data = idl_write_data(&idl)?;
+            let written = idl_written(&client, &idl_address, &idl_data)?;
+            let space = match client
+                .get_account_with_commitment(&idl_address, CommitmentConfig::processed())?
+                .value
+            {
+                Some(_) => None,
+                None => Some(idl_account_space(idl_data.len() * 2)),
+            };
+            return print_idl_write_plan(
+                &client,
+                &idl_data,
+                written,
+                space,
+                0,
+                write_opts.chunk_size,
+            );
+        }
 
-        let idl_address = create_idl_account(&cfg, &keypair, &program_id, &idl)?;
+        let idl_address =
+            create_idl_account(&cfg, &keypair, &program_id, &idl, write_opts.chunk_size)?;
 
         println!("Idl account created: {:?}", idl_address);
         Ok(())
@@ -683,15 +1154,35 @@ fn idl_write_buffer(
     cfg_override: &ConfigOverride,
     program_id: Pubkey,
     idl_filepath: String,
+    buffer: Option<Pubkey>,
+    write_opts: IdlWriteOpts,
+) -> Result<()> {
+    write_opts.validate()?;
+    match write_opts.dry_run {
+        true => idl_write_buffer_plan(cfg_override, idl_filepath, buffer, &write_opts, 0),
+        false => _idl_write_buffer(cfg_override, program_id, idl_filepath, buffer, &write_opts)
+            .map(|_| ()),
+    }
+}
+
+// Writes the IDL into a new buffer, or resumes writing into the given one.
+fn _idl_write_buffer(
+    cfg_override: &ConfigOverride,
+    program_id: Pubkey,
+    idl_filepath: String,
+    buffer: Option<Pubkey>,
+    write_opts: &IdlWriteOpts,
 ) -> Result<Pubkey> {
     with_workspace(cfg_override, |cfg, _path, _cargo| {
         let keypair = cfg.provider.wallet.to_string();
 
-        let bytes = std::fs::read(idl_filepath)?;
-        let idl: Idl = serde_json::from_reader(&*bytes)?;
+        let idl = write_opts.read_idl(&idl_filepath)?;
 
-        let idl_buffer = create_idl_buffer(&cfg, &keypair, &program_id, &idl)?;
-        idl_write(&cfg, &program_id, &idl, idl_buffer)?;
+        let idl_buffer = match buffer {
+            Some(buffer) => buffer,
+            None => create_idl_buffer(&cfg, &keypair, &program_id, &idl)?,
+        };
+        idl_write(&cfg, &program_id, &idl, idl_buffer, write_opts.chunk_size)?;
 
         println!("Idl buffer created: {:?}", idl_buffer);
 
@@ -699,6 +1190,35 @@ fn idl_write_buffer(
     })
 }
 
+// Prints the plan for writing the IDL into a buffer, followed by `other_txs`
+// transactions.
+fn idl_write_buffer_plan(
+    cfg_override: &ConfigOverride,
+    idl_filepath: String,
+    buffer: Option<Pubkey>,
+    write_opts: &IdlWriteOpts,
+    other_txs: usize,
+) -> Result<()> {
+    with_workspace(cfg_override, |cfg, _path, _cargo| {
+        let idl = write_opts.read_idl(&idl_filepath)?;
+
+        let client = RpcClient::new(cfg.provider.cluster.url().to_string());
+        let idl_data = idl_write_data(&idl)?;
+        let (written, space) = match buffer {
+            Some(buffer) => (idl_written(&client, &buffer, &idl_data)?, None),
+            None => (0, Some(idl_account_space(idl_data.len()))),
+        };
+        print_idl_write_plan(
+            &client,
+            &idl_data,
+            written,
+            space,
+            other_txs,
+            write_opts.chunk_size,
+        )
+    })
+}
+
 fn idl_set_buffer(cfg_override: &ConfigOverride, program_id: Pubkey, buffer: Pubkey) -> Result<()> {
     with_workspace(cfg_override, |cfg, _path, _cargo| {
         let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
@@ -748,9 +1268,158 @@ fn idl_upgrade(
     cfg_override: &ConfigOverride,
     program_id: Pubkey,
     idl_filepath: String,
+    buffer: Option<Pubkey>,
+    write_opts: IdlWriteOpts,
+) -> Result<()> {
+    write_opts.validate()?;
+    idl_resize(cfg_override, program_id, &idl_filepath, &write_opts)?;
+    if write_opts.dry_run {
+        // Plus setting and closing the buffer.
+        return idl_write_buffer_plan(cfg_override, idl_filepath, buffer, &write_opts, 2);
+    }
+    let buffer = _idl_write_buffer(cfg_override, program_id, idl_filepath, buffer, &write_opts)?;
+    idl_set_buffer(cfg_override, program_id, buffer)?;
+    idl_close(cfg_override, program_id, buffer)
+}
+
+// Resizes the program's canonical IDL account if the IDL doesn't fit into
+// it, before any rent is spent on a buffer, finishing any interrupted resize
+// first. As on creation, the account is resized to double the IDL's size.
+fn idl_resize(
+    cfg_override: &ConfigOverride,
+    program_id: Pubkey,
+    idl_filepath: &str,
+    write_opts: &IdlWriteOpts,
 ) -> Result<()> {
-    let buffer = idl_write_buffer(cfg_override, program_id, idl_filepath)?;
-    idl_set_buffer(cfg_override, program_id, buffer)
+    with_workspace(cfg_override, |cfg, _path, _cargo| {
+        let idl = write_opts.read_idl(idl_filepath)?;
+        let idl_len = idl_write_data(&idl)?.len();
+
+        let client = RpcClient::new(cfg.provider.cluster.url().to_string());
+        let idl_address = IdlAccount::address(&program_id);
+        let resize_address = IdlAccount::resize_address(&program_id);
+        let get_account = |address: &Pubkey| -> Result<Option<solana_sdk::account::Account>> {
+            Ok(client
+                .get_account_with_commitment(address, CommitmentConfig::processed())?
+                .value)
+        };
+
+        // Every resize instruction moves the IDL between the IDL account and
+        // the resize account, so loop until it's back in an account that's
+        // large enough.
+        loop {
+            let (data_len, txs, lamports) =
+                match (get_account(&idl_address)?, get_account(&resize_address)?) {
+                    (Some(account), _) => {
+                        let capacity = account.data.len().saturating_sub(idl_account_space(0));
+                        if idl_len <= capacity {
+                            return Ok(());
+                        }
+                        println!(
+                            "Resizing the IDL account from {} to {} bytes",
+                            capacity,
+                            idl_len * 2
+                        );
+                        (idl_len * 2, 2, account.lamports)
+                    }
+                    (None, Some(account)) => {
+                        println!("Finishing the interrupted resize of the IDL account");
+                        let data_len = account.data.len().saturating_sub(idl_account_space(0));
+                        (data_len, 1, account.lamports)
+                    }
+                    (None, None) => {
+                        return Err(anyhow!("Idl account not found: {}", idl_address));
+                    }
+                };
+
+            if write_opts.dry_run {
+                let rent =
+                    client.get_minimum_balance_for_rent_exemption(idl_account_space(data_len))?;
+                println!("Resize transactions: {}", txs);
+                println!(
+                    "Resize rent: {} SOL ({} lamports), less {} lamports refunded",
+                    solana_sdk::native_token::lamports_to_sol(rent),
+                    rent,
+                    lamports
+                );
+                return Ok(());
+            }
+
+            let keypair =
+                solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+                    .map_err(|_| anyhow!("Unable to read keypair file"))?;
+            let ix = Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(idl_address, false),
+                    AccountMeta::new(resize_address, false),
+                    AccountMeta::new(keypair.pubkey(), true),
+                    AccountMeta::new_readonly(
+                        Pubkey::find_program_address(&[], &program_id).0,
+                        false,
+                    ),
+                    AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                    AccountMeta::new_readonly(program_id, false),
+                    AccountMeta::new_readonly(sysvar::rent::ID, false),
+                ],
+                data: serialize_idl_ix(IdlInstruction::Resize {
+                    data_len: data_len as u64,
+                })?,
+            };
+            let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+            let tx = Transaction::new_signed_with_payer(
+                &[ix],
+                Some(&keypair.pubkey()),
+                &[&keypair],
+                recent_hash,
+            );
+            client.send_and_confirm_transaction_with_spinner_and_config(
+                &tx,
+                CommitmentConfig::confirmed(),
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )?;
+        }
+    })
+}
+
+fn idl_close(cfg_override: &ConfigOverride, program_id: Pubkey, buffer: Pubkey) -> Result<()> {
+    with_workspace(cfg_override, |cfg, _path, _cargo| {
+        let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+            .map_err(|_| anyhow!("Unable to read keypair file"))?;
+        let client = RpcClient::new(cfg.provider.cluster.url().to_string());
+
+        let ix = Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(buffer, false),
+                AccountMeta::new_readonly(keypair.pubkey(), true),
+                AccountMeta::new(keypair.pubkey(), false),
+            ],
+            data: serialize_idl_ix(IdlInstruction::Close)?,
+        };
+        let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&keypair.pubkey()),
+            &[&keypair],
+            recent_hash,
+        );
+        client.send_and_confirm_transaction_with_spinner_and_config(
+            &tx,
+            CommitmentConfig::confirmed(),
+            RpcSendTransactionConfig {
+                skip_preflight: true,
+                ..RpcSendTransactionConfig::default()
+            },
+        )?;
+
+        println!("Idl buffer closed: {:?}", buffer);
+
+        Ok(())
+    })
 }
 
 fn idl_authority(cfg_override: &ConfigOverride, program_id: Pubkey) -> Result<()> {
@@ -854,35 +1523,32 @@ fn idl_erase_authority(cfg_override: &ConfigOverride, program_id: Pubkey) -> Res
 // Write the idl to the account buffer, chopping up the IDL into pieces
 // and sending multiple transactions in the event the IDL doesn't fit into
 // a single transaction.
-fn idl_write(cfg: &Config, program_id: &Pubkey, idl: &Idl, idl_address: Pubkey) -> Result<()> {
-    // Remove the metadata before deploy.
-    let mut idl = idl.clone();
-    idl.metadata = None;
-
+fn idl_write(
+    cfg: &Config,
+    program_id: &Pubkey,
+    idl: &Idl,
+    idl_address: Pubkey,
+    chunk_size: usize,
+) -> Result<()> {
     // Misc.
     let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
         .map_err(|_| anyhow!("Unable to read keypair file"))?;
     let client = RpcClient::new(cfg.provider.cluster.url().to_string());
 
-    // Serialize and compress the idl.
-    let idl_data = {
-        let json_bytes = serde_json::to_vec(&idl)?;
-        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-        e.write_all(&json_bytes)?;
-        e.finish()?
-    };
+    let idl_data = idl_write_data(idl)?;
+
+    // Resume after anything written by a previous, interrupted write.
+    let mut offset = idl_written(&client, &idl_address, &idl_data)?;
+    if offset > 0 {
+        println!("Resuming write at byte {} of {}", offset, idl_data.len());
+    }
 
-    const MAX_WRITE_SIZE: usize = 1000;
-    let mut offset = 0;
     while offset < idl_data.len() {
+        let end = std::cmp::min(offset + chunk_size, idl_data.len());
         // Instruction data.
-        let data = {
-            let start = offset;
-            let end = std::cmp::min(offset + MAX_WRITE_SIZE, idl_data.len());
-            serialize_idl_ix(anchor_lang::idl::IdlInstruction::Write {
-                data: idl_data[start..end].to_vec(),
-            })?
-        };
+        let data = serialize_idl_ix(anchor_lang::idl::IdlInstruction::Write {
+            data: idl_data[offset..end].to_vec(),
+        })?;
         // Instruction accounts.
         let accounts = vec![
             AccountMeta::new(idl_address, false),
@@ -910,11 +1576,130 @@ fn idl_write(cfg: &Config, program_id: &Pubkey, idl: &Idl, idl_address: Pubkey)
                 ..RpcSendTransactionConfig::default()
             },
         )?;
-        offset += MAX_WRITE_SIZE;
+        offset = end;
     }
     Ok(())
 }
 
+// Returns the compressed IDL as written on chain, i.e., without metadata.
+fn idl_write_data(idl: &Idl) -> Result<Vec<u8>> {
+    let mut idl = idl.clone();
+    idl.metadata = None;
+    serialize_idl(&idl)
+}
+
+fn strip_docs(idl: &mut Idl) {
+    fn strip_accounts(accounts: &mut [IdlAccountItem]) {
+        for acc in accounts {
+            match acc {
+                IdlAccountItem::IdlAccount(acc) => acc.docs = None,
+                IdlAccountItem::IdlAccounts(accs) => {
+                    accs.docs = None;
+                    strip_accounts(&mut accs.accounts);
+                }
+            }
+        }
+    }
+    fn strip_fields(fields: &mut [IdlField]) {
+        fields.iter_mut().for_each(|f| f.docs = None);
+    }
+    fn strip_ty_def(ty_def: &mut IdlTypeDef) {
+        ty_def.docs = None;
+        match &mut ty_def.ty {
+            IdlTypeDefTy::Struct { fields } => {
+                if let Some(EnumFields::Named(fields)) = fields {
+                    strip_fields(fields);
+                }
+            }
+            IdlTypeDefTy::Enum { variants } => {
+                for variant in variants {
+                    if let Some(EnumFields::Named(fields)) = &mut variant.fields {
+                        strip_fields(fields);
+                    }
+                }
+            }
+        }
+    }
+    fn strip_ix(ix: &mut IdlIx) {
+        ix.docs = None;
+        strip_accounts(&mut ix.accounts);
+        strip_fields(&mut ix.args);
+    }
+
+    idl.docs = None;
+    idl.instructions.iter_mut().for_each(strip_ix);
+    if let Some(state) = &mut idl.state {
+        strip_ty_def(&mut state.strct);
+        state.methods.iter_mut().for_each(strip_ix);
+    }
+    idl.accounts.iter_mut().for_each(strip_ty_def);
+    idl.types.iter_mut().for_each(strip_ty_def);
+    idl.constants.iter_mut().for_each(|c| c.docs = None);
+}
+
+// Returns the number of bytes of `idl_data` already written into the IDL
+// account, which must be a prefix of it.
+fn idl_written(client: &RpcClient, idl_address: &Pubkey, idl_data: &[u8]) -> Result<usize> {
+    let account = match client
+        .get_account_with_commitment(idl_address, CommitmentConfig::processed())?
+        .value
+    {
+        None => return Ok(0),
+        Some(account) => account,
+    };
+    let mut data: &[u8] = &account.data;
+    let idl_account: IdlAccount = AccountDeserialize::try_deserialize(&mut data)?;
+    if !idl_data.starts_with(&idl_account.data) {
+        return Err(anyhow!(
+            "{} already contains a different IDL. Use a new buffer instead.",
+            idl_address
+        ));
+    }
+    Ok(idl_account.data.len())
+}
+
+// Space of an IDL account holding `data_len` bytes of IDL: account
+// discriminator || authority pubkey || vec len || vec data.
+fn idl_account_space(data_len: usize) -> usize {
+    8 + 32 + 4 + data_len
+}
+
+// Prints the transactions and rent needed to write the IDL, after the bytes
+// already `written`, creating an account with `space` bytes first, if given.
+fn print_idl_write_plan(
+    client: &RpcClient,
+    idl_data: &[u8],
+    written: usize,
+    space: Option<usize>,
+    other_txs: usize,
+    chunk_size: usize,
+) -> Result<()> {
+    let remaining = idl_data.len() - written;
+    let write_txs = (remaining + chunk_size - 1) / chunk_size;
+    let create_txs = if space.is_some() { 1 } else { 0 };
+    let rent = match space {
+        None => 0,
+        Some(space) => client.get_minimum_balance_for_rent_exemption(space)?,
+    };
+    println!(
+        "Compressed IDL: {} bytes, {} already written",
+        idl_data.len(),
+        written
+    );
+    println!(
+        "Transactions: {} ({} writes of up to {} bytes)",
+        create_txs + write_txs + other_txs,
+        write_txs,
+        chunk_size
+    );
+    println!(
+        "Rent: {} SOL ({} lamports)",
+        solana_sdk::native_token::lamports_to_sol(rent),
+        rent
+    );
+    Ok(())
+}
+
 fn idl_parse(file: String, out: Option<String>) -> Result<()> {
     let idl = extract_idl(&file)?;
     let out = match out {
@@ -924,6 +1709,111 @@ fn idl_parse(file: String, out: Option<String>) -> Result<()> {
     write_idl(&idl, out)
 }
 
+fn idl_build(program: Option<String>, out: Option<String>) -> Result<()> {
+    let idl = build_idl(Path::new(program.as_deref().unwrap_or(".")))?;
+    let out = match out {
+        None => OutFile::Stdout,
+        Some(out) => OutFile::File(PathBuf::from(out)),
+    };
+    write_idl(&idl, out)
+}
+
+// Builds the IDL of the program crate in `program_dir` from the fragments
+// printed by the tests the `idl-build` feature generates.
+fn build_idl(program_dir: &Path) -> Result<Idl> {
+    let output = std::process::Command::new("cargo")
+        .args(&[
+            "test",
+            "__anchor_private_print_idl",
+            "--features",
+            "idl-build",
+            "--",
+            "--show-output",
+            "--quiet",
+            "--test-threads=1",
+        ])
+        .current_dir(program_dir)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| anyhow::format_err!("cargo test failed: {}", e.to_string()))?;
+    if !output.status.success() {
+        std::process::exit(output.status.code().unwrap_or(1));
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut fragments = vec![];
+    let mut lines = stdout.lines();
+    while let Some(line) = lines.next() {
+        if line == anchor_syn::idl::IDL_FRAGMENT_BEGIN {
+            let json = lines
+                .by_ref()
+                .take_while(|line| *line != anchor_syn::idl::IDL_FRAGMENT_END)
+                .collect::<String>();
+            fragments.push(serde_json::from_str(&json)?);
+        }
+    }
+    idl_from_fragments(fragments)
+}
+
+fn idl_from_fragments(fragments: Vec<IdlFragment>) -> Result<Idl> {
+    let mut name = None;
+    let mut docs = None;
+    let mut instructions = vec![];
+    let mut accounts: Vec<IdlTypeDef> = vec![];
+    let mut types: Vec<IdlTypeDef> = vec![];
+    let mut events = vec![];
+    let mut errors = vec![];
+    let mut constants = vec![];
+    for fragment in fragments {
+        if let Some(fragment_name) = fragment.name {
+            if name.is_some() {
+                return Err(anyhow!("Found more than one #[program]"));
+            }
+            name = Some(fragment_name);
+            docs = fragment.docs;
+            instructions = fragment.instructions;
+        }
+        accounts.extend(fragment.accounts);
+        // Types are repeated in the fragment of each item using them.
+        for ty_def in fragment.types {
+            if !types.iter().any(|t| t.name == ty_def.name) {
+                types.push(ty_def);
+            }
+        }
+        events.extend(fragment.events);
+        errors.extend(fragment.errors);
+        constants.extend(fragment.constants);
+    }
+    types.retain(|ty_def| !accounts.iter().any(|acc| acc.name == ty_def.name));
+
+    let mut idl = Idl {
+        version: "0.0.0".to_string(),
+        spec: anchor_syn::idl::IDL_SPEC.to_string(),
+        name: name.ok_or_else(|| {
+            anyhow!("#[program] not found. Is the idl-build feature defined in Cargo.toml?")
+        })?,
+        docs,
+        state: None,
+        instructions,
+        accounts,
+        types,
+        events: if events.is_empty() {
+            None
+        } else {
+            Some(events)
+        },
+        errors: if errors.is_empty() {
+            None
+        } else {
+            Some(errors)
+        },
+        constants,
+        metadata: None,
+    };
+    anchor_syn::idl::set_layouts(&mut idl);
+    Ok(idl)
+}
+
 fn idl_fetch(cfg_override: &ConfigOverride, address: Pubkey, out: Option<String>) -> Result<()> {
     let idl = fetch_idl(cfg_override, address)?;
     let out = match out {
@@ -933,6 +1823,73 @@ fn idl_fetch(cfg_override: &ConfigOverride, address: Pubkey, out: Option<String>
     write_idl(&idl, out)
 }
 
+fn idl_diff(cfg_override: &ConfigOverride, old: String, new: String) -> Result<()> {
+    let old_idl = read_or_fetch_idl(cfg_override, &old)?;
+    let new_idl = read_or_fetch_idl(cfg_override, &new)?;
+    let (breaking, compatible): (Vec<_>, Vec<_>) = idl_diff::diff(&old_idl, &new_idl)
+        .into_iter()
+        .partition(|change| change.breaking);
+
+    if breaking.is_empty() && compatible.is_empty() {
+        println!("No changes");
+        return Ok(());
+    }
+    if !breaking.is_empty() {
+        println!("Breaking changes:");
+        for change in &breaking {
+            println!("  {}", change);
+        }
+    }
+    if !compatible.is_empty() {
+        println!("Compatible changes:");
+        for change in &compatible {
+            println!("  {}", change);
+        }
+    }
+    if !breaking.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn idl_validate(file: String) -> Result<()> {
+    let bytes = fs::read(&file).map_err(|e| anyhow!("Unable to read IDL {}: {}", file, e))?;
+    let idl: serde_json::Value =
+        serde_json::from_slice(&bytes).map_err(|e| anyhow!("Invalid JSON {}: {}", file, e))?;
+    let errors = anchor_syn::idl::schema::validate(&idl);
+    if errors.is_empty() {
+        println!("Valid IDL of spec {}", anchor_syn::idl::IDL_SPEC);
+        return Ok(());
+    }
+    println!("Invalid IDL:");
+    for error in &errors {
+        println!("  {}", error);
+    }
+    let is_outdated = idl.get("spec") != Some(&serde_json::json!(anchor_syn::idl::IDL_SPEC));
+    if is_outdated && anchor_syn::idl::migrate(idl).is_ok() {
+        println!(
+            "The IDL is of a previous spec, which the CLI upgrades to spec {} when reading it.",
+            anchor_syn::idl::IDL_SPEC
+        );
+    }
+    std::process::exit(1);
+}
+
+// Reads the IDL from the given file or, if there isn't one, fetches it from
+// the given address.
+fn read_or_fetch_idl(cfg_override: &ConfigOverride, idl: &str) -> Result<Idl> {
+    if !Path::new(idl).exists() {
+        if let Ok(address) = idl.parse::<Pubkey>() {
+            return fetch_idl(cfg_override, address);
+        }
+    }
+    let bytes = fs::read(idl).map_err(|e| anyhow!("Unable to read IDL {}: {}", idl, e))?;
+    serde_json::from_slice(&bytes)
+        .map_err(Into::into)
+        .and_then(anchor_syn::idl::migrate)
+        .map_err(|e| anyhow!("Invalid IDL {}: {}", idl, e))
+}
+
 fn write_idl(idl: &Idl, out: OutFile) -> Result<()> {
     let idl_json = serde_json::to_string_pretty(idl)?;
     match out {
@@ -942,6 +1899,34 @@ fn write_idl(idl: &Idl, out: OutFile) -> Result<()> {
     Ok(())
 }
 
+fn account(cfg_override: &ConfigOverride, subcmd: AccountCommand) -> Result<()> {
+    match subcmd {
+        AccountCommand::Dump { address, out } => account_dump(cfg_override, address, out),
+    }
+}
+
+fn account_dump(cfg_override: &ConfigOverride, address: Pubkey, out: Option<String>) -> Result<()> {
+    // Outside a workspace, the cluster must be given explicitly.
+    let cluster = match Config::discover(cfg_override)? {
+        Some((cfg, _, _)) => cfg.provider.cluster,
+        None => cfg_override.cluster.clone().ok_or_else(|| {
+            anyhow!("Not in a workspace, use --provider.cluster to select a cluster")
+        })?,
+    };
+    let client = RpcClient::new(cluster.url().to_string());
+    let account = client
+        .get_account_with_commitment(&address, CommitmentConfig::processed())?
+        .value
+        .map_or(Err(anyhow!("Account not found")), Ok)?;
+
+    let fixture_json = serde_json::to_string_pretty(&AccountFixture::new(&address, &account))?;
+    match out {
+        None => println!("{}", fixture_json),
+        Some(out) => std::fs::write(out, fixture_json)?,
+    };
+    Ok(())
+}
+
 enum OutFile {
     Stdout,
     File(PathBuf),
@@ -954,6 +1939,7 @@ fn test(
     skip_local_validator: bool,
     skip_build: bool,
     use_yarn: bool,
+    suite: Option<String>,
     file: Option<String>,
 ) -> Result<()> {
     with_workspace(cfg_override, |cfg, _path, _cargo| {
@@ -962,147 +1948,282 @@ fn test(
             build(cfg_override, None, false)?;
         }
 
-        // Run the deploy against the cluster in two cases:
-        //
-        // 1. The cluster is not localnet.
-        // 2. The cluster is localnet, but we're not booting a local validator.
-        //
-        // In either case, skip the deploy if the user specifies.
-        let is_localnet = cfg.provider.cluster == Cluster::Localnet;
-        if !is_localnet || (is_localnet && skip_local_validator) {
-            if !skip_deploy {
-                deploy(cfg_override, None)?;
+        // Run the given suite, all configured suites or, if there are none,
+        // the default tests.
+        let suites: Vec<(Option<&String>, Option<&TestSuite>)> = match &suite {
+            Some(name) => vec![(Some(name), Some(cfg.test_suite(name)?))],
+            None => match cfg.test_suites() {
+                suites if suites.is_empty() => vec![(None, None)],
+                suites => suites
+                    .into_iter()
+                    .map(|(name, suite)| (Some(name), Some(suite)))
+                    .collect(),
+            },
+        };
+
+        // Run every suite, even if one fails, and exit with the status of the
+        // first failure at the end.
+        let mut results = Vec::new();
+        let mut exit_code = None;
+        let mut deployed = false;
+        for (name, suite) in suites {
+            if let Some(name) = name {
+                println!("Running test suite: {}", name);
+            }
+            let skip_local_validator =
+                skip_local_validator || suite.map(|s| s.skip_local_validator).unwrap_or(false);
+
+            // The suite's validator settings replace the workspace's.
+            let validator = suite
+                .and_then(|s| s.validator.as_ref())
+                .or_else(|| cfg.test.as_ref().and_then(|t| t.validator.as_ref()))
+                .cloned()
+                .unwrap_or_default();
+
+            // Run the deploy against the cluster in two cases:
+            //
+            // 1. The cluster is not localnet.
+            // 2. The cluster is localnet, but we're not booting a local validator.
+            //
+            // In either case, skip the deploy if the user specifies or if
+            // a previous suite already deployed.
+            let is_localnet = cfg.provider.cluster == Cluster::Localnet;
+            if !is_localnet || (is_localnet && skip_local_validator) {
+                if !skip_deploy && !deployed {
+                    deploy(cfg_override, None)?;
+                    deployed = true;
+                }
+            }
+            // Start local test validator, if needed.
+            let mut validator_handle = None;
+            let mut url = cfg.provider.cluster.url().to_string();
+            if is_localnet && (!skip_local_validator) {
+                let flags = match skip_deploy {
+                    true => None,
+                    false => Some(genesis_flags(cfg, suite, &validator)?),
+                };
+                validator_handle = Some(start_test_validator(cfg, &validator, flags)?);
+                url = format!("http://127.0.0.1:{}", validator.rpc_port());
+
+                // Upgradeable programs can't be embedded in the genesis, so
+                // deploy them to the running validator.
+                if validator.upgradeable && !skip_deploy {
+                    let ws_url = format!("ws://127.0.0.1:{}", validator.rpc_port() + 1);
+                    let validator_override = ConfigOverride {
+                        cluster: Some(Cluster::Custom(url.clone(), ws_url)),
+                        wallet: cfg_override.wallet.clone(),
+                    };
+                    deploy(&validator_override, None)?;
+                }
             }
-        }
-        // Start local test validator, if needed.
-        let mut validator_handle = None;
-        if is_localnet && (!skip_local_validator) {
-            let flags = match skip_deploy {
-                true => None,
-                false => Some(genesis_flags(cfg)?),
-            };
-            validator_handle = Some(start_test_validator(cfg, flags)?);
-        }
 
-        // Setup log reader.
-        let log_streams = stream_logs(&cfg.provider.cluster.url());
+            // Setup log reader.
+            let log_streams = stream_logs(&url);
 
-        // Check to see if yarn is installed, panic if not.
-        if use_yarn {
-            which::which("yarn").unwrap();
-        }
+            // Run the tests.
+            let test_result = match (suite, cfg.scripts.get("test")) {
+                (Some(suite), _) => {
+                    run_script(cfg, &url, cfg.script(&suite.script), file.as_deref())
+                }
+                (None, Some(script)) => run_script(cfg, &url, script, file.as_deref()),
+                (None, None) => run_mocha(cfg, &url, use_yarn, file.as_deref()),
+            };
 
-        // Run the tests.
-        let test_result: Result<_> = {
-            let ts_config_exist = Path::new("tsconfig.json").exists();
-            let mut args = vec!["-t", "1000000"];
-            if let Some(ref file) = file {
-                args.push(file);
-            } else if ts_config_exist {
-                args.push("tests/**/*.spec.ts");
-            } else {
-                args.push("tests/");
+            // Check all errors and shut down.
+            if let Some(mut child) = validator_handle {
+                if let Err(err) = child.kill() {
+                    println!("Failed to kill subprocess {}: {}", child.id(), err);
+                }
+            }
+            for mut child in log_streams? {
+                if let Err(err) = child.kill() {
+                    println!("Failed to kill subprocess {}: {}", child.id(), err);
+                }
             }
-            let exit = match (ts_config_exist, use_yarn) {
-                (true, true) => std::process::Command::new("yarn")
-                    .arg("ts-mocha")
-                    .arg("-p")
-                    .arg("./tsconfig.json")
-                    .args(args)
-                    .env("ANCHOR_PROVIDER_URL", cfg.provider.cluster.url())
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit())
-                    .output()
-                    .map_err(anyhow::Error::from)
-                    .with_context(|| "ts-mocha"),
-                (false, true) => std::process::Command::new("yarn")
-                    .arg("mocha")
-                    .args(args)
-                    .env("ANCHOR_PROVIDER_URL", cfg.provider.cluster.url())
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit())
-                    .output()
-                    .map_err(anyhow::Error::from)
-                    .with_context(|| "mocha"),
-                (true, false) => std::process::Command::new("ts-mocha")
-                    .arg("-p")
-                    .arg("./tsconfig.json")
-                    .args(args)
-                    .env("ANCHOR_PROVIDER_URL", cfg.provider.cluster.url())
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit())
-                    .output()
-                    .map_err(anyhow::Error::from)
-                    .with_context(|| "ts-mocha"),
-                (false, false) => std::process::Command::new("mocha")
-                    .args(args)
-                    .env("ANCHOR_PROVIDER_URL", cfg.provider.cluster.url())
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit())
-                    .output()
-                    .map_err(anyhow::Error::from)
-                    .with_context(|| "mocha"),
+            let passed = match test_result {
+                Ok(exit) => {
+                    if !exit.status.success() {
+                        exit_code.get_or_insert(exit.status.code().unwrap_or(1));
+                    }
+                    exit.status.success()
+                }
+                Err(err) => {
+                    println!("Failed to run test: {:#}", err);
+                    exit_code.get_or_insert(1);
+                    false
+                }
             };
+            results.push((name, passed));
+        }
 
-            exit
-        };
-
-        // Check all errors and shut down.
-        if let Some(mut child) = validator_handle {
-            if let Err(err) = child.kill() {
-                println!("Failed to kill subprocess {}: {}", child.id(), err);
+        // Summarize the suites, so that failures aren't lost in the output
+        // of the ones run after them.
+        if results.iter().any(|(name, _)| name.is_some()) {
+            println!("\nTest suites:");
+            for (name, passed) in &results {
+                let status = match passed {
+                    true => "passed",
+                    false => "failed",
+                };
+                println!(
+                    "  {}: {}",
+                    name.map(String::as_str).unwrap_or("default"),
+                    status
+                );
             }
         }
-        for mut child in log_streams? {
-            if let Err(err) = child.kill() {
-                println!("Failed to kill subprocess {}: {}", child.id(), err);
-            }
+        if let Some(code) = exit_code {
+            std::process::exit(code);
         }
-        match test_result {
-            Ok(exit) => {
-                if !exit.status.success() {
-                    std::process::exit(exit.status.code().unwrap());
-                }
-            }
-            Err(err) => {
-                println!("Failed to run test: {:#}", err)
-            }
+
+        Ok(())
+    })
+}
+
+// Runs a shell command, appending `file` if given, with the cluster `url` and
+// the provider's wallet in its environment.
+fn run_script(
+    cfg: &Config,
+    url: &str,
+    script: &str,
+    file: Option<&str>,
+) -> Result<std::process::Output> {
+    let script = match file {
+        None => script.to_string(),
+        Some(file) => format!("{} {}", script, file),
+    };
+    std::process::Command::new("bash")
+        .arg("-c")
+        .arg(&script)
+        .env("ANCHOR_PROVIDER_URL", url)
+        .env("ANCHOR_WALLET", cfg.provider.wallet.to_string())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(anyhow::Error::from)
+        .with_context(|| script)
+}
+
+// Runs the tests with mocha, for workspaces without a test script.
+fn run_mocha(
+    cfg: &Config,
+    url: &str,
+    use_yarn: bool,
+    file: Option<&str>,
+) -> Result<std::process::Output> {
+    // Check to see if yarn is installed, panic if not.
+    if use_yarn {
+        which::which("yarn").unwrap();
+    }
+
+    let ts_config_exist = Path::new("tsconfig.json").exists();
+    let mut args = vec!["-t", "1000000"];
+    if let Some(file) = file {
+        args.push(file);
+    } else if ts_config_exist {
+        args.push("tests/**/*.spec.ts");
+    } else {
+        args.push("tests/");
+    }
+    let (program, mut program_args) = match (ts_config_exist, use_yarn) {
+        (true, true) => ("yarn", vec!["ts-mocha", "-p", "./tsconfig.json"]),
+        (false, true) => ("yarn", vec!["mocha"]),
+        (true, false) => ("ts-mocha", vec!["-p", "./tsconfig.json"]),
+        (false, false) => ("mocha", vec![]),
+    };
+    program_args.append(&mut args);
+    std::process::Command::new(program)
+        .args(program_args)
+        .env("ANCHOR_PROVIDER_URL", url)
+        .env("ANCHOR_WALLET", cfg.provider.wallet.to_string())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(anyhow::Error::from)
+        .with_context(|| program)
+}
+
+fn run(cfg_override: &ConfigOverride, script: String) -> Result<()> {
+    with_workspace(cfg_override, |cfg, _path, _cargo| {
+        let script = cfg
+            .scripts
+            .get(&script)
+            .ok_or_else(|| anyhow!("Unable to find script: {}", script))?;
+        let exit = run_script(cfg, cfg.provider.cluster.url(), script, None)?;
+        if !exit.status.success() {
+            std::process::exit(exit.status.code().unwrap_or(1));
         }
+        Ok(())
+    })
+}
 
+// Runs the workspace's Rust tests against the compiled programs, via the
+// `anchor-test` harness. No validator or deploy is needed, since the harness
+// loads the programs from `BPF_OUT_DIR`.
+fn test_rust(
+    cfg_override: &ConfigOverride,
+    skip_build: bool,
+    filter: Option<String>,
+) -> Result<()> {
+    with_workspace(cfg_override, |_cfg, _path, _cargo| {
+        if !skip_build {
+            build(cfg_override, None, false)?;
+        }
+        let bpf_out_dir = std::env::current_dir()?.join("target/deploy");
+        let exit = std::process::Command::new("cargo")
+            .arg("test")
+            .args(filter)
+            .env("BPF_OUT_DIR", bpf_out_dir)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| anyhow::format_err!("cargo test failed: {}", e.to_string()))?;
+        if !exit.status.success() {
+            std::process::exit(exit.status.code().unwrap_or(1));
+        }
         Ok(())
     })
 }
 
 // Returns the solana-test-validator flags to embed the workspace programs
 // in the genesis block. This allows us to run tests without every deploying.
-fn genesis_flags(cfg: &Config) -> Result<Vec<String>> {
+fn genesis_flags(
+    cfg: &Config,
+    suite: Option<&TestSuite>,
+    validator: &Validator,
+) -> Result<Vec<String>> {
     let mut flags = Vec::new();
-    for mut program in read_all_programs()? {
-        let binary_path = program.binary_path().display().to_string();
+    // Upgradeable programs are deployed after startup instead.
+    if !validator.upgradeable {
+        for mut program in read_all_programs()? {
+            let binary_path = program.binary_path().display().to_string();
 
-        let kp = Keypair::generate(&mut OsRng);
-        let address = kp.pubkey().to_string();
+            let address = cfg.program_id(&program)?.to_string();
 
-        flags.push("--bpf-program".to_string());
-        flags.push(address.clone());
-        flags.push(binary_path);
+            flags.push("--bpf-program".to_string());
+            flags.push(address.clone());
+            flags.push(binary_path);
 
-        // Add program address to the IDL.
-        program.idl.metadata = Some(serde_json::to_value(IdlTestMetadata { address })?);
+            // Add program address to the IDL.
+            program.idl.metadata = Some(serde_json::to_value(IdlTestMetadata { address })?);
 
-        // Persist it.
-        let idl_out = PathBuf::from("target/idl")
-            .join(&program.idl.name)
-            .with_extension("json");
-        write_idl(&program.idl, OutFile::File(idl_out))?;
-    }
-    if let Some(test) = cfg.test.as_ref() {
-        for entry in &test.genesis {
-            flags.push("--bpf-program".to_string());
-            flags.push(entry.address.clone());
-            flags.push(entry.program.clone());
+            // Persist it.
+            let idl_out = PathBuf::from("target/idl")
+                .join(&program.idl.name)
+                .with_extension("json");
+            write_idl(&program.idl, OutFile::File(idl_out))?;
         }
     }
+    let genesis = cfg
+        .test
+        .iter()
+        .flat_map(|test| test.genesis.iter())
+        .chain(suite.iter().flat_map(|suite| suite.genesis.iter()));
+    for entry in genesis {
+        flags.push("--bpf-program".to_string());
+        flags.push(entry.address.clone());
+        flags.push(entry.program.clone());
+    }
     Ok(flags)
 }
 
@@ -1117,7 +2238,7 @@ fn stream_logs(url: &str) -> Result<Vec<std::process::Child>> {
         let mut file = File::open(&format!("target/idl/{}.json", program.lib_name))?;
         let mut contents = vec![];
         file.read_to_end(&mut contents)?;
-        let idl: Idl = serde_json::from_slice(&contents)?;
+        let idl = anchor_syn::idl::migrate(serde_json::from_slice(&contents)?)?;
         let metadata = idl
             .metadata
             .ok_or_else(|| anyhow!("Program address not found."))?;
@@ -1145,13 +2266,27 @@ pub struct IdlTestMetadata {
     address: String,
 }
 
-fn start_test_validator(cfg: &Config, flags: Option<Vec<String>>) -> Result<Child> {
+fn start_test_validator(
+    cfg: &Config,
+    validator: &Validator,
+    flags: Option<Vec<String>>,
+) -> Result<Child> {
     fs::create_dir_all(".anchor")?;
-    let test_ledger_filename = ".anchor/test-ledger";
+    let test_ledger_filename = validator.ledger();
     let test_ledger_log_filename = ".anchor/test-ledger-log.txt";
 
-    if Path::new(test_ledger_filename).exists() {
-        std::fs::remove_dir_all(test_ledger_filename)?;
+    // Only clear directories that are ledgers, so that a misconfigured path
+    // never deletes anything else.
+    let test_ledger = Path::new(test_ledger_filename);
+    if test_ledger.exists() {
+        let is_empty = test_ledger.read_dir()?.next().is_none();
+        if !is_empty && !test_ledger.join("genesis.bin").exists() {
+            return Err(anyhow!(
+                "{} is not a ledger directory, refusing to clear it",
+                test_ledger.display()
+            ));
+        }
+        std::fs::remove_dir_all(test_ledger)?;
     }
     if Path::new(test_ledger_log_filename).exists() {
         std::fs::remove_file(test_ledger_log_filename)?;
@@ -1166,15 +2301,16 @@ fn start_test_validator(cfg: &Config, flags: Option<Vec<String>>) -> Result<Chil
         .arg("--mint")
         .arg(cfg.wallet_kp()?.pubkey().to_string())
         .args(flags.unwrap_or_default())
+        .args(validator_flags(validator)?)
         .stdout(Stdio::from(test_validator_stdout))
         .stderr(Stdio::from(test_validator_stderr))
         .spawn()
         .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
 
     // Wait for the validator to be ready.
-    let client = RpcClient::new("http://localhost:8899".to_string());
+    let client = RpcClient::new(format!("http://127.0.0.1:{}", validator.rpc_port()));
     let mut count = 0;
-    let ms_wait = 5000;
+    let ms_wait = validator.startup_wait();
     while count < ms_wait {
         let r = client.get_recent_blockhash();
         if r.is_ok() {
@@ -1183,7 +2319,7 @@ fn start_test_validator(cfg: &Config, flags: Option<Vec<String>>) -> Result<Chil
         std::thread::sleep(std::time::Duration::from_millis(1));
         count += 1;
     }
-    if count == 5000 {
+    if count == ms_wait {
         println!("Unable to start test validator.");
         std::process::exit(1);
     }
@@ -1191,6 +2327,50 @@ fn start_test_validator(cfg: &Config, flags: Option<Vec<String>>) -> Result<Chil
     Ok(validator_handle)
 }
 
+// Returns the solana-test-validator flags for the given validator settings.
+fn validator_flags(validator: &Validator) -> Result<Vec<String>> {
+    let mut flags = vec!["--rpc-port".to_string(), validator.rpc_port().to_string()];
+    if let Some(slots_per_epoch) = validator.slots_per_epoch {
+        flags.push("--slots-per-epoch".to_string());
+        flags.push(slots_per_epoch.to_string());
+    }
+    if let Some(warp_slot) = validator.warp_slot {
+        flags.push("--warp-slot".to_string());
+        flags.push(warp_slot.to_string());
+    }
+    if let Some(url) = validator.url.as_ref() {
+        flags.push("--url".to_string());
+        flags.push(url.clone());
+    }
+    for entry in &validator.clone {
+        flags.push("--clone".to_string());
+        flags.push(entry.address.clone());
+    }
+
+    // Accounts are loaded from fixture files, so inline accounts are written to
+    // `.anchor/accounts` first.
+    let accounts_dir = Path::new(".anchor/accounts");
+    if accounts_dir.exists() {
+        fs::remove_dir_all(accounts_dir)?;
+    }
+    for entry in &validator.account {
+        let fixture = entry.fixture()?;
+        let filename = match entry.filename.as_ref() {
+            Some(filename) => filename.clone(),
+            None => {
+                fs::create_dir_all(accounts_dir)?;
+                let path = accounts_dir.join(&entry.address).with_extension("json");
+                fs::write(&path, serde_json::to_string_pretty(&fixture)?)?;
+                path.display().to_string()
+            }
+        };
+        flags.push("--account".to_string());
+        flags.push(entry.address.clone());
+        flags.push(filename);
+    }
+    Ok(flags)
+}
+
 fn deploy(cfg_override: &ConfigOverride, program_name: Option<String>) -> Result<()> {
     _deploy(cfg_override, program_name).map(|_| ())
 }
@@ -1202,6 +2382,7 @@ fn _deploy(
     with_workspace(cfg_override, |cfg, _path, _cargo| {
         let url = cfg.provider.cluster.url().to_string();
         let keypair = cfg.provider.wallet.to_string();
+        let client = RpcClient::new(url.clone());
 
         // Deploy the programs.
         println!("Deploying workspace: {}", url);
@@ -1210,25 +2391,39 @@ fn _deploy(
         let mut programs = Vec::new();
 
         for mut program in read_all_programs()? {
+            let program_name = program.path.file_name().unwrap().to_str().unwrap();
             if let Some(single_prog_str) = &program_str {
-                let program_name = program.path.file_name().unwrap().to_str().unwrap();
-                if single_prog_str.as_str() != program_name {
+                if single_prog_str != program_name && single_prog_str != &program.lib_name {
                     continue;
                 }
             }
             let binary_path = program.binary_path().display().to_string();
+            let program_id = cfg.program_id(&program)?;
+            let is_deployed = client
+                .get_account_with_commitment(&program_id, CommitmentConfig::processed())?
+                .value
+                .is_some();
 
-            println!(
-                "Deploying program {:?}...",
-                program.path.file_name().unwrap().to_str().unwrap()
-            );
+            match is_deployed {
+                false => println!("Deploying program {:?}...", program_name),
+                true => println!("Upgrading program {:?}...", program_name),
+            }
             println!("Program path: {}...", binary_path);
-
-            // Write the program's keypair filepath. This forces a new deploy
-            // address.
-            let program_kp = Keypair::generate(&mut OsRng);
-            let mut file = File::create(program.anchor_keypair_path())?;
-            file.write_all(format!("{:?}", &program_kp.to_bytes()).as_bytes())?;
+            println!("Program Id: {}", program_id);
+
+            // A new program can only be deployed with its keypair, whereas an
+            // upgrade only needs the address.
+            let program_id_arg = match program.keypair()?.pubkey() == program_id {
+                true => program.keypair_path().display().to_string(),
+                false if is_deployed => program_id.to_string(),
+                false => {
+                    return Err(anyhow!(
+                        "Program {} is not deployed and its keypair {} doesn't match its address in Anchor.toml",
+                        program_id,
+                        program.keypair_path().display()
+                    ))
+                }
+            };
 
             // Send deploy transactions.
             let exit = std::process::Command::new("solana")
@@ -1239,7 +2434,7 @@ fn _deploy(
                 .arg("--keypair")
                 .arg(&keypair)
                 .arg("--program-id")
-                .arg(program.anchor_keypair_path().display().to_string())
+                .arg(program_id_arg)
                 .arg(&binary_path)
                 .stdout(Stdio::inherit())
                 .stderr(Stdio::inherit())
@@ -1252,7 +2447,7 @@ fn _deploy(
 
             // Add program address to the IDL.
             program.idl.metadata = Some(serde_json::to_value(IdlTestMetadata {
-                address: program_kp.pubkey().to_string(),
+                address: program_id.to_string(),
             })?);
 
             // Persist it.
@@ -1261,7 +2456,13 @@ fn _deploy(
                 .with_extension("json");
             write_idl(&program.idl, OutFile::File(idl_out))?;
 
-            programs.push((program_kp.pubkey(), program))
+            programs.push((program_id, program))
+        }
+
+        if let Some(single_prog_str) = &program_str {
+            if programs.is_empty() {
+                return Err(anyhow!("Program not found: {}", single_prog_str));
+            }
         }
 
         println!("Deploy success");
@@ -1316,7 +2517,8 @@ fn launch(
         // Add metadata to all IDLs.
         for (address, program) in programs {
             // Store the IDL on chain.
-            let idl_address = create_idl_account(&cfg, &keypair, &address, &program.idl)?;
+            let idl_address =
+                create_idl_account(&cfg, &keypair, &address, &program.idl, MAX_IDL_WRITE_SIZE)?;
             println!("IDL account created: {}", idl_address.to_string());
         }
 
@@ -1330,33 +2532,28 @@ fn launch(
     })
 }
 
-// The Solana CLI doesn't redeploy a program if this file exists.
-// So remove it to make all commands explicit.
-fn clear_program_keys() -> Result<()> {
-    for program in read_all_programs()? {
-        let anchor_keypair_path = program.anchor_keypair_path();
-        if Path::exists(&anchor_keypair_path) {
-            std::fs::remove_file(anchor_keypair_path).expect("Always remove");
-        }
-    }
-    Ok(())
-}
-
+// Creates the canonical IDL account and writes the IDL into it. If a previous
+// attempt was interrupted, the existing account is written into instead.
 fn create_idl_account(
     cfg: &Config,
     keypair_path: &str,
     program_id: &Pubkey,
     idl: &Idl,
+    chunk_size: usize,
 ) -> Result<Pubkey> {
     // Misc.
     let idl_address = IdlAccount::address(program_id);
     let keypair = solana_sdk::signature::read_keypair_file(keypair_path)
         .map_err(|_| anyhow!("Unable to read keypair file"))?;
     let client = RpcClient::new(cfg.provider.cluster.url().to_string());
-    let idl_data = serialize_idl(idl)?;
+    let idl_data = idl_write_data(idl)?;
+    let is_created = client
+        .get_account_with_commitment(&idl_address, CommitmentConfig::processed())?
+        .value
+        .is_some();
 
     // Run `Create instruction.
-    {
+    if !is_created {
         let data = serialize_idl_ix(anchor_lang::idl::IdlInstruction::Create {
             data_len: (idl_data.len() as u64) * 2, // Double for future growth.
         })?;
@@ -1368,6 +2565,7 @@ fn create_idl_account(
             AccountMeta::new_readonly(solana_program::system_program::ID, false),
             AccountMeta::new_readonly(*program_id, false),
             AccountMeta::new_readonly(solana_program::sysvar::rent::ID, false),
+            AccountMeta::new_readonly(IdlAccount::resize_address(program_id), false),
         ];
         let ix = Instruction {
             program_id: *program_id,
@@ -1392,7 +2590,13 @@ fn create_idl_account(
     }
 
     // Write directly to the IDL account buffer.
-    idl_write(cfg, program_id, idl, IdlAccount::address(program_id))?;
+    idl_write(
+        cfg,
+        program_id,
+        idl,
+        IdlAccount::address(program_id),
+        chunk_size,
+    )?;
 
     Ok(idl_address)
 }
@@ -1411,7 +2615,7 @@ fn create_idl_buffer(
 
     // Creates the new buffer account with the system program.
     let create_account_ix = {
-        let space = 8 + 32 + 4 + serialize_idl(idl)?.len() as usize;
+        let space = idl_account_space(idl_write_data(idl)?.len());
         let lamports = client.get_minimum_balance_for_rent_exemption(space)?;
         solana_sdk::system_instruction::create_account(
             &keypair.pubkey(),
@@ -1582,6 +2786,150 @@ fn airdrop(cfg_override: &ConfigOverride) -> Result<()> {
     }
 }
 
+fn keys(cfg_override: &ConfigOverride, subcmd: KeysCommand) -> Result<()> {
+    match subcmd {
+        KeysCommand::List => keys_list(cfg_override),
+        KeysCommand::Sync => keys_sync(cfg_override),
+    }
+}
+
+fn keys_list(cfg_override: &ConfigOverride) -> Result<()> {
+    with_workspace(cfg_override, |cfg, _path, _cargo| {
+        for program in read_all_programs()? {
+            println!("{}: {}", program.lib_name, cfg.program_id(&program)?);
+        }
+        Ok(())
+    })
+}
+
+fn keys_sync(cfg_override: &ConfigOverride) -> Result<()> {
+    with_workspace(cfg_override, |cfg, cfg_path, _cargo| {
+        // Edit the config file as text, so that overrides aren't persisted
+        // and comments and formatting are kept.
+        let cfg_src = fs::read_to_string(&cfg_path)?;
+        let cfg_file: Config = cfg_src.parse()?;
+        let cluster = cfg.provider.cluster.to_string();
+        let mut synced_cfg_src = cfg_src.clone();
+
+        generate_program_keypairs()?;
+        for program in read_all_programs()? {
+            let program_id = cfg.program_id(&program)?;
+
+            // Sync Anchor.toml.
+            let is_configured = cfg_file
+                .programs
+                .get(&cfg.provider.cluster)
+                .map(|programs| programs.contains_key(&program.lib_name))
+                .unwrap_or(false);
+            if !is_configured {
+                println!("Adding {} to Anchor.toml: {}", program.lib_name, program_id);
+                synced_cfg_src = add_program_to_config(
+                    &synced_cfg_src,
+                    &cluster,
+                    &program.lib_name,
+                    &program_id,
+                );
+            }
+
+            // Sync declare_id!.
+            let lib_path = program.path.join("src/lib.rs");
+            let lib_src = fs::read_to_string(&lib_path)?;
+            if let Some(synced_src) = sync_declare_id(&lib_src, &program_id) {
+                if synced_src != lib_src {
+                    println!("Updating declare_id! in {}", lib_path.display());
+                    fs::write(&lib_path, synced_src)?;
+                }
+            }
+        }
+
+        if synced_cfg_src != cfg_src {
+            // The programs may be configured in a way the edit doesn't
+            // handle, e.g., as an inline table, so check before writing.
+            synced_cfg_src.parse::<Config>().map_err(|e| {
+                anyhow!(
+                    "Unable to add the programs to [programs.{}] in Anchor.toml: {}",
+                    cluster,
+                    e
+                )
+            })?;
+            fs::write(&cfg_path, synced_cfg_src)?;
+        }
+
+        println!("All program ids are in sync");
+        Ok(())
+    })
+}
+
+// Returns the Anchor.toml source with `<name> = "<program_id>"` added to the
+// `[programs.<cluster>]` table, which is appended if it doesn't exist. The rest
+// of the file, e.g., comments and the order of tables, is left untouched.
+fn add_program_to_config(src: &str, cluster: &str, name: &str, program_id: &Pubkey) -> String {
+    // Custom clusters are keyed by URL, which must be quoted.
+    let key = |k: &str| match k
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        true => k.to_string(),
+        false => format!("\"{}\"", k),
+    };
+    let header = format!("[programs.{}]", key(cluster));
+    let entry = format!("{} = \"{}\"", key(name), program_id);
+
+    let lines: Vec<&str> = src.lines().collect();
+    let table = lines
+        .iter()
+        .position(|l| l.split('#').next().unwrap_or("").trim() == header);
+    match table {
+        None => {
+            let mut synced = src.trim_end().to_string();
+            if !synced.is_empty() {
+                synced.push_str("\n\n");
+            }
+            format!("{}{}\n{}\n", synced, header, entry)
+        }
+        Some(table) => {
+            // Insert after the table's last entry, before any blank lines and
+            // comments leading up to the next table.
+            let mut end = lines[table + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with('['))
+                .map(|idx| table + 1 + idx)
+                .unwrap_or_else(|| lines.len());
+            while end > table + 1 {
+                let line = lines[end - 1].trim();
+                if !line.is_empty() && !line.starts_with('#') {
+                    break;
+                }
+                end -= 1;
+            }
+            let mut synced_lines = lines[..end].to_vec();
+            synced_lines.push(&entry);
+            synced_lines.extend_from_slice(&lines[end..]);
+            let mut synced = synced_lines.join("\n");
+            if src.ends_with('\n') {
+                synced.push('\n');
+            }
+            synced
+        }
+    }
+}
+
+// Returns the source with the address in its `declare_id!` invocation
+// replaced, or None if there isn't one. Only invocations at the start of a
+// line count, so that mentions in comments and docs are skipped.
+fn sync_declare_id(src: &str, program_id: &Pubkey) -> Option<String> {
+    let invocation = std::iter::once(0)
+        .chain(src.match_indices('\n').map(|(idx, _)| idx + 1))
+        .find(|&line| src[line..].trim_start().starts_with("declare_id!("))?;
+    let line_end = src[invocation..]
+        .find('\n')
+        .map(|idx| invocation + idx)
+        .unwrap_or_else(|| src.len());
+    let start = invocation + src[invocation..line_end].find('"')? + 1;
+    let end = start + src[start..line_end].find('"')?;
+    Some(format!("{}{}{}", &src[..start], program_id, &src[end..]))
+}
+
 fn cluster(_cmd: ClusterCommand) -> Result<()> {
     println!("Cluster Endpoints:\n");
     println!("* Mainnet - https://solana-api.projectserum.com");
@@ -1598,7 +2946,7 @@ fn shell(cfg_override: &ConfigOverride) -> Result<()> {
                 .iter()
                 .map(|program| (program.idl.name.clone(), program.idl.clone()))
                 .collect();
-            match cfg.clusters.get(&cfg.provider.cluster) {
+            match cfg.programs.get(&cfg.provider.cluster) {
                 None => Vec::new(),
                 Some(programs) => programs
                     .iter()
@@ -1648,8 +2996,6 @@ fn with_workspace<R>(
 ) -> R {
     set_workspace_dir_or_exit();
 
-    clear_program_keys().unwrap();
-
     let (cfg, cfg_path, cargo_toml) = Config::discover(cfg_override)
         .expect("Previously set the workspace dir")
         .expect("Anchor.toml must always exist");
@@ -1657,7 +3003,6 @@ fn with_workspace<R>(
     let r = f(&cfg, cfg_path, cargo_toml);
 
     set_workspace_dir_or_exit();
-    clear_program_keys().unwrap();
 
     r
 }
@@ -0,0 +1,1556 @@
+//! `coder` encodes and decodes instructions, their return values, accounts,
+//! events, constants and user defined types between their Borsh serialized
+//! bytes and `serde_json::Value`s, as described by an IDL. It's the Rust
+//! equivalent of the TypeScript coder, for clients that don't have the
+//! program's Rust types at compile time.
+//!
+//! Values are mapped to JSON as follows:
+//!
+//! * Integers up to 64 bits are numbers. 128 and 256 bit integers are decimal
+//!   strings, since they can't be represented by a JSON number. Strings are
+//!   also accepted for all integers when encoding.
+//! * Floats are numbers, or `null` for NaN and infinities.
+//! * `publicKey`s are base58 strings.
+//! * `bytes` and `byteArray`s are arrays of numbers.
+//! * `option`s are `null` or the inner value.
+//! * Tuples are arrays, and maps are arrays of `[key, value]` pairs, encoded
+//!   in the given order.
+//! * Structs are objects keyed by field name, so unit structs are empty
+//!   objects, and tuple structs are arrays.
+//! * Zero copy types, i.e., those with `bytemuck` serialization, map to JSON
+//!   as any other struct, but their fields are encoded at the offsets of
+//!   their `layout`, padding included, rather than Borsh serialized.
+//! * Enum variants are externally tagged, i.e., unit variants are strings and
+//!   all other variants are objects with a single key, the variant name,
+//!   mapping to an object of named fields or an array of tuple fields.
+
+use crate::ClientError;
+use anchor_lang::solana_program::hash;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_syn::idl::{
+    EnumFields, Idl, IdlConst, IdlEvent, IdlField, IdlIx, IdlLayout, IdlSerialization, IdlType,
+    IdlTypeDef, IdlTypeDefTy,
+};
+use anchor_syn::parser::file::ConstValue;
+use heck::SnakeCase;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+
+/// Namespace for calculating state instruction sighash signatures.
+pub const SIGHASH_STATE_NAMESPACE: &str = "state";
+
+/// Namespace for calculating instruction sighash signatures for any instruction
+/// not affecting program state.
+pub const SIGHASH_GLOBAL_NAMESPACE: &str = "global";
+
+/// Coder encodes and decodes all the types described by an `Idl`.
+pub struct Coder {
+    idl: Idl,
+}
+
+impl Coder {
+    pub fn new(idl: Idl) -> Self {
+        Self { idl }
+    }
+
+    pub fn idl(&self) -> &Idl {
+        &self.idl
+    }
+
+    /// Encodes the instruction with the given IDL name, prefixed with its
+    /// sighash. `args` is an object mapping every argument name to its value.
+    pub fn encode_instruction(&self, name: &str, args: &Value) -> Result<Vec<u8>, ClientError> {
+        let ix = find_ix(&self.idl.instructions, name)?;
+        self.encode_ix(SIGHASH_GLOBAL_NAMESPACE, ix, args)
+    }
+
+    /// Encodes the `#[state]` method with the given IDL name, prefixed with
+    /// its sighash.
+    pub fn encode_state_instruction(
+        &self,
+        name: &str,
+        args: &Value,
+    ) -> Result<Vec<u8>, ClientError> {
+        let ix = find_ix(self.state_methods(), name)?;
+        self.encode_ix(SIGHASH_STATE_NAMESPACE, ix, args)
+    }
+
+    /// Decodes instruction data, returning the IDL name of the instruction
+    /// and its arguments.
+    pub fn decode_instruction(&self, data: &[u8]) -> Result<(String, Value), ClientError> {
+        let (disc, mut args) = split_discriminator(data)?;
+        let namespaced = self
+            .idl
+            .instructions
+            .iter()
+            .map(|ix| (SIGHASH_GLOBAL_NAMESPACE, ix))
+            .chain(
+                self.state_methods()
+                    .iter()
+                    .map(|ix| (SIGHASH_STATE_NAMESPACE, ix)),
+            );
+        for (namespace, ix) in namespaced {
+            if disc == discriminator(namespace, &ix.name.to_snake_case()) {
+                let value = decode_fields(&self.idl, &ix.args, &mut args)?;
+                return Ok((ix.name.clone(), value));
+            }
+        }
+        Err(error("Unknown instruction sighash".to_string()))
+    }
+
+    /// Decodes the value returned by the instruction with the given IDL name
+    /// from its return data.
+    pub fn decode_return(&self, name: &str, mut data: &[u8]) -> Result<Value, ClientError> {
+        let ix = find_ix(&self.idl.instructions, name)?;
+        let ty = ix
+            .returns
+            .as_ref()
+            .ok_or_else(|| error(format!("Instruction doesn't return a value: {}", name)))?;
+        decode_type(&self.idl, ty, &mut data)
+    }
+
+    fn encode_ix(&self, namespace: &str, ix: &IdlIx, args: &Value) -> Result<Vec<u8>, ClientError> {
+        let mut data = discriminator(namespace, &ix.name.to_snake_case()).to_vec();
+        encode_fields(&self.idl, &ix.args, args, &mut data)?;
+        Ok(data)
+    }
+
+    fn state_methods(&self) -> &[IdlIx] {
+        self.idl
+            .state
+            .as_ref()
+            .map(|s| &s.methods[..])
+            .unwrap_or(&[])
+    }
+
+    /// Encodes the account with the given IDL name, prefixed with its
+    /// discriminator.
+    pub fn encode_account(&self, name: &str, value: &Value) -> Result<Vec<u8>, ClientError> {
+        let type_def = self.account_def(name)?;
+        let mut data = discriminator("account", name).to_vec();
+        encode_type_def(&self.idl, type_def, value, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes the account with the given IDL name from its data, returning
+    /// an error if the discriminator doesn't match.
+    pub fn decode_account(&self, name: &str, data: &[u8]) -> Result<Value, ClientError> {
+        let type_def = self.account_def(name)?;
+        let (disc, mut data) = split_discriminator(data)?;
+        if disc != discriminator("account", name) {
+            return Err(error(format!("Account discriminator mismatch: {}", name)));
+        }
+        decode_type_def(&self.idl, type_def, &mut data)
+    }
+
+    // The state struct is an account, too.
+    fn account_def(&self, name: &str) -> Result<&IdlTypeDef, ClientError> {
+        self.idl
+            .accounts
+            .iter()
+            .chain(self.idl.state.as_ref().map(|s| &s.strct))
+            .find(|a| a.name == name)
+            .ok_or_else(|| error(format!("Account not found: {}", name)))
+    }
+
+    /// Encodes the event with the given IDL name, prefixed with its
+    /// discriminator, as logged by `emit!`.
+    pub fn encode_event(&self, name: &str, value: &Value) -> Result<Vec<u8>, ClientError> {
+        let fields = event_fields(self.event_def(name)?);
+        let mut data = discriminator("event", name).to_vec();
+        encode_fields(&self.idl, &fields, value, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes an event, including its discriminator, returning the IDL name
+    /// of the event and its fields.
+    pub fn decode_event(&self, data: &[u8]) -> Result<(String, Value), ClientError> {
+        let (disc, data) = split_discriminator(data)?;
+        let event = self
+            .idl
+            .events
+            .iter()
+            .flatten()
+            .find(|e| disc == discriminator("event", &e.name))
+            .ok_or_else(|| error("Unknown event discriminator".to_string()))?;
+        let value = self.decode_event_data(&event.name, data)?;
+        Ok((event.name.clone(), value))
+    }
+
+    // Decodes the fields of an event, without the discriminator.
+    pub(crate) fn decode_event_data(
+        &self,
+        name: &str,
+        mut data: &[u8],
+    ) -> Result<Value, ClientError> {
+        let fields = event_fields(self.event_def(name)?);
+        decode_fields(&self.idl, &fields, &mut data)
+    }
+
+    fn event_def(&self, name: &str) -> Result<&IdlEvent, ClientError> {
+        self.idl
+            .events
+            .iter()
+            .flatten()
+            .find(|e| e.name == name)
+            .ok_or_else(|| error(format!("Event not found: {}", name)))
+    }
+
+    /// Encodes the user defined type with the given IDL name.
+    pub fn encode_type(&self, name: &str, value: &Value) -> Result<Vec<u8>, ClientError> {
+        let mut data = Vec::new();
+        encode_type_def(&self.idl, type_def(&self.idl, name)?, value, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes the user defined type with the given IDL name.
+    pub fn decode_type(&self, name: &str, mut data: &[u8]) -> Result<Value, ClientError> {
+        decode_type_def(&self.idl, type_def(&self.idl, name)?, &mut data)
+    }
+
+    /// Encodes the value of the constant with the given IDL name, as if it
+    /// were Borsh serialized by the program.
+    pub fn encode_constant(&self, name: &str) -> Result<Vec<u8>, ClientError> {
+        let constant = self.find_constant(name)?;
+        let mut data = Vec::new();
+        encode_type(
+            &self.idl,
+            &constant.ty,
+            &constant_value(constant)?,
+            &mut data,
+        )?;
+        Ok(data)
+    }
+
+    /// Returns the value of the constant with the given IDL name.
+    pub fn constant(&self, name: &str) -> Result<Value, ClientError> {
+        let constant = self.find_constant(name)?;
+        let data = self.encode_constant(name)?;
+        decode_type(&self.idl, &constant.ty, &mut &data[..])
+    }
+
+    fn find_constant(&self, name: &str) -> Result<&IdlConst, ClientError> {
+        self.idl
+            .constants
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| error(format!("Constant not found: {}", name)))
+    }
+}
+
+/// Returns the first 8 bytes of the sha256 of "<namespace>:<name>", as used
+/// for instruction sighashes ("global" and "state") as well as account
+/// ("account") and event ("event") discriminators.
+pub fn discriminator(namespace: &str, name: &str) -> [u8; 8] {
+    let preimage = format!("{}:{}", namespace, name);
+    let mut disc = [0u8; 8];
+    disc.copy_from_slice(&hash::hash(preimage.as_bytes()).to_bytes()[..8]);
+    disc
+}
+
+fn split_discriminator(data: &[u8]) -> Result<([u8; 8], &[u8]), ClientError> {
+    let mut data = data;
+    let disc = take(&mut data)?;
+    Ok((disc, data))
+}
+
+fn find_ix<'a>(ixs: &'a [IdlIx], name: &str) -> Result<&'a IdlIx, ClientError> {
+    ixs.iter()
+        .find(|ix| ix.name == name)
+        .ok_or_else(|| error(format!("Instruction not found: {}", name)))
+}
+
+fn event_fields(event: &IdlEvent) -> Vec<IdlField> {
+    event
+        .fields
+        .iter()
+        .map(|f| IdlField {
+            name: f.name.clone(),
+            ty: f.ty.clone(),
+            docs: None,
+        })
+        .collect()
+}
+
+fn encode_fields(
+    idl: &Idl,
+    fields: &[IdlField],
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ClientError> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| error(format!("Expected an object, found {}", value)))?;
+    for f in fields {
+        let v = obj
+            .get(&f.name)
+            .ok_or_else(|| error(format!("Missing field: {}", f.name)))?;
+        encode_type(idl, &f.ty, v, out)?;
+    }
+    Ok(())
+}
+
+fn decode_fields(idl: &Idl, fields: &[IdlField], data: &mut &[u8]) -> Result<Value, ClientError> {
+    let mut obj = Map::new();
+    for f in fields {
+        obj.insert(f.name.clone(), decode_type(idl, &f.ty, data)?);
+    }
+    Ok(Value::Object(obj))
+}
+
+fn encode_type(
+    idl: &Idl,
+    ty: &IdlType,
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ClientError> {
+    match ty {
+        IdlType::Bool => out.push(
+            value
+                .as_bool()
+                .ok_or_else(|| error(format!("Expected a bool, found {}", value)))?
+                as u8,
+        ),
+        IdlType::U8 => out.extend_from_slice(&unsigned::<u8>(value)?.to_le_bytes()),
+        IdlType::I8 => out.extend_from_slice(&signed::<i8>(value)?.to_le_bytes()),
+        IdlType::U16 => out.extend_from_slice(&unsigned::<u16>(value)?.to_le_bytes()),
+        IdlType::I16 => out.extend_from_slice(&signed::<i16>(value)?.to_le_bytes()),
+        IdlType::U32 => out.extend_from_slice(&unsigned::<u32>(value)?.to_le_bytes()),
+        IdlType::I32 => out.extend_from_slice(&signed::<i32>(value)?.to_le_bytes()),
+        IdlType::F32 => out.extend_from_slice(&(float(value)? as f32).to_le_bytes()),
+        IdlType::U64 => out.extend_from_slice(&unsigned::<u64>(value)?.to_le_bytes()),
+        IdlType::I64 => out.extend_from_slice(&signed::<i64>(value)?.to_le_bytes()),
+        IdlType::F64 => out.extend_from_slice(&float(value)?.to_le_bytes()),
+        IdlType::U128 => out.extend_from_slice(&unsigned::<u128>(value)?.to_le_bytes()),
+        IdlType::I128 => out.extend_from_slice(&signed::<i128>(value)?.to_le_bytes()),
+        IdlType::U256 => out.extend_from_slice(&int256(value, false)?),
+        IdlType::I256 => out.extend_from_slice(&int256(value, true)?),
+        IdlType::Bytes => encode_type(idl, &IdlType::Vec(Box::new(IdlType::U8)), value, out)?,
+        IdlType::String => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| error(format!("Expected a string, found {}", value)))?;
+            out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+        IdlType::PublicKey => out.extend_from_slice(pubkey(value)?.as_ref()),
+        IdlType::Defined(name) => encode_type_def(idl, type_def(idl, name)?, value, out)?,
+        IdlType::DefinedWithTypeArgs { name, args } => {
+            encode_type_def(idl, &instantiate(type_def(idl, name)?, args)?, value, out)?
+        }
+        IdlType::Generic(name) => return Err(error(format!("Uninstantiated generic: {}", name))),
+        IdlType::Option(inner) => match value {
+            Value::Null => out.push(0),
+            _ => {
+                out.push(1);
+                encode_type(idl, inner, value, out)?;
+            }
+        },
+        IdlType::Vec(inner) => {
+            let items = array(value)?;
+            out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                encode_type(idl, inner, item, out)?;
+            }
+        }
+        IdlType::Array(inner, len) => {
+            let items = array(value)?;
+            if items.len() != *len {
+                return Err(error(format!(
+                    "Expected an array of length {}, found {}",
+                    len,
+                    items.len()
+                )));
+            }
+            for item in items {
+                encode_type(idl, inner, item, out)?;
+            }
+        }
+        IdlType::ByteArray(len) => encode_type(
+            idl,
+            &IdlType::Array(Box::new(IdlType::U8), *len),
+            value,
+            out,
+        )?,
+        IdlType::Tuple(tys) => {
+            let items = array(value)?;
+            if items.len() != tys.len() {
+                return Err(error(format!(
+                    "Expected a tuple of length {}, found {}",
+                    tys.len(),
+                    items.len()
+                )));
+            }
+            for (ty, item) in tys.iter().zip(items) {
+                encode_type(idl, ty, item, out)?;
+            }
+        }
+        IdlType::HashMap(key, value_ty) | IdlType::BTreeMap(key, value_ty) => {
+            let entry_ty = IdlType::Tuple(vec![*key.clone(), *value_ty.clone()]);
+            encode_type(idl, &IdlType::Vec(Box::new(entry_ty)), value, out)?
+        }
+    }
+    Ok(())
+}
+
+fn decode_type(idl: &Idl, ty: &IdlType, data: &mut &[u8]) -> Result<Value, ClientError> {
+    let value = match ty {
+        IdlType::Bool => match take_slice(data, 1)?[0] {
+            0 => Value::Bool(false),
+            1 => Value::Bool(true),
+            b => return Err(error(format!("Invalid bool: {}", b))),
+        },
+        IdlType::U8 => u8::from_le_bytes(take(data)?).into(),
+        IdlType::I8 => i8::from_le_bytes(take(data)?).into(),
+        IdlType::U16 => u16::from_le_bytes(take(data)?).into(),
+        IdlType::I16 => i16::from_le_bytes(take(data)?).into(),
+        IdlType::U32 => u32::from_le_bytes(take(data)?).into(),
+        IdlType::I32 => i32::from_le_bytes(take(data)?).into(),
+        IdlType::F32 => f32::from_le_bytes(take(data)?).into(),
+        IdlType::U64 => u64::from_le_bytes(take(data)?).into(),
+        IdlType::I64 => i64::from_le_bytes(take(data)?).into(),
+        IdlType::F64 => f64::from_le_bytes(take(data)?).into(),
+        IdlType::U128 => u128::from_le_bytes(take(data)?).to_string().into(),
+        IdlType::I128 => i128::from_le_bytes(take(data)?).to_string().into(),
+        IdlType::U256 => int256_to_string(take(data)?, false).into(),
+        IdlType::I256 => int256_to_string(take(data)?, true).into(),
+        IdlType::Bytes => decode_type(idl, &IdlType::Vec(Box::new(IdlType::U8)), data)?,
+        IdlType::String => {
+            let len = u32::from_le_bytes(take(data)?) as usize;
+            let bytes = take_slice(data, len)?;
+            String::from_utf8(bytes.to_vec())
+                .map_err(|e| error(e.to_string()))?
+                .into()
+        }
+        IdlType::PublicKey => Pubkey::new_from_array(take(data)?).to_string().into(),
+        IdlType::Defined(name) => decode_type_def(idl, type_def(idl, name)?, data)?,
+        IdlType::DefinedWithTypeArgs { name, args } => {
+            decode_type_def(idl, &instantiate(type_def(idl, name)?, args)?, data)?
+        }
+        IdlType::Generic(name) => return Err(error(format!("Uninstantiated generic: {}", name))),
+        IdlType::Option(inner) => match take_slice(data, 1)?[0] {
+            0 => Value::Null,
+            1 => decode_type(idl, inner, data)?,
+            b => return Err(error(format!("Invalid option tag: {}", b))),
+        },
+        IdlType::Vec(inner) => {
+            let len = u32::from_le_bytes(take(data)?) as usize;
+            let items = (0..len)
+                .map(|_| decode_type(idl, inner, data))
+                .collect::<Result<Vec<Value>, ClientError>>()?;
+            Value::Array(items)
+        }
+        IdlType::Array(inner, len) => {
+            let items = (0..*len)
+                .map(|_| decode_type(idl, inner, data))
+                .collect::<Result<Vec<Value>, ClientError>>()?;
+            Value::Array(items)
+        }
+        IdlType::ByteArray(len) => {
+            decode_type(idl, &IdlType::Array(Box::new(IdlType::U8), *len), data)?
+        }
+        IdlType::Tuple(tys) => Value::Array(
+            tys.iter()
+                .map(|ty| decode_type(idl, ty, data))
+                .collect::<Result<Vec<Value>, ClientError>>()?,
+        ),
+        IdlType::HashMap(key, value) | IdlType::BTreeMap(key, value) => {
+            let entry_ty = IdlType::Tuple(vec![*key.clone(), *value.clone()]);
+            decode_type(idl, &IdlType::Vec(Box::new(entry_ty)), data)?
+        }
+    };
+    Ok(value)
+}
+
+fn encode_type_def(
+    idl: &Idl,
+    type_def: &IdlTypeDef,
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ClientError> {
+    if let Some(layout) = zero_copy_layout(type_def)? {
+        return encode_zero_copy(idl, type_def, layout, value, out);
+    }
+    match &type_def.ty {
+        IdlTypeDefTy::Struct { fields } => match fields {
+            None => Ok(()),
+            Some(EnumFields::Named(fields)) => encode_fields(idl, fields, value, out),
+            Some(EnumFields::Tuple(tys)) => {
+                encode_type(idl, &IdlType::Tuple(tys.clone()), value, out)
+            }
+        },
+        IdlTypeDefTy::Enum { variants } => {
+            let (name, fields_value) = match value {
+                Value::String(name) => (name, None),
+                Value::Object(obj) if obj.len() == 1 => {
+                    let (name, fields) = obj.iter().next().unwrap();
+                    (name, Some(fields))
+                }
+                _ => {
+                    return Err(error(format!(
+                        "Expected an enum variant of {}, found {}",
+                        type_def.name, value
+                    )))
+                }
+            };
+            let (idx, variant) = variants
+                .iter()
+                .enumerate()
+                .find(|(_, v)| &v.name == name)
+                .ok_or_else(|| error(format!("Unknown variant: {}::{}", type_def.name, name)))?;
+            out.push(idx as u8);
+            match (&variant.fields, fields_value) {
+                (None, _) => Ok(()),
+                (Some(EnumFields::Named(fields)), Some(value)) => {
+                    encode_fields(idl, fields, value, out)
+                }
+                (Some(EnumFields::Tuple(tys)), Some(value)) => {
+                    let items = array(value)?;
+                    if items.len() != tys.len() {
+                        return Err(error(format!(
+                            "Expected {} fields for {}::{}",
+                            tys.len(),
+                            type_def.name,
+                            name
+                        )));
+                    }
+                    for (ty, item) in tys.iter().zip(items) {
+                        encode_type(idl, ty, item, out)?;
+                    }
+                    Ok(())
+                }
+                (Some(_), None) => Err(error(format!(
+                    "Missing fields for {}::{}",
+                    type_def.name, name
+                ))),
+            }
+        }
+    }
+}
+
+fn decode_type_def(
+    idl: &Idl,
+    type_def: &IdlTypeDef,
+    data: &mut &[u8],
+) -> Result<Value, ClientError> {
+    if let Some(layout) = zero_copy_layout(type_def)? {
+        return decode_zero_copy(idl, type_def, layout, data);
+    }
+    match &type_def.ty {
+        IdlTypeDefTy::Struct { fields } => match fields {
+            None => Ok(Value::Object(Map::new())),
+            Some(EnumFields::Named(fields)) => decode_fields(idl, fields, data),
+            Some(EnumFields::Tuple(tys)) => decode_type(idl, &IdlType::Tuple(tys.clone()), data),
+        },
+        IdlTypeDefTy::Enum { variants } => {
+            let idx = take_slice(data, 1)?[0] as usize;
+            let variant = variants.get(idx).ok_or_else(|| {
+                error(format!(
+                    "Invalid variant index for {}: {}",
+                    type_def.name, idx
+                ))
+            })?;
+            let fields = match &variant.fields {
+                None => return Ok(Value::String(variant.name.clone())),
+                Some(EnumFields::Named(fields)) => decode_fields(idl, fields, data)?,
+                Some(EnumFields::Tuple(tys)) => Value::Array(
+                    tys.iter()
+                        .map(|ty| decode_type(idl, ty, data))
+                        .collect::<Result<Vec<Value>, ClientError>>()?,
+                ),
+            };
+            let mut obj = Map::new();
+            obj.insert(variant.name.clone(), fields);
+            Ok(Value::Object(obj))
+        }
+    }
+}
+
+// Zero copy types are cast from their bytes rather than Borsh serialized, so
+// each field is at the offset given by the type's layout, and padding is
+// zeroed.
+fn encode_zero_copy(
+    idl: &Idl,
+    type_def: &IdlTypeDef,
+    layout: &IdlLayout,
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ClientError> {
+    let values: Vec<&Value> = match &type_def.ty {
+        IdlTypeDefTy::Struct { fields: None } => vec![],
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Named(fields)),
+        } => {
+            let obj = value
+                .as_object()
+                .ok_or_else(|| error(format!("Expected an object, found {}", value)))?;
+            fields
+                .iter()
+                .map(|f| {
+                    obj.get(&f.name)
+                        .ok_or_else(|| error(format!("Missing field: {}", f.name)))
+                })
+                .collect::<Result<Vec<&Value>, ClientError>>()?
+        }
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Tuple(_)),
+        } => array(value)?.iter().collect(),
+        IdlTypeDefTy::Enum { .. } => vec![],
+    };
+    let tys = zero_copy_tys(type_def, layout)?;
+    if values.len() != tys.len() {
+        return Err(error(format!(
+            "Expected {} fields for {}, found {}",
+            tys.len(),
+            type_def.name,
+            values.len()
+        )));
+    }
+    let mut data = vec![0; layout.size];
+    for ((ty, field), value) in tys.iter().zip(&layout.fields).zip(values) {
+        let mut bytes = Vec::new();
+        encode_type(idl, ty, value, &mut bytes)?;
+        data.get_mut(field.offset..field.offset + bytes.len())
+            .filter(|_| bytes.len() == field.size)
+            .ok_or_else(|| error(format!("Invalid layout of {}", type_def.name)))?
+            .copy_from_slice(&bytes);
+    }
+    out.extend(data);
+    Ok(())
+}
+
+fn decode_zero_copy(
+    idl: &Idl,
+    type_def: &IdlTypeDef,
+    layout: &IdlLayout,
+    data: &mut &[u8],
+) -> Result<Value, ClientError> {
+    let tys = zero_copy_tys(type_def, layout)?;
+    let bytes = take_slice(data, layout.size)?;
+    let values = tys
+        .iter()
+        .zip(&layout.fields)
+        .map(|(ty, field)| {
+            let mut field_data = bytes
+                .get(field.offset..field.offset + field.size)
+                .ok_or_else(|| error(format!("Invalid layout of {}", type_def.name)))?;
+            decode_type(idl, ty, &mut field_data)
+        })
+        .collect::<Result<Vec<Value>, ClientError>>()?;
+    match &type_def.ty {
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Named(fields)),
+        } => Ok(Value::Object(
+            fields.iter().map(|f| f.name.clone()).zip(values).collect(),
+        )),
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Tuple(_)),
+        } => Ok(Value::Array(values)),
+        _ => Ok(Value::Object(Map::new())),
+    }
+}
+
+// The layout of the type, if it's zero copy. Zero copy types without one,
+// e.g., with fields of types that aren't zero copy, can't be coded, since
+// their bytes aren't Borsh serialized either.
+fn zero_copy_layout(type_def: &IdlTypeDef) -> Result<Option<&IdlLayout>, ClientError> {
+    match type_def.serialization {
+        IdlSerialization::Borsh => Ok(None),
+        IdlSerialization::Bytemuck => type_def.layout.as_ref().map(Some).ok_or_else(|| {
+            error(format!(
+                "Unable to code zero copy type {} without a layout",
+                type_def.name
+            ))
+        }),
+    }
+}
+
+// The types of the fields of a zero copy struct, one for each field layout.
+fn zero_copy_tys<'a>(
+    type_def: &'a IdlTypeDef,
+    layout: &IdlLayout,
+) -> Result<Vec<&'a IdlType>, ClientError> {
+    let tys: Vec<&IdlType> = match &type_def.ty {
+        IdlTypeDefTy::Struct { fields: None } => vec![],
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Named(fields)),
+        } => fields.iter().map(|f| &f.ty).collect(),
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Tuple(tys)),
+        } => tys.iter().collect(),
+        IdlTypeDefTy::Enum { .. } => {
+            return Err(error(format!("Invalid layout of {}", type_def.name)))
+        }
+    };
+    if tys.len() != layout.fields.len() {
+        return Err(error(format!("Invalid layout of {}", type_def.name)));
+    }
+    Ok(tys)
+}
+
+// Looks up a user defined type. Accounts can be embedded in other types, so
+// they're searched as well.
+fn type_def<'a>(idl: &'a Idl, name: &str) -> Result<&'a IdlTypeDef, ClientError> {
+    idl.types
+        .iter()
+        .chain(idl.accounts.iter())
+        .find(|ty| ty.name == name)
+        .ok_or_else(|| error(format!("Type not found: {}", name)))
+}
+
+// Substitutes the type arguments for the type parameters of a generic type.
+fn instantiate(type_def: &IdlTypeDef, args: &[IdlType]) -> Result<IdlTypeDef, ClientError> {
+    if type_def.generics.len() != args.len() {
+        return Err(error(format!(
+            "Expected {} type arguments for {}, found {}",
+            type_def.generics.len(),
+            type_def.name,
+            args.len()
+        )));
+    }
+    fn substitute_fields(fields: &mut Option<EnumFields>, generics: &[String], args: &[IdlType]) {
+        match fields {
+            None => {}
+            Some(EnumFields::Named(fields)) => fields
+                .iter_mut()
+                .for_each(|f| substitute(&mut f.ty, generics, args)),
+            Some(EnumFields::Tuple(tys)) => {
+                tys.iter_mut().for_each(|ty| substitute(ty, generics, args))
+            }
+        }
+    }
+    fn substitute(ty: &mut IdlType, generics: &[String], args: &[IdlType]) {
+        match ty {
+            IdlType::Generic(name) => {
+                if let Some(idx) = generics.iter().position(|g| g == name) {
+                    *ty = args[idx].clone();
+                }
+            }
+            IdlType::Option(ty) | IdlType::Vec(ty) | IdlType::Array(ty, _) => {
+                substitute(ty, generics, args)
+            }
+            IdlType::Tuple(tys) | IdlType::DefinedWithTypeArgs { args: tys, .. } => {
+                tys.iter_mut().for_each(|ty| substitute(ty, generics, args))
+            }
+            IdlType::HashMap(key, value) | IdlType::BTreeMap(key, value) => {
+                substitute(key, generics, args);
+                substitute(value, generics, args);
+            }
+            _ => {}
+        }
+    }
+    let mut type_def = type_def.clone();
+    let generics = std::mem::take(&mut type_def.generics);
+    match &mut type_def.ty {
+        IdlTypeDefTy::Struct { fields } => substitute_fields(fields, &generics, args),
+        IdlTypeDefTy::Enum { variants } => variants
+            .iter_mut()
+            .for_each(|v| substitute_fields(&mut v.fields, &generics, args)),
+    }
+    Ok(type_def)
+}
+
+// Reads the value of a constant, a literal such as `b"seed"` or `10_000`, as
+// evaluated when generating the IDL. Integers are read as decimal strings, so
+// that they're encoded without loss.
+fn constant_value(constant: &IdlConst) -> Result<Value, ClientError> {
+    fn to_json(value: ConstValue) -> Value {
+        match value {
+            ConstValue::Bool(b) => Value::Bool(b),
+            ConstValue::Int(n) => Value::String(n.to_string()),
+            ConstValue::BigInt(n) => Value::String(n.to_string()),
+            ConstValue::Float(f) => Value::from(f),
+            ConstValue::Str(s) => Value::String(s),
+            ConstValue::Char(c) => Value::String(c.to_string()),
+            ConstValue::Bytes(bytes) => Value::from(bytes),
+            ConstValue::Array(values) | ConstValue::Tuple(values) => {
+                values.into_iter().map(to_json).collect()
+            }
+            ConstValue::Some(value) => to_json(*value),
+            ConstValue::None => Value::Null,
+        }
+    }
+    ConstValue::from_idl(&constant.value)
+        .map(to_json)
+        .ok_or_else(|| error(format!("Unable to evaluate constant: {}", constant.value)))
+}
+
+fn unsigned<T: TryFrom<u128>>(value: &Value) -> Result<T, ClientError> {
+    let n = match value {
+        Value::Number(n) => n.as_u64().map(u128::from),
+        Value::String(s) => s.parse::<u128>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| error(format!("Expected an unsigned integer, found {}", value)))?;
+    T::try_from(n).map_err(|_| error(format!("Integer out of range: {}", value)))
+}
+
+fn signed<T: TryFrom<i128>>(value: &Value) -> Result<T, ClientError> {
+    let n = match value {
+        Value::Number(n) => n.as_i64().map(i128::from),
+        Value::String(s) => s.parse::<i128>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| error(format!("Expected an integer, found {}", value)))?;
+    T::try_from(n).map_err(|_| error(format!("Integer out of range: {}", value)))
+}
+
+fn float(value: &Value) -> Result<f64, ClientError> {
+    match value {
+        Value::Null => Some(f64::NAN),
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| error(format!("Expected a float, found {}", value)))
+}
+
+// Encodes a decimal 256 bit integer as 32 little endian bytes, in two's
+// complement if `signed`.
+fn int256(value: &Value, signed: bool) -> Result<[u8; 32], ClientError> {
+    let s = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return Err(error(format!("Expected an integer, found {}", value))),
+    };
+    let invalid = || error(format!("Invalid 256 bit integer: {}", value));
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) if signed => (true, digits),
+        _ => (false, s.as_str()),
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    // Little endian 64 bit limbs.
+    let mut limbs = [0u64; 4];
+    for c in digits.chars() {
+        let mut carry = c.to_digit(10).ok_or_else(invalid)? as u128;
+        for limb in limbs.iter_mut() {
+            let x = *limb as u128 * 10 + carry;
+            *limb = x as u64;
+            carry = x >> 64;
+        }
+        if carry != 0 {
+            return Err(invalid());
+        }
+    }
+    if signed {
+        // The magnitude can be at most 2^255 when negative, else 2^255 - 1.
+        let top = limbs[3] >> 63 == 1;
+        let min = limbs[3] == 1 << 63 && limbs[..3].iter().all(|l| *l == 0);
+        if top && !(negative && min) {
+            return Err(invalid());
+        }
+        if negative {
+            negate256(&mut limbs);
+        }
+    }
+    let mut bytes = [0u8; 32];
+    for (chunk, limb) in bytes.chunks_mut(8).zip(limbs.iter()) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+fn int256_to_string(bytes: [u8; 32], signed: bool) -> String {
+    let mut limbs = [0u64; 4];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks(8)) {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(chunk);
+        *limb = u64::from_le_bytes(b);
+    }
+    let negative = signed && limbs[3] >> 63 == 1;
+    if negative {
+        negate256(&mut limbs);
+    }
+    let mut digits = vec![];
+    loop {
+        let mut rem = 0u128;
+        for limb in limbs.iter_mut().rev() {
+            let x = rem << 64 | *limb as u128;
+            *limb = (x / 10) as u64;
+            rem = x % 10;
+        }
+        digits.push(std::char::from_digit(rem as u32, 10).unwrap());
+        if limbs.iter().all(|l| *l == 0) {
+            break;
+        }
+    }
+    if negative {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+// Two's complement negation.
+fn negate256(limbs: &mut [u64; 4]) {
+    let mut carry = true;
+    for limb in limbs.iter_mut() {
+        let (x, overflow) = (!*limb).overflowing_add(carry as u64);
+        *limb = x;
+        carry = overflow;
+    }
+}
+
+fn pubkey(value: &Value) -> Result<Pubkey, ClientError> {
+    value
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| error(format!("Expected a base58 public key, found {}", value)))
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, ClientError> {
+    value
+        .as_array()
+        .ok_or_else(|| error(format!("Expected an array, found {}", value)))
+}
+
+// Reads the next fixed size byte array, e.g., `[u8; 8]`.
+fn take<T: Default + AsMut<[u8]>>(data: &mut &[u8]) -> Result<T, ClientError> {
+    let mut bytes = T::default();
+    let len = bytes.as_mut().len();
+    bytes.as_mut().copy_from_slice(take_slice(data, len)?);
+    Ok(bytes)
+}
+
+fn take_slice<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], ClientError> {
+    if data.len() < len {
+        return Err(error("Unexpected end of data".to_string()));
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Ok(bytes)
+}
+
+fn error(msg: String) -> ClientError {
+    ClientError::IdlError(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::*;
+    use serde_json::json;
+
+    // Mirrors of the types in `examples/`, used to check the coder against
+    // the IDLs parsed from the examples' source.
+
+    #[account]
+    struct Multisig {
+        owners: Vec<Pubkey>,
+        threshold: u64,
+        nonce: u8,
+    }
+
+    #[account]
+    struct Transaction {
+        multisig: Pubkey,
+        program_id: Pubkey,
+        accounts: Vec<TransactionAccount>,
+        data: Vec<u8>,
+        signers: Vec<bool>,
+        did_execute: bool,
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+    struct TransactionAccount {
+        pubkey: Pubkey,
+        is_signer: bool,
+        is_writable: bool,
+    }
+
+    #[account]
+    struct Data {
+        udata: u128,
+        idata: i128,
+    }
+
+    #[event]
+    struct MyEvent {
+        data: u64,
+        label: String,
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    enum Side {
+        Bid,
+        Ask,
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Pair<T: AnchorSerialize + AnchorDeserialize> {
+        first: T,
+        second: Wrapper<Option<T>>,
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Wrapper<T: AnchorSerialize + AnchorDeserialize>(T, u8);
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Unit;
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Generics {
+        pair: Pair<u16>,
+        unit: Option<Unit>,
+    }
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct RichTypes {
+        float: f32,
+        double: f64,
+        tuple: (u8, String),
+        btree_map: std::collections::BTreeMap<u16, bool>,
+        hash_map: std::collections::HashMap<u8, i8>,
+        boxed: Box<u64>,
+        byte_array: [u8; 3],
+        options: [Option<u8>; 2],
+    }
+
+    #[account(zero_copy)]
+    struct Foo {
+        authority: Pubkey,
+        data: u64,
+        second_data: u64,
+        second_authority: [u8; 32],
+    }
+
+    fn example_coder(path: &str) -> Coder {
+        let path = format!("{}/../examples/{}", env!("CARGO_MANIFEST_DIR"), path);
+        Coder::new(anchor_syn::parser::file::parse(path).unwrap())
+    }
+
+    // A coder for a single struct, `T`, with fields given as Rust types.
+    fn type_coder(fields: &[(&str, &str)]) -> Coder {
+        let fields: Vec<Value> = fields
+            .iter()
+            .map(|(name, ty)| json!({ "name": name, "type": ty.parse::<IdlType>().unwrap() }))
+            .collect();
+        Coder::new(
+            serde_json::from_value(json!({
+                "version": "0.0.0",
+                "spec": anchor_syn::idl::IDL_SPEC,
+                "name": "test",
+                "instructions": [],
+                "types": [{ "name": "T", "type": { "kind": "struct", "fields": fields } }],
+            }))
+            .unwrap(),
+        )
+    }
+
+    // A coder for the IDL parsed from the given program source.
+    fn source_coder(name: &str, source: &str) -> Coder {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("anchor-client-{}", name))
+            .tempdir()
+            .unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, source).unwrap();
+        Coder::new(anchor_syn::parser::file::parse(path).unwrap())
+    }
+
+    fn account_data(account: impl AccountSerialize) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn ix_data(name: &str, args: impl AnchorSerialize) -> Vec<u8> {
+        let mut data = discriminator(SIGHASH_GLOBAL_NAMESPACE, name).to_vec();
+        data.append(&mut args.try_to_vec().unwrap());
+        data
+    }
+
+    #[test]
+    fn multisig_accounts() {
+        let coder = example_coder("multisig/programs/multisig/src/lib.rs");
+        let owner = Pubkey::new_unique();
+        let data = account_data(Multisig {
+            owners: vec![owner],
+            threshold: 1,
+            nonce: 255,
+        });
+        let value = json!({
+            "owners": [owner.to_string()],
+            "threshold": 1,
+            "nonce": 255,
+        });
+        assert_eq!(coder.decode_account("Multisig", &data).unwrap(), value);
+        assert_eq!(coder.encode_account("Multisig", &value).unwrap(), data);
+
+        let (multisig, program_id, account) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let data = account_data(Transaction {
+            multisig,
+            program_id,
+            accounts: vec![TransactionAccount {
+                pubkey: account,
+                is_signer: false,
+                is_writable: true,
+            }],
+            data: vec![1, 2, 3],
+            signers: vec![true, false],
+            did_execute: false,
+        });
+        let value = json!({
+            "multisig": multisig.to_string(),
+            "programId": program_id.to_string(),
+            "accounts": [{
+                "pubkey": account.to_string(),
+                "isSigner": false,
+                "isWritable": true,
+            }],
+            "data": [1, 2, 3],
+            "signers": [true, false],
+            "didExecute": false,
+        });
+        assert_eq!(coder.decode_account("Transaction", &data).unwrap(), value);
+        assert_eq!(coder.encode_account("Transaction", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn multisig_instruction() {
+        let coder = example_coder("multisig/programs/multisig/src/lib.rs");
+        let (pid, account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = ix_data(
+            "create_transaction",
+            (
+                pid,
+                vec![TransactionAccount {
+                    pubkey: account,
+                    is_signer: true,
+                    is_writable: false,
+                }],
+                vec![7u8],
+            ),
+        );
+        let value = json!({
+            "pid": pid.to_string(),
+            "accs": [{
+                "pubkey": account.to_string(),
+                "isSigner": true,
+                "isWritable": false,
+            }],
+            "data": [7],
+        });
+        assert_eq!(
+            coder
+                .encode_instruction("createTransaction", &value)
+                .unwrap(),
+            data
+        );
+        assert_eq!(
+            coder.decode_instruction(&data).unwrap(),
+            ("createTransaction".to_string(), value)
+        );
+    }
+
+    #[test]
+    fn misc_wide_integers() {
+        let coder = example_coder("misc/programs/misc/src/lib.rs");
+        let data = account_data(Data {
+            udata: u128::MAX,
+            idata: i128::MIN,
+        });
+        let value = json!({
+            "udata": u128::MAX.to_string(),
+            "idata": i128::MIN.to_string(),
+        });
+        assert_eq!(coder.decode_account("Data", &data).unwrap(), value);
+        assert_eq!(coder.encode_account("Data", &value).unwrap(), data);
+
+        // Small 128 bit integers can be given as numbers, too.
+        let data = ix_data("initialize", (1234u128, -5i128));
+        let value = json!({ "udata": 1234, "idata": -5 });
+        assert_eq!(
+            coder.encode_instruction("initialize", &value).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn events() {
+        let coder = example_coder("events/programs/events/src/lib.rs");
+        let data = anchor_lang::Event::data(&MyEvent {
+            data: 5,
+            label: "hello".to_string(),
+        });
+        let value = json!({ "data": 5, "label": "hello" });
+        assert_eq!(
+            coder.decode_event(&data).unwrap(),
+            ("MyEvent".to_string(), value.clone())
+        );
+        assert_eq!(coder.encode_event("MyEvent", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn enums() {
+        let coder = example_coder("swap/programs/swap/src/lib.rs");
+        let data = Side::Ask.try_to_vec().unwrap();
+        assert_eq!(coder.decode_type("Side", &data).unwrap(), json!("Ask"));
+        assert_eq!(coder.encode_type("Side", &json!("Ask")).unwrap(), data);
+
+        let data = ix_data("swap", (Side::Bid, 10u64, 9u64));
+        let value = json!({ "side": "Bid", "amount": 10, "minExpectedSwapAmount": 9 });
+        assert_eq!(coder.encode_instruction("swap", &value).unwrap(), data);
+        assert_eq!(
+            coder.decode_instruction(&data).unwrap(),
+            ("swap".to_string(), value)
+        );
+    }
+
+    #[test]
+    fn types_from_submodules() {
+        // Defined in `src/pc.rs`, not `src/lib.rs`.
+        let coder = example_coder("pyth/programs/pyth/src/lib.rs");
+        let data = (5i64, 1u64, 1u8, 0u8, 9u64).try_to_vec().unwrap();
+        let value = json!({
+            "price": 5,
+            "conf": 1,
+            "status": "Trading",
+            "corpAct": "NoCorpAct",
+            "pubSlot": 9,
+        });
+        assert_eq!(coder.decode_type("PriceInfo", &data).unwrap(), value);
+        assert_eq!(coder.encode_type("PriceInfo", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn account_discriminator_mismatch() {
+        let coder = example_coder("multisig/programs/multisig/src/lib.rs");
+        let data = account_data(Multisig {
+            owners: vec![],
+            threshold: 1,
+            nonce: 0,
+        });
+        assert!(coder.decode_account("Transaction", &data).is_err());
+    }
+
+    #[test]
+    fn rich_types() {
+        let coder = type_coder(&[
+            ("float", "f32"),
+            ("double", "f64"),
+            ("tuple", "(u8, String)"),
+            ("btreeMap", "BTreeMap<u16, bool>"),
+            ("hashMap", "std::collections::HashMap<u8, i8>"),
+            ("boxed", "Box<u64>"),
+            ("byteArray", "[u8; 3]"),
+            ("options", "[Option<u8>; 2]"),
+        ]);
+        let data = RichTypes {
+            float: 1.5,
+            double: -0.25,
+            tuple: (7, "seven".to_string()),
+            btree_map: vec![(1, true), (2, false)].into_iter().collect(),
+            hash_map: vec![(3, -3)].into_iter().collect(),
+            boxed: Box::new(9),
+            byte_array: [1, 2, 3],
+            options: [None, Some(4)],
+        }
+        .try_to_vec()
+        .unwrap();
+        let value = json!({
+            "float": 1.5,
+            "double": -0.25,
+            "tuple": [7, "seven"],
+            "btreeMap": [[1, true], [2, false]],
+            "hashMap": [[3, -3]],
+            "boxed": 9,
+            "byteArray": [1, 2, 3],
+            "options": [null, 4],
+        });
+        assert_eq!(coder.decode_type("T", &data).unwrap(), value);
+        assert_eq!(coder.encode_type("T", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn int256() {
+        let coder = type_coder(&[("u", "u256"), ("i", "i256")]);
+        let u_max =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        let i_min =
+            "-57896044618658097711785492504343953926634992332820282019728792003956564819968";
+        let value = json!({ "u": u_max, "i": i_min });
+        let mut data = vec![0xff; 32];
+        data.extend_from_slice(&[0; 31]);
+        data.push(0x80);
+        assert_eq!(coder.encode_type("T", &value).unwrap(), data);
+        assert_eq!(coder.decode_type("T", &data).unwrap(), value);
+
+        let value = json!({ "u": 258, "i": "-2" });
+        let mut data = vec![2, 1];
+        data.extend_from_slice(&[0; 30]);
+        data.push(0xfe);
+        data.extend_from_slice(&[0xff; 31]);
+        assert_eq!(coder.encode_type("T", &value).unwrap(), data);
+        assert_eq!(
+            coder.decode_type("T", &data).unwrap(),
+            json!({ "u": "258", "i": "-2" })
+        );
+
+        let i_max = "57896044618658097711785492504343953926634992332820282019728792003956564819967";
+        assert!(coder
+            .encode_type("T", &json!({ "u": "0", "i": i_max }))
+            .is_ok());
+        // Out of range.
+        let i_over =
+            "57896044618658097711785492504343953926634992332820282019728792003956564819968";
+        let u_over =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(coder
+            .encode_type("T", &json!({ "u": "0", "i": i_over }))
+            .is_err());
+        assert!(coder
+            .encode_type("T", &json!({ "u": u_over, "i": "0" }))
+            .is_err());
+        assert!(coder
+            .encode_type("T", &json!({ "u": "-1", "i": "0" }))
+            .is_err());
+    }
+
+    #[test]
+    fn generic_tuple_and_unit_structs() {
+        let coder = source_coder(
+            "generics",
+            r#"
+            #[program]
+            pub mod generics {
+                use super::*;
+            }
+
+            pub struct Pair<T> {
+                pub first: T,
+                pub second: Wrapper<Option<T>>,
+            }
+
+            pub struct Wrapper<T>(T, u8);
+
+            pub struct Unit;
+
+            pub struct Generics {
+                pub pair: Pair<u16>,
+                pub unit: Option<Unit>,
+            }
+            "#,
+        );
+        let data = Generics {
+            pair: Pair {
+                first: 1,
+                second: Wrapper(Some(2), 3),
+            },
+            unit: Some(Unit),
+        }
+        .try_to_vec()
+        .unwrap();
+        let value = json!({
+            "pair": { "first": 1, "second": [2, 3] },
+            "unit": {},
+        });
+        assert_eq!(coder.decode_type("Generics", &data).unwrap(), value);
+        assert_eq!(coder.encode_type("Generics", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn zero_copy_packed() {
+        let coder = example_coder("zero-copy/programs/zero-copy/src/lib.rs");
+        let authority = Pubkey::new_unique();
+        let foo = Foo {
+            authority,
+            data: 1,
+            second_data: 2,
+            second_authority: [3; 32],
+        };
+        let mut data = discriminator("account", "Foo").to_vec();
+        data.extend_from_slice(anchor_lang::__private::bytemuck::bytes_of(&foo));
+        let value = json!({
+            "authority": authority.to_string(),
+            "data": 1,
+            "secondData": 2,
+            "secondAuthority": vec![3; 32],
+        });
+        assert_eq!(coder.decode_account("Foo", &data).unwrap(), value);
+        assert_eq!(coder.encode_account("Foo", &value).unwrap(), data);
+    }
+
+    #[test]
+    fn zero_copy_repr_c() {
+        let field = |name: &str, ty: &str| json!({ "name": name, "type": ty });
+        let mut idl: Idl = serde_json::from_value(json!({
+            "version": "0.0.0",
+            "spec": anchor_syn::idl::IDL_SPEC,
+            "name": "repr_c",
+            "instructions": [],
+            "types": [
+                {
+                    "name": "Inner",
+                    "type": {
+                        "kind": "struct",
+                        "fields": [field("a", "u8"), field("b", "u64"), field("c", "u16")],
+                    },
+                    "serialization": "bytemuck",
+                    "repr": "c",
+                },
+                {
+                    "name": "Outer",
+                    "type": {
+                        "kind": "struct",
+                        "fields": [
+                            field("flag", "bool"),
+                            json!({ "name": "inner", "type": { "defined": "Inner" } }),
+                            field("wide", "u128"),
+                        ],
+                    },
+                    "serialization": "bytemuck",
+                    "repr": "c",
+                },
+                {
+                    "name": "Dynamic",
+                    "type": { "kind": "struct", "fields": [field("data", "bytes")] },
+                    "serialization": "bytemuck",
+                    "repr": "c",
+                },
+            ],
+        }))
+        .unwrap();
+        anchor_syn::idl::set_layouts(&mut idl);
+        let coder = Coder::new(idl);
+        let mut data = vec![0; 48];
+        data[0] = 1;
+        data[8] = 2;
+        data[16..24].copy_from_slice(&3u64.to_le_bytes());
+        data[24..26].copy_from_slice(&4u16.to_le_bytes());
+        data[32..48].copy_from_slice(&5u128.to_le_bytes());
+        let value = json!({
+            "flag": true,
+            "inner": { "a": 2, "b": 3, "c": 4 },
+            "wide": "5",
+        });
+        assert_eq!(coder.decode_type("Outer", &data).unwrap(), value);
+        assert_eq!(coder.encode_type("Outer", &value).unwrap(), data);
+        // Zero copy types without a layout aren't Borsh serialized either.
+        assert!(coder.decode_type("Dynamic", &[0; 4]).is_err());
+        assert!(coder
+            .encode_type("Dynamic", &json!({ "data": [] }))
+            .is_err());
+    }
+
+    #[test]
+    fn constants() {
+        let coder = source_coder(
+            "constants",
+            r#"
+            #[constant]
+            pub const VAULT_SEED: &[u8] = b"vault";
+
+            #[constant]
+            pub const NAME: &str = "vault\n";
+
+            pub const NOT_EXPORTED: u8 = 1;
+
+            #[program]
+            pub mod constants {
+                use super::*;
+
+                #[constant]
+                pub const FEE_BPS: u16 = 30;
+
+                #[constant]
+                pub const MAX_SUPPLY: u128 = u128::MAX - 1;
+
+                #[constant]
+                pub const OFFSET: i64 = -(1_000);
+
+                #[constant]
+                pub const RATE: f64 = -0.5;
+
+                #[constant]
+                pub const LIMITS: (u8, [u16; 2]) = (1, [2, 3]);
+
+                #[constant]
+                pub const DERIVED: u64 = FEE_BPS as u64 * 60 * 60;
+
+                #[constant]
+                pub const QUOTE: Option<u8> = Some(4);
+
+                #[constant]
+                pub const UNKNOWN: u64 = compute();
+            }
+            "#,
+        );
+        assert_eq!(coder.constant("VAULT_SEED").unwrap(), json!(b"vault"));
+        assert_eq!(coder.constant("NAME").unwrap(), json!("vault\n"));
+        assert_eq!(coder.constant("FEE_BPS").unwrap(), json!(30));
+        assert_eq!(
+            coder.constant("MAX_SUPPLY").unwrap(),
+            json!((u128::MAX - 1).to_string())
+        );
+        assert_eq!(coder.constant("OFFSET").unwrap(), json!(-1000));
+        assert_eq!(coder.constant("RATE").unwrap(), json!(-0.5));
+        assert_eq!(coder.constant("LIMITS").unwrap(), json!([1, [2, 3]]));
+        assert_eq!(coder.constant("DERIVED").unwrap(), json!(108000));
+        assert_eq!(coder.constant("QUOTE").unwrap(), json!(4));
+        assert_eq!(
+            coder.encode_constant("VAULT_SEED").unwrap(),
+            b"vault".to_vec().try_to_vec().unwrap()
+        );
+        // Expressions that can't be evaluated are left as written.
+        assert!(coder.constant("UNKNOWN").is_err());
+        assert!(coder.constant("NOT_EXPORTED").is_err());
+    }
+
+    #[test]
+    fn instruction_returns() {
+        let coder = source_coder(
+            "returns",
+            r#"
+            #[program(return_data)]
+            pub mod returns {
+                use super::*;
+
+                pub fn nothing(ctx: Context<Empty>) -> ProgramResult {
+                    Ok(())
+                }
+
+                pub fn unit(ctx: Context<Empty>) -> Result<()> {
+                    Ok(())
+                }
+
+                pub fn amount(ctx: Context<Empty>) -> Result<u64> {
+                    Ok(1)
+                }
+
+                pub fn quote(ctx: Context<Empty>) -> Result<Option<crate::Quote>, ProgramError> {
+                    Ok(None)
+                }
+            }
+
+            #[derive(Accounts)]
+            pub struct Empty {}
+
+            #[derive(AnchorSerialize, AnchorDeserialize)]
+            pub struct Quote {
+                pub price: u64,
+                pub side: u8,
+            }
+            "#,
+        );
+        assert_eq!(
+            coder
+                .decode_return("amount", &7u64.try_to_vec().unwrap())
+                .unwrap(),
+            json!(7)
+        );
+        assert_eq!(
+            coder
+                .decode_return("quote", &Some((10u64, 1u8)).try_to_vec().unwrap())
+                .unwrap(),
+            json!({"price": 10, "side": 1})
+        );
+        assert_eq!(coder.decode_return("quote", &[0]).unwrap(), json!(null));
+        assert!(coder.decode_return("nothing", &[]).is_err());
+    }
+}
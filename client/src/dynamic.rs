@@ -0,0 +1,457 @@
+//! `dynamic` provides a client for programs that aren't known at compile
+//! time. Rather than using generated types, instructions, accounts and events
+//! are described by the program's IDL, e.g., as fetched with `anchor idl
+//! fetch`, and are represented as `serde_json::Value`s. See the `coder`
+//! module docs for how values map to JSON.
+
+use crate::coder::{self, Coder};
+use crate::event::{self, EventParser};
+use crate::ClientError;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use anchor_syn::idl::{Idl, IdlAccountItem, IdlIx, IdlPda, IdlSeed};
+use serde_json::{Map, Value};
+use solana_client::rpc_client::RpcClient;
+use std::sync::Arc;
+
+/// Program is a client handle to a program described by an IDL.
+pub struct Program {
+    coder: Arc<Coder>,
+    program_id: Pubkey,
+}
+
+/// An event emitted by a dynamic program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicEvent {
+    pub name: String,
+    pub data: Value,
+}
+
+impl Program {
+    pub fn new(idl: Idl, program_id: Pubkey) -> Self {
+        Self {
+            coder: Arc::new(Coder::new(idl)),
+            program_id,
+        }
+    }
+
+    /// Creates a program from an IDL with the program address in its
+    /// metadata, as written to `target/idl` by the CLI. IDLs fetched from
+    /// chain don't have this metadata, so use `Program::new` instead.
+    pub fn from_idl(idl: Idl) -> Result<Self, ClientError> {
+        let program_id = idl
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get("address"))
+            .and_then(|a| a.as_str())
+            .ok_or_else(|| ClientError::IdlError("Program address not found".to_string()))?
+            .parse()
+            .map_err(|_| ClientError::IdlError("Invalid program address".to_string()))?;
+        Ok(Self::new(idl, program_id))
+    }
+
+    pub fn id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    pub fn idl(&self) -> &Idl {
+        self.coder.idl()
+    }
+
+    pub fn coder(&self) -> &Coder {
+        &self.coder
+    }
+
+    /// Builds the instruction with the given IDL name.
+    ///
+    /// `accounts` is an object mapping every account name of the instruction
+    /// to a base58 address, with composite accounts given as nested objects.
+    /// Program derived addresses whose seeds are constants or other accounts
+    /// of the same object, e.g., `associated` accounts, can be omitted.
+    /// `args` is an object mapping every argument name to its value.
+    pub fn instruction(
+        &self,
+        name: &str,
+        accounts: &Value,
+        args: &Value,
+    ) -> Result<Instruction, ClientError> {
+        let ix = find_ix(&self.idl().instructions, name)?;
+        let mut metas = Vec::new();
+        account_metas(&self.program_id, &ix.accounts, accounts, &mut metas)?;
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: metas,
+            data: self.coder.encode_instruction(name, args)?,
+        })
+    }
+
+    /// Builds the `#[state]` method with the given IDL name. The program
+    /// state account is prepended to `accounts`.
+    pub fn state_instruction(
+        &self,
+        name: &str,
+        accounts: &Value,
+        args: &Value,
+    ) -> Result<Instruction, ClientError> {
+        let methods = self
+            .idl()
+            .state
+            .as_ref()
+            .map(|s| &s.methods[..])
+            .unwrap_or(&[]);
+        let ix = find_ix(methods, name)?;
+        let mut metas = vec![AccountMeta::new(
+            anchor_lang::__private::state::address(&self.program_id),
+            false,
+        )];
+        account_metas(&self.program_id, &ix.accounts, accounts, &mut metas)?;
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: metas,
+            data: self.coder.encode_state_instruction(name, args)?,
+        })
+    }
+
+    /// Decodes the account with the given IDL name from its data, including
+    /// the discriminator.
+    pub fn decode_account(&self, name: &str, data: &[u8]) -> Result<Value, ClientError> {
+        self.coder.decode_account(name, data)
+    }
+
+    /// Fetches and decodes the account with the given IDL name.
+    pub fn account(
+        &self,
+        rpc: &RpcClient,
+        name: &str,
+        address: &Pubkey,
+    ) -> Result<Value, ClientError> {
+        let account = rpc
+            .get_account_with_commitment(address, rpc.commitment())?
+            .value
+            .ok_or(ClientError::AccountNotFound)?;
+        self.decode_account(name, &account.data)
+    }
+
+    /// Returns the value of the `#[constant]` with the given IDL name, as the
+    /// Rust type it's Borsh compatible with, e.g., `Vec<u8>` for `&[u8]`
+    /// constants.
+    pub fn constant<T: AnchorDeserialize>(&self, name: &str) -> Result<T, ClientError> {
+        let data = self.coder.encode_constant(name)?;
+        T::try_from_slice(&data).map_err(|e| {
+            ClientError::IdlError(format!("Unable to decode constant {}: {}", name, e))
+        })
+    }
+
+    /// Decodes the value returned by the last instruction with the given IDL
+    /// name to the program, from the logs of a transaction, e.g., as returned
+    /// by `RequestBuilder::simulate`, or errors with
+    /// `ClientError::ReturnDataNotFound` if it didn't return anything.
+    pub fn decode_return(&self, name: &str, logs: &[String]) -> Result<Value, ClientError> {
+        let data = event::parse_return_data(&self.program_id, logs)?
+            .ok_or(ClientError::ReturnDataNotFound)?;
+        self.coder.decode_return(name, &data)
+    }
+
+    /// Returns a parser for all events in the IDL, which can be used with
+    /// `anchor_client::Program::on_events` and friends.
+    pub fn event_parser(&self) -> EventParser<DynamicEvent> {
+        let events = self.idl().events.clone().unwrap_or_default();
+        events
+            .into_iter()
+            .fold(EventParser::new(self.program_id), |parser, event| {
+                let coder = self.coder.clone();
+                let discriminator = coder::discriminator("event", &event.name);
+                parser.event_with_discriminator(discriminator, move |data: &[u8]| {
+                    Ok(DynamicEvent {
+                        name: event.name.clone(),
+                        data: coder.decode_event_data(&event.name, data)?,
+                    })
+                })
+            })
+    }
+}
+
+fn find_ix<'a>(ixs: &'a [IdlIx], name: &str) -> Result<&'a IdlIx, ClientError> {
+    ixs.iter()
+        .find(|ix| ix.name == name)
+        .ok_or_else(|| ClientError::IdlError(format!("Instruction not found: {}", name)))
+}
+
+// Flattens the IDL accounts, in order, into account metas.
+fn account_metas(
+    program_id: &Pubkey,
+    items: &[IdlAccountItem],
+    accounts: &Value,
+    metas: &mut Vec<AccountMeta>,
+) -> Result<(), ClientError> {
+    let accounts: &Map<String, Value> = accounts
+        .as_object()
+        .ok_or_else(|| ClientError::IdlError(format!("Expected an object, found {}", accounts)))?;
+    for item in items {
+        match item {
+            IdlAccountItem::IdlAccount(acc) => {
+                let pubkey = match (accounts.get(&acc.name), &acc.pda) {
+                    (None, Some(pda)) => {
+                        pda_address(program_id, pda, accounts).ok_or_else(|| {
+                            ClientError::IdlError(format!("Missing account: {}", acc.name))
+                        })?
+                    }
+                    (account, _) => account_address(account, &acc.name)?,
+                };
+                metas.push(match acc.is_mut {
+                    false => AccountMeta::new_readonly(pubkey, acc.is_signer),
+                    true => AccountMeta::new(pubkey, acc.is_signer),
+                });
+            }
+            IdlAccountItem::IdlAccounts(accs) => {
+                let nested = accounts.get(&accs.name).ok_or_else(|| {
+                    ClientError::IdlError(format!("Missing accounts: {}", accs.name))
+                })?;
+                account_metas(program_id, &accs.accounts, nested, metas)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn account_address(account: Option<&Value>, name: &str) -> Result<Pubkey, ClientError> {
+    account
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ClientError::IdlError(format!("Missing account: {}", name)))?
+        .parse()
+        .map_err(|_| ClientError::IdlError(format!("Invalid account: {}", name)))
+}
+
+// Derives the address, if all seeds are known, i.e., none are expressions
+// and the accounts they refer to are given.
+fn pda_address(program_id: &Pubkey, pda: &IdlPda, accounts: &Map<String, Value>) -> Option<Pubkey> {
+    let seeds = pda
+        .seeds
+        .iter()
+        .map(|seed| match seed {
+            IdlSeed::Const { value } => Some(value.clone()),
+            IdlSeed::Account { path } => account_address(accounts.get(path), path)
+                .ok()
+                .map(|pubkey| pubkey.to_bytes().to_vec()),
+            IdlSeed::Expr { .. } => None,
+        })
+        .collect::<Option<Vec<Vec<u8>>>>()?;
+    let seeds: Vec<&[u8]> = seeds.iter().map(|seed| &seed[..]).collect();
+    match pda.find_bump {
+        true => Some(Pubkey::find_program_address(&seeds, program_id).0),
+        false => Pubkey::create_program_address(&seeds, program_id).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn example(path: &str) -> Program {
+        let path = format!("{}/../examples/{}", env!("CARGO_MANIFEST_DIR"), path);
+        Program::new(
+            anchor_syn::parser::file::parse(path).unwrap(),
+            Pubkey::new_unique(),
+        )
+    }
+
+    fn misc() -> Program {
+        example("misc/programs/misc/src/lib.rs")
+    }
+
+    fn composite() -> Program {
+        example("composite/programs/composite/src/lib.rs")
+    }
+
+    #[test]
+    fn composite_instruction() {
+        let program = composite();
+        let (dummy_a, dummy_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = json!({
+            "foo": { "dummyA": dummy_a.to_string() },
+            "bar": { "dummyB": dummy_b.to_string() },
+        });
+        let args = json!({ "dummyA": 1, "dummyB": "2" });
+        let ix = program
+            .instruction("compositeUpdate", &accounts, &args)
+            .unwrap();
+
+        assert_eq!(ix.program_id, program.id());
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new(dummy_a, false),
+                AccountMeta::new(dummy_b, false),
+            ]
+        );
+        let mut data = coder::discriminator("global", "composite_update").to_vec();
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes());
+        assert_eq!(ix.data, data);
+    }
+
+    #[test]
+    fn instruction_errors() {
+        let program = composite();
+        let accounts = json!({
+            "foo": { "dummyA": Pubkey::new_unique().to_string() },
+            "bar": { "dummyB": Pubkey::new_unique().to_string() },
+        });
+        let args = json!({ "dummyA": 1, "dummyB": 2 });
+        assert!(program
+            .instruction("compositeUpdate", &accounts, &args)
+            .is_ok());
+
+        // Unknown instruction.
+        assert!(program.instruction("update", &accounts, &args).is_err());
+        // Missing nested account.
+        let missing = json!({ "foo": {}, "bar": accounts["bar"] });
+        assert!(program
+            .instruction("compositeUpdate", &missing, &args)
+            .is_err());
+        // Missing and out of range arguments.
+        assert!(program
+            .instruction("compositeUpdate", &accounts, &json!({ "dummyA": 1 }))
+            .is_err());
+        let args = json!({ "dummyA": -1, "dummyB": 2 });
+        assert!(program
+            .instruction("compositeUpdate", &accounts, &args)
+            .is_err());
+    }
+
+    #[test]
+    fn state_instruction() {
+        let program = example("interface/programs/counter/src/lib.rs");
+        let auth_program = Pubkey::new_unique();
+        let accounts = json!({ "authProgram": auth_program.to_string() });
+        let ix = program
+            .state_instruction("setCount", &accounts, &json!({ "newCount": 3 }))
+            .unwrap();
+
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new(anchor_lang::__private::state::address(&program.id()), false),
+                AccountMeta::new_readonly(auth_program, false),
+            ]
+        );
+        let mut data = coder::discriminator("state", "set_count").to_vec();
+        data.extend_from_slice(&3u64.to_le_bytes());
+        assert_eq!(ix.data, data);
+
+        // State methods aren't global instructions.
+        assert!(program
+            .instruction("setCount", &accounts, &json!({ "newCount": 3 }))
+            .is_err());
+    }
+
+    #[test]
+    fn decode_accounts() {
+        let program = composite();
+        let mut data = coder::discriminator("account", "DummyA").to_vec();
+        data.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(
+            program.decode_account("DummyA", &data).unwrap(),
+            json!({ "data": 7 })
+        );
+        // Discriminator mismatch.
+        assert!(program.decode_account("DummyB", &data).is_err());
+        // Truncated data.
+        assert!(program.decode_account("DummyA", &data[..12]).is_err());
+    }
+
+    #[test]
+    fn program_id_from_metadata() {
+        let program_id = Pubkey::new_unique();
+        let mut idl = composite().idl().clone();
+        assert!(Program::from_idl(idl.clone()).is_err());
+
+        idl.metadata = Some(json!({ "address": program_id.to_string() }));
+        assert_eq!(Program::from_idl(idl).unwrap().id(), program_id);
+    }
+
+    #[test]
+    fn associated_accounts() {
+        let program = misc();
+        let (authority, state, data) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let accounts = json!({
+            "authority": authority.to_string(),
+            "state": state.to_string(),
+            "data": data.to_string(),
+        });
+        let ix = program
+            .instruction("testAssociatedAccount", &accounts, &json!({ "data": 1 }))
+            .unwrap();
+        let (address, _) = Pubkey::find_program_address(
+            &[b"anchor", authority.as_ref(), state.as_ref(), data.as_ref()],
+            &program.id(),
+        );
+        assert_eq!(ix.accounts[0], AccountMeta::new(address, false));
+
+        // Seeds must be given.
+        let accounts = json!({ "authority": authority.to_string() });
+        assert!(program
+            .instruction("testAssociatedAccount", &accounts, &json!({ "data": 1 }))
+            .is_err());
+    }
+
+    #[test]
+    fn typed_constants() {
+        let idl = serde_json::from_value(json!({
+            "version": "0.0.0",
+            "spec": anchor_syn::idl::IDL_SPEC,
+            "name": "test",
+            "instructions": [],
+            "constants": [
+                { "name": "SEED", "type": "bytes", "value": "b\"seed\"" },
+                { "name": "LIMITS", "type": { "array": ["u16", 2] }, "value": "[1, 2]" },
+            ],
+        }))
+        .unwrap();
+        let program = Program::new(idl, Pubkey::new_unique());
+        assert_eq!(program.constant::<Vec<u8>>("SEED").unwrap(), b"seed");
+        assert_eq!(program.constant::<[u16; 2]>("LIMITS").unwrap(), [1, 2]);
+        // The type must be Borsh compatible with the constant's.
+        assert!(program.constant::<u8>("SEED").is_err());
+    }
+
+    #[test]
+    fn decode_return() {
+        let idl = serde_json::from_value(json!({
+            "version": "0.0.0",
+            "spec": anchor_syn::idl::IDL_SPEC,
+            "name": "test",
+            "instructions": [
+                { "name": "amount", "accounts": [], "args": [], "returns": "u64" },
+            ],
+        }))
+        .unwrap();
+        let program = Program::new(idl, Pubkey::new_unique());
+        let logs = |ret: Option<u64>| {
+            let mut logs = vec![format!("Program {} invoke [1]", program.id())];
+            logs.extend(ret.map(|ret| {
+                format!(
+                    "Program return: {} {}",
+                    program.id(),
+                    anchor_lang::__private::base64::encode(ret.to_le_bytes())
+                )
+            }));
+            logs.push(format!("Program {} success", program.id()));
+            logs
+        };
+        assert_eq!(
+            program.decode_return("amount", &logs(Some(7))).unwrap(),
+            json!(7)
+        );
+        assert!(matches!(
+            program.decode_return("amount", &logs(None)),
+            Err(ClientError::ReturnDataNotFound)
+        ));
+    }
+}
@@ -0,0 +1,524 @@
+use crate::ClientError;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::{AnchorDeserialize, Event};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+
+// Prefix of logs emitted via `msg!`, which is how `emit!` logs events.
+const PROGRAM_LOG: &str = "Program log: ";
+
+// Prefix of the log emitted by the runtime when a program returns with return
+// data set, followed by the program that set it and the base64 data.
+const PROGRAM_RETURN: &str = "Program return: ";
+
+// Log emitted by the runtime once a transaction exceeds its log limit. No
+// logs are recorded after it.
+const LOG_TRUNCATED: &str = "Log truncated";
+
+lazy_static! {
+    // Logged by the runtime when a program, given by its base58 address, is
+    // invoked at the given stack depth. Anchored so that program logs, e.g.,
+    // "Program log: invoke", aren't mistaken for it.
+    static ref INVOKE_RE: Regex =
+        Regex::new(r"^Program ([1-9A-HJ-NP-Za-km-z]{32,44}) invoke \[\d+\]$").unwrap();
+    // Logged by the runtime when a program returns.
+    static ref RETURN_RE: Regex =
+        Regex::new(r"^Program ([1-9A-HJ-NP-Za-km-z]{32,44}) (success|failed.*)$").unwrap();
+}
+
+type EventDecoder<E> = Box<dyn Fn(&[u8]) -> Result<E, ClientError> + Send + Sync>;
+
+/// `EventParser` decodes the events emitted by a single program from the logs
+/// of a transaction. Any number of event types can be registered, each
+/// identified by its discriminator and decoded into a common type `E`. For
+/// example, an enum with a variant per event:
+///
+/// ```ignore
+/// enum MyEvents {
+///     Deposit(Deposit),
+///     Withdraw(Withdraw),
+/// }
+///
+/// let parser = EventParser::new(program_id)
+///     .event(MyEvents::Deposit)
+///     .event(MyEvents::Withdraw);
+/// ```
+pub struct EventParser<E> {
+    program_id: Pubkey,
+    decoders: HashMap<[u8; 8], EventDecoder<E>>,
+}
+
+impl<E> EventParser<E> {
+    pub fn new(program_id: Pubkey) -> Self {
+        Self {
+            program_id,
+            decoders: HashMap::new(),
+        }
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// Registers the event type `T`, converting every decoded `T` with `f`.
+    pub fn event<T: Event + AnchorDeserialize>(
+        self,
+        f: impl Fn(T) -> E + Send + Sync + 'static,
+    ) -> Self {
+        self.event_with_discriminator(T::discriminator(), move |mut data: &[u8]| {
+            let event =
+                T::deserialize(&mut data).map_err(|e| ClientError::LogParseError(e.to_string()))?;
+            Ok(f(event))
+        })
+    }
+
+    /// Registers a decoder for the event with the given discriminator. `f` is
+    /// given the serialized event, without the discriminator. This allows
+    /// decoding events that aren't known at compile time, e.g., from an IDL.
+    pub fn event_with_discriminator(
+        mut self,
+        discriminator: [u8; 8],
+        f: impl Fn(&[u8]) -> Result<E, ClientError> + Send + Sync + 'static,
+    ) -> Self {
+        self.decoders.insert(discriminator, Box::new(f));
+        self
+    }
+
+    /// Parses all registered events emitted by the program from the logs of a
+    /// single transaction.
+    ///
+    /// A transaction can execute many different programs across CPI
+    /// boundaries, but only the events emitted by *this* program are wanted.
+    /// To achieve this, the program execution context is tracked with a stack
+    /// of invoked programs, pushing on every `invoke` log and popping on every
+    /// `success` or `failed` log, so that events are attributed correctly even
+    /// when this program is itself invoked via CPI.
+    pub fn parse_logs(&self, logs: &[String]) -> Result<ParsedEvents<E>, ClientError> {
+        let self_program_str = self.program_id.to_string();
+        let mut parsed = ParsedEvents {
+            events: Vec::new(),
+            truncated: false,
+            errors: Vec::new(),
+        };
+        let mut logs = logs;
+        if logs.is_empty() {
+            return Ok(parsed);
+        }
+        let mut execution = Execution::new(&mut logs)?;
+        for l in logs {
+            if l == LOG_TRUNCATED {
+                parsed.truncated = true;
+                break;
+            }
+            // The previous top level instruction returned, so this must be the
+            // invocation of the next one.
+            if execution.is_empty() {
+                execution.push(parse_invoke(l)?);
+                continue;
+            }
+            // Only logs from this program can be events.
+            if self_program_str == execution.program() {
+                match self.handle_program_log(l) {
+                    Ok(Some(event)) => {
+                        parsed.events.push(EventRecord {
+                            event,
+                            depth: execution.depth(),
+                        });
+                        continue;
+                    }
+                    // Skip the event, so that one bad event doesn't hide
+                    // the others in the transaction.
+                    Err(e) => {
+                        parsed.errors.push(e);
+                        continue;
+                    }
+                    Ok(None) => {}
+                }
+            }
+            let (new_program, did_pop) = handle_system_log(l);
+            // Switch program context on CPI.
+            if let Some(new_program) = new_program {
+                execution.push(new_program);
+            }
+            // Program returned.
+            if did_pop {
+                execution.pop();
+            }
+        }
+        Ok(parsed)
+    }
+
+    // Decodes the event in the log, if any. Logs that aren't events, e.g.,
+    // plain `msg!` strings or events that aren't registered, are skipped.
+    fn handle_program_log(&self, l: &str) -> Result<Option<E>, ClientError> {
+        let log = match l.strip_prefix(PROGRAM_LOG) {
+            None => return Ok(None),
+            Some(log) => log,
+        };
+        let borsh_bytes = match anchor_lang::__private::base64::decode(log) {
+            Err(_) => return Ok(None),
+            Ok(borsh_bytes) => borsh_bytes,
+        };
+        if borsh_bytes.len() < 8 {
+            return Ok(None);
+        }
+        let mut disc = [0; 8];
+        disc.copy_from_slice(&borsh_bytes[..8]);
+        match self.decoders.get(&disc) {
+            None => Ok(None),
+            Some(decode) => decode(&borsh_bytes[8..]).map(Some),
+        }
+    }
+}
+
+/// The events parsed from the logs of a single transaction.
+#[derive(Debug)]
+pub struct ParsedEvents<E> {
+    pub events: Vec<EventRecord<E>>,
+    /// True if the runtime truncated the logs, in which case any events
+    /// emitted after the truncation are missing.
+    pub truncated: bool,
+    /// The errors decoding registered events, which are skipped.
+    pub errors: Vec<ClientError>,
+}
+
+/// An event emitted by a program along with the depth of the invocation
+/// stack at the time it was emitted, where a depth of 1 is a top level
+/// instruction and anything greater is a CPI.
+#[derive(Debug)]
+pub struct EventRecord<E> {
+    pub event: E,
+    pub depth: usize,
+}
+
+/// Returns the data returned by the last top level instruction to the program
+/// in the logs of a transaction, e.g., of a simulation, or `None` if it
+/// didn't return anything. Data returned via CPI is ignored.
+pub fn parse_return_data(
+    program_id: &Pubkey,
+    logs: &[String],
+) -> Result<Option<Vec<u8>>, ClientError> {
+    let self_program_str = program_id.to_string();
+    let mut data = None;
+    let mut logs = logs;
+    if logs.is_empty() {
+        return Ok(data);
+    }
+    let mut execution = Execution::new(&mut logs)?;
+    for l in logs {
+        if l == LOG_TRUNCATED {
+            break;
+        }
+        if execution.is_empty() {
+            let program = parse_invoke(l)?;
+            // A new instruction to the program, discard any previous return.
+            if program == self_program_str {
+                data = None;
+            }
+            execution.push(program);
+            continue;
+        }
+        if let Some(log) = l.strip_prefix(PROGRAM_RETURN) {
+            // Return data isn't cleared after CPI, so the runtime logs the
+            // return data of the callee again, if the caller doesn't set its
+            // own.
+            let mut parts = log.splitn(2, ' ');
+            let setter = parts.next();
+            if execution.depth() == 1
+                && execution.program() == self_program_str
+                && setter == Some(self_program_str.as_str())
+            {
+                let encoded = parts
+                    .next()
+                    .ok_or_else(|| ClientError::LogParseError(l.to_string()))?;
+                let decoded = anchor_lang::__private::base64::decode(encoded)
+                    .map_err(|_| ClientError::LogParseError(l.to_string()))?;
+                data = Some(decoded);
+            }
+            continue;
+        }
+        let (new_program, did_pop) = handle_system_log(l);
+        if let Some(new_program) = new_program {
+            execution.push(new_program);
+        }
+        if did_pop {
+            execution.pop();
+        }
+    }
+    Ok(data)
+}
+
+// Returns the program invoked, if the log is an `invoke` log, and true if the
+// log marks the return of the currently executing program.
+fn handle_system_log(log: &str) -> (Option<String>, bool) {
+    if let Ok(program) = parse_invoke(log) {
+        (Some(program), false)
+    } else {
+        if RETURN_RE.is_match(log) {
+            (None, true)
+        } else {
+            (None, false)
+        }
+    }
+}
+
+// Returns the program invoked by an `invoke` log.
+fn parse_invoke(l: &str) -> Result<String, ClientError> {
+    let c = INVOKE_RE
+        .captures(l)
+        .ok_or(ClientError::LogParseError(l.to_string()))?;
+    let program = c
+        .get(1)
+        .ok_or(ClientError::LogParseError(l.to_string()))?
+        .as_str()
+        .to_string();
+    Ok(program)
+}
+
+// Stack frame execution context, allowing one to track what program is
+// executing for a given log.
+struct Execution {
+    stack: Vec<String>,
+}
+
+impl Execution {
+    pub fn new(logs: &mut &[String]) -> Result<Self, ClientError> {
+        let l = &logs[0];
+        *logs = &logs[1..];
+
+        let program = parse_invoke(l)?;
+        Ok(Self {
+            stack: vec![program],
+        })
+    }
+
+    pub fn program(&self) -> String {
+        assert!(self.stack.len() > 0);
+        self.stack[self.stack.len() - 1].clone()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn push(&mut self, new_program: String) {
+        self.stack.push(new_program);
+    }
+
+    pub fn pop(&mut self) {
+        assert!(self.stack.len() > 0);
+        self.stack.pop().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::*;
+
+    const PROGRAM_ID: &str = "7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw";
+    const OTHER_ID: &str = "7swsTUiQ6KUK4uFYquQKg4epFRsBnvbrTf2fZQCa2sTJ";
+
+    #[event]
+    struct EventA {
+        data: u64,
+    }
+
+    #[event]
+    struct EventB {
+        label: String,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum TestEvents {
+        A(u64),
+        B(String),
+    }
+
+    fn event_log(event: impl anchor_lang::Event) -> String {
+        format!(
+            "Program log: {}",
+            anchor_lang::__private::base64::encode(event.data())
+        )
+    }
+
+    fn parser() -> EventParser<TestEvents> {
+        EventParser::new(PROGRAM_ID.parse().unwrap())
+            .event(|e: EventA| TestEvents::A(e.data))
+            .event(|e: EventB| TestEvents::B(e.label))
+    }
+
+    fn events(parsed: &ParsedEvents<TestEvents>) -> Vec<(&TestEvents, usize)> {
+        parsed.events.iter().map(|e| (&e.event, e.depth)).collect()
+    }
+
+    #[test]
+    fn new_execution() {
+        let mut logs: &[String] =
+            &["Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw invoke [1]".to_string()];
+        let exe = Execution::new(&mut logs).unwrap();
+        assert_eq!(
+            exe.stack[0],
+            "7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw".to_string()
+        );
+    }
+
+    #[test]
+    fn handle_system_log_pop() {
+        let log = "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw success";
+        let (program, did_pop) = handle_system_log(log);
+        assert_eq!(program, None);
+        assert_eq!(did_pop, true);
+    }
+
+    #[test]
+    fn handle_system_log_no_pop() {
+        let log = "Program 7swsTUiQ6KUK4uFYquQKg4epFRsBnvbrTf2fZQCa2sTJ qwer";
+        let (program, did_pop) = handle_system_log(log);
+        assert_eq!(program, None);
+        assert_eq!(did_pop, false);
+    }
+
+    #[test]
+    fn handle_system_log_program_log() {
+        // Program logs must not be mistaken for runtime logs.
+        for log in &[
+            "Program log: invoke [1]",
+            "Program log: invoke the auth program",
+            "Program log: success",
+        ] {
+            let (program, did_pop) = handle_system_log(log);
+            assert_eq!(program, None);
+            assert_eq!(did_pop, false);
+        }
+    }
+
+    #[test]
+    fn parse_multiple_event_types_across_instructions() {
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            event_log(EventA { data: 1 }),
+            "Program log: not an event".to_string(),
+            format!("Program {} invoke [2]", OTHER_ID),
+            event_log(EventA { data: 2 }),
+            format!("Program {} success", OTHER_ID),
+            event_log(EventB {
+                label: "b".to_string(),
+            }),
+            format!("Program {} success", PROGRAM_ID),
+            format!("Program {} invoke [1]", OTHER_ID),
+            event_log(EventA { data: 3 }),
+            format!("Program {} success", OTHER_ID),
+        ];
+        let parsed = parser().parse_logs(&logs).unwrap();
+        assert!(!parsed.truncated);
+        assert_eq!(
+            events(&parsed),
+            vec![(&TestEvents::A(1), 1), (&TestEvents::B("b".to_string()), 1)]
+        );
+    }
+
+    #[test]
+    fn parse_events_emitted_via_cpi() {
+        let logs = vec![
+            format!("Program {} invoke [1]", OTHER_ID),
+            format!("Program {} invoke [2]", PROGRAM_ID),
+            event_log(EventA { data: 1 }),
+            format!(
+                "Program {} consumed 1000 of 200000 compute units",
+                PROGRAM_ID
+            ),
+            format!("Program {} success", PROGRAM_ID),
+            event_log(EventA { data: 2 }),
+            format!("Program {} success", OTHER_ID),
+        ];
+        let parsed = parser().parse_logs(&logs).unwrap();
+        assert_eq!(events(&parsed), vec![(&TestEvents::A(1), 2)]);
+    }
+
+    #[test]
+    fn parse_truncated_logs() {
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            event_log(EventA { data: 1 }),
+            "Log truncated".to_string(),
+        ];
+        let parsed = parser().parse_logs(&logs).unwrap();
+        assert!(parsed.truncated);
+        assert_eq!(events(&parsed), vec![(&TestEvents::A(1), 1)]);
+    }
+
+    #[test]
+    fn parse_logs_skips_bad_events() {
+        let mut bad_event = <EventB as anchor_lang::Discriminator>::discriminator().to_vec();
+        bad_event.extend_from_slice(&[1, 0]);
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            event_log(EventA { data: 1 }),
+            format!(
+                "Program log: {}",
+                anchor_lang::__private::base64::encode(bad_event)
+            ),
+            event_log(EventA { data: 2 }),
+            format!("Program {} success", PROGRAM_ID),
+        ];
+        let parsed = parser().parse_logs(&logs).unwrap();
+        assert_eq!(
+            events(&parsed),
+            vec![(&TestEvents::A(1), 1), (&TestEvents::A(2), 1)]
+        );
+        assert_eq!(parsed.errors.len(), 1);
+    }
+
+    fn return_log(program: &str, data: impl AnchorSerialize) -> String {
+        format!(
+            "Program return: {} {}",
+            program,
+            anchor_lang::__private::base64::encode(data.try_to_vec().unwrap())
+        )
+    }
+
+    #[test]
+    fn parse_return_data_of_last_instruction() {
+        let program_id: Pubkey = PROGRAM_ID.parse().unwrap();
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            return_log(PROGRAM_ID, 1u64),
+            format!("Program {} success", PROGRAM_ID),
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            format!("Program {} invoke [2]", OTHER_ID),
+            return_log(OTHER_ID, 2u64),
+            format!("Program {} success", OTHER_ID),
+            // Logged again, since the caller didn't set its own return data.
+            return_log(OTHER_ID, 2u64),
+            format!("Program {} success", PROGRAM_ID),
+        ];
+        // The last instruction didn't return anything itself.
+        assert_eq!(parse_return_data(&program_id, &logs).unwrap(), None);
+        assert_eq!(
+            parse_return_data(&program_id, &logs[..3]).unwrap(),
+            Some(1u64.try_to_vec().unwrap())
+        );
+
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            format!("Program {} invoke [2]", OTHER_ID),
+            return_log(OTHER_ID, 2u64),
+            format!("Program {} success", OTHER_ID),
+            return_log(PROGRAM_ID, 3u64),
+            format!("Program {} success", PROGRAM_ID),
+            format!("Program {} invoke [1]", OTHER_ID),
+            return_log(OTHER_ID, 4u64),
+            format!("Program {} success", OTHER_ID),
+        ];
+        assert_eq!(
+            parse_return_data(&program_id, &logs).unwrap(),
+            Some(3u64.try_to_vec().unwrap())
+        );
+    }
+}
@@ -6,29 +6,47 @@ use anchor_lang::solana_program::program_error::ProgramError;
 use anchor_lang::solana_program::pubkey::Pubkey;
 use anchor_lang::solana_program::system_program;
 use anchor_lang::solana_program::sysvar::rent;
-use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
-use regex::Regex;
+use anchor_lang::{
+    AccountDeserialize, AnchorDeserialize, Discriminator, InstructionData, ToAccountMetas,
+};
+use solana_account_decoder::{UiAccount, UiAccountData, UiAccountEncoding};
 use solana_client::client_error::ClientError as SolanaClientError;
 use solana_client::pubsub_client::{PubsubClient, PubsubClientError, PubsubClientSubscription};
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
-use solana_client::rpc_response::{Response as RpcResponse, RpcLogsResponse};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
+};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_client::rpc_response::{Response as RpcResponse, RpcKeyedAccount, RpcLogsResponse};
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::signature::{Keypair, Signature, Signer};
 use solana_sdk::transaction::Transaction;
+use solana_transaction_status::UiTransactionEncoding;
 use std::convert::Into;
 use thiserror::Error;
 
 pub use anchor_lang;
 pub use cluster::Cluster;
+pub use event::{parse_return_data, EventParser, EventRecord, ParsedEvents};
 pub use solana_client;
 pub use solana_sdk;
 
 mod cluster;
+pub mod coder;
+pub mod dynamic;
+mod event;
 
 /// EventHandle unsubscribes from a program event stream on drop.
 pub type EventHandle = PubsubClientSubscription<RpcResponse<RpcLogsResponse>>;
 
+/// AccountHandle unsubscribes from an account change stream on drop.
+pub type AccountHandle = PubsubClientSubscription<RpcResponse<UiAccount>>;
+
+/// ProgramAccountsHandle unsubscribes from a program account change stream
+/// on drop.
+pub type ProgramAccountsHandle = PubsubClientSubscription<RpcResponse<RpcKeyedAccount>>;
+
 /// Client defines the base configuration for building RPC clients to
 /// communitcate with Anchor programs running on a Solana cluster. It's
 /// primary use is to build a `Program` client via the `program` method.
@@ -138,156 +156,220 @@ impl Program {
         self.program_id
     }
 
-    pub fn on<T: anchor_lang::Event + anchor_lang::AnchorDeserialize>(
+    /// Returns a parser for the events emitted by the program.
+    pub fn event_parser<E>(&self) -> EventParser<E> {
+        EventParser::new(self.program_id)
+    }
+
+    /// Subscribes to the events of type `T` emitted by the program. Events
+    /// that can't be decoded, or that are missing from truncated logs, are
+    /// skipped. Use `on_events` to be notified of them.
+    pub fn on<T: anchor_lang::Event + anchor_lang::AnchorDeserialize + 'static>(
         &self,
         f: impl Fn(&EventContext, T) -> () + Send + 'static,
     ) -> Result<EventHandle, ClientError> {
-        let addresses = vec![self.program_id.to_string()];
+        self.on_events(self.event_parser().event(|e: T| e), move |ctx, parsed| {
+            if let Ok(parsed) = parsed {
+                for e in parsed.events {
+                    f(ctx, e.event);
+                }
+            }
+        })
+    }
+
+    /// Subscribes to all events registered with the given `parser`, invoking
+    /// `f` with the events parsed from every transaction of the program,
+    /// including whether its logs were truncated and the events that couldn't
+    /// be decoded, or with the error if its logs couldn't be parsed at all.
+    pub fn on_events<E: 'static>(
+        &self,
+        parser: EventParser<E>,
+        f: impl Fn(&EventContext, Result<ParsedEvents<E>, ClientError>) -> () + Send + 'static,
+    ) -> Result<EventHandle, ClientError> {
+        let addresses = vec![parser.program_id().to_string()];
         let filter = RpcTransactionLogsFilter::Mentions(addresses);
         let ws_url = self.cfg.cluster.ws_url().to_string();
         let cfg = RpcTransactionLogsConfig {
             commitment: self.cfg.options,
         };
-        let self_program_str = self.program_id.to_string();
         let (client, receiver) = PubsubClient::logs_subscribe(&ws_url, filter.clone(), cfg)?;
-        std::thread::spawn(move || {
-            loop {
-                match receiver.recv() {
-                    Ok(logs) => {
-                        let ctx = EventContext {
-                            signature: logs.value.signature.parse().unwrap(),
-                            slot: logs.context.slot,
-                        };
-                        let mut logs = &logs.value.logs[..];
-                        if logs.len() > 0 {
-                            if let Ok(mut execution) = Execution::new(&mut logs) {
-                                for l in logs {
-                                    // Parse the log.
-                                    let (event, new_program, did_pop) = {
-                                        if self_program_str == execution.program() {
-                                            handle_program_log(&self_program_str, &l)
-                                                .unwrap_or_else(|e| {
-                                                    println!(
-                                                        "Unable to parse log: {}",
-                                                        e.to_string()
-                                                    );
-                                                    std::process::exit(1);
-                                                })
-                                        } else {
-                                            let (program, did_pop) =
-                                                handle_system_log(&self_program_str, &l);
-                                            (None, program, did_pop)
-                                        }
-                                    };
-                                    // Emit the event.
-                                    if let Some(e) = event {
-                                        f(&ctx, e);
-                                    }
-                                    // Switch program context on CPI.
-                                    if let Some(new_program) = new_program {
-                                        execution.push(new_program);
-                                    }
-                                    // Program returned.
-                                    if did_pop {
-                                        execution.pop();
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(_err) => {
-                        return;
-                    }
+        std::thread::spawn(move || loop {
+            match receiver.recv() {
+                Ok(logs) => {
+                    let ctx = EventContext {
+                        signature: logs.value.signature.parse().unwrap(),
+                        slot: logs.context.slot,
+                    };
+                    f(&ctx, parser.parse_logs(&logs.value.logs));
+                }
+                Err(_err) => {
+                    return;
                 }
             }
         });
         Ok(client)
     }
-}
 
-fn handle_program_log<T: anchor_lang::Event + anchor_lang::AnchorDeserialize>(
-    self_program_str: &str,
-    l: &str,
-) -> Result<(Option<T>, Option<String>, bool), ClientError> {
-    // Log emitted from the current program.
-    if l.starts_with("Program log:") {
-        let log = l.to_string().split_off("Program log: ".len());
-        let borsh_bytes = anchor_lang::__private::base64::decode(log)
-            .map_err(|_| ClientError::LogParseError(l.to_string()))?;
-
-        let mut slice: &[u8] = &borsh_bytes[..];
-        let disc: [u8; 8] = {
-            let mut disc = [0; 8];
-            disc.copy_from_slice(&borsh_bytes[..8]);
-            slice = &slice[8..];
-            disc
+    /// Fetches the confirmed transaction with the given signature and returns
+    /// all events registered with `parser` emitted in it.
+    pub fn events_from_transaction<E>(
+        &self,
+        parser: &EventParser<E>,
+        signature: &Signature,
+    ) -> Result<(EventContext, ParsedEvents<E>), ClientError> {
+        let tx = self
+            .rpc()
+            .get_confirmed_transaction(signature, UiTransactionEncoding::Json)?;
+        let ctx = EventContext {
+            signature: *signature,
+            slot: tx.slot,
         };
-        let mut event = None;
-        if disc == T::discriminator() {
-            let e: T = anchor_lang::AnchorDeserialize::deserialize(&mut slice)
-                .map_err(|e| ClientError::LogParseError(e.to_string()))?;
-            event = Some(e);
-        }
-        Ok((event, None, false))
-    }
-    // System log.
-    else {
-        let (program, did_pop) = handle_system_log(&self_program_str, &l);
-        Ok((None, program, did_pop))
-    }
-}
-
-fn handle_system_log(this_program_str: &str, log: &str) -> (Option<String>, bool) {
-    if log.starts_with(&format!("Program {} log:", this_program_str)) {
-        (Some(this_program_str.to_string()), false)
-    } else if log.contains("invoke") {
-        (Some("cpi".to_string()), false) // Any string will do.
-    } else {
-        let re = Regex::new(r"^Program (.*) success*$").unwrap();
-        if re.is_match(log) {
-            (None, true)
-        } else {
-            (None, false)
+        let logs: Vec<String> = tx
+            .transaction
+            .meta
+            .and_then(|meta| meta.log_messages)
+            .ok_or(ClientError::LogsNotFound)?;
+        let events = parser.parse_logs(&logs)?;
+        Ok((ctx, events))
+    }
+
+    /// Fetches a page of the program's transaction history, newest first,
+    /// and returns the events registered with `parser` emitted in each
+    /// transaction. Failed transactions don't persist any state, so they're
+    /// returned without events, but they're still returned so that the page
+    /// can be continued from. To rebuild the full history, pass the signature
+    /// of the last transaction returned as `config.before` for the next page,
+    /// until an empty page is returned.
+    pub fn event_history<E>(
+        &self,
+        parser: &EventParser<E>,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<Vec<(EventContext, ParsedEvents<E>)>, ClientError> {
+        let signatures = self
+            .rpc()
+            .get_confirmed_signatures_for_address2_with_config(&parser.program_id(), config)?;
+        let mut history = Vec::with_capacity(signatures.len());
+        for status in signatures {
+            let signature: Signature = status
+                .signature
+                .parse()
+                .map_err(|_| ClientError::LogParseError(status.signature.clone()))?;
+            if status.err.is_some() {
+                let ctx = EventContext {
+                    signature,
+                    slot: status.slot,
+                };
+                let events = ParsedEvents {
+                    events: Vec::new(),
+                    truncated: false,
+                    errors: Vec::new(),
+                };
+                history.push((ctx, events));
+                continue;
+            }
+            history.push(self.events_from_transaction(parser, &signature)?);
         }
+        Ok(history)
     }
-}
-
-struct Execution {
-    stack: Vec<String>,
-}
 
-impl Execution {
-    pub fn new(logs: &mut &[String]) -> Result<Self, ClientError> {
-        let l = &logs[0];
-        *logs = &logs[1..];
-
-        let re = Regex::new(r"^Program (.*) invoke.*$").unwrap();
-        let c = re
-            .captures(l)
-            .ok_or(ClientError::LogParseError(l.to_string()))?;
-        let program = c
-            .get(1)
-            .ok_or(ClientError::LogParseError(l.to_string()))?
-            .as_str()
-            .to_string();
-        Ok(Self {
-            stack: vec![program],
-        })
-    }
-
-    pub fn program(&self) -> String {
-        assert!(self.stack.len() > 0);
-        self.stack[self.stack.len() - 1].clone()
+    /// Subscribes to changes of the account at the given address, invoking
+    /// `f` with the deserialized account on every update. Updates that can't
+    /// be deserialized into `T`, e.g., after the account is closed, are
+    /// skipped.
+    pub fn subscribe_account<T: AccountDeserialize>(
+        &self,
+        address: Pubkey,
+        f: impl Fn(&AccountContext, T) -> () + Send + 'static,
+    ) -> Result<AccountHandle, ClientError> {
+        let ws_url = self.cfg.cluster.ws_url().to_string();
+        let cfg = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: self.cfg.options,
+            ..RpcAccountInfoConfig::default()
+        };
+        let (client, receiver) = PubsubClient::account_subscribe(&ws_url, &address, Some(cfg))?;
+        std::thread::spawn(move || loop {
+            match receiver.recv() {
+                Ok(update) => {
+                    let ctx = AccountContext {
+                        pubkey: address,
+                        slot: update.context.slot,
+                    };
+                    if let Ok(account) = decode_ui_account(&update.value) {
+                        f(&ctx, account);
+                    }
+                }
+                Err(_err) => {
+                    return;
+                }
+            }
+        });
+        Ok(client)
     }
 
-    pub fn push(&mut self, new_program: String) {
-        self.stack.push(new_program);
+    /// Subscribes to changes of every account of type `T` owned by the
+    /// program. Accounts are filtered by their discriminator on the server,
+    /// so `f` is only invoked for accounts of the requested type.
+    pub fn subscribe_program_accounts<T: AccountDeserialize + Discriminator>(
+        &self,
+        f: impl Fn(&AccountContext, T) -> () + Send + 'static,
+    ) -> Result<ProgramAccountsHandle, ClientError> {
+        let ws_url = self.cfg.cluster.ws_url().to_string();
+        let discriminator_filter = RpcFilterType::Memcmp(Memcmp {
+            offset: 0,
+            bytes: MemcmpEncodedBytes::Binary(bs58::encode(T::discriminator()).into_string()),
+            encoding: None,
+        });
+        let cfg = RpcProgramAccountsConfig {
+            filters: Some(vec![discriminator_filter]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: self.cfg.options,
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        let (client, receiver) =
+            PubsubClient::program_subscribe(&ws_url, &self.program_id, Some(cfg))?;
+        std::thread::spawn(move || loop {
+            match receiver.recv() {
+                Ok(update) => {
+                    let pubkey = match update.value.pubkey.parse() {
+                        Ok(pubkey) => pubkey,
+                        Err(_) => continue,
+                    };
+                    let ctx = AccountContext {
+                        pubkey,
+                        slot: update.context.slot,
+                    };
+                    if let Ok(account) = decode_ui_account(&update.value.account) {
+                        f(&ctx, account);
+                    }
+                }
+                Err(_err) => {
+                    return;
+                }
+            }
+        });
+        Ok(client)
     }
+}
 
-    pub fn pop(&mut self) {
-        assert!(self.stack.len() > 0);
-        self.stack.pop().unwrap();
-    }
+// Deserializes a base64 encoded account received from a pubsub subscription.
+fn decode_ui_account<T: AccountDeserialize>(account: &UiAccount) -> Result<T, ClientError> {
+    let data = match &account.data {
+        UiAccountData::Binary(data, UiAccountEncoding::Base64) => {
+            anchor_lang::__private::base64::decode(data)
+                .map_err(|e| ClientError::AccountDecodeError(e.to_string()))?
+        }
+        _ => {
+            return Err(ClientError::AccountDecodeError(
+                "Unexpected account encoding".to_string(),
+            ))
+        }
+    };
+    let mut data: &[u8] = &data;
+    T::try_deserialize(&mut data).map_err(Into::into)
 }
 
 #[derive(Debug)]
@@ -296,6 +378,12 @@ pub struct EventContext {
     pub slot: u64,
 }
 
+#[derive(Debug)]
+pub struct AccountContext {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+}
+
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("Account not found")]
@@ -308,6 +396,18 @@ pub enum ClientError {
     SolanaClientPubsubError(#[from] PubsubClientError),
     #[error("Unable to parse log: {0}")]
     LogParseError(String),
+    #[error("Unable to decode account: {0}")]
+    AccountDecodeError(String),
+    #[error("Transaction logs not found")]
+    LogsNotFound,
+    #[error("Transaction simulation failed: {0}")]
+    SimulationError(String),
+    #[error("Return data not found")]
+    ReturnDataNotFound,
+    #[error("Unable to decode return data: {0}")]
+    ReturnDataDecodeError(String),
+    #[error("{0}")]
+    IdlError(String),
 }
 
 /// `RequestBuilder` provides a builder interface to create and send
@@ -407,6 +507,43 @@ impl<'a> RequestBuilder<'a> {
     }
 
     pub fn send(self) -> Result<Signature, ClientError> {
+        let rpc_client = RpcClient::new_with_commitment(self.cluster.clone(), self.options);
+        let tx = self.signed_transaction(&rpc_client)?;
+        rpc_client
+            .send_and_confirm_transaction(&tx)
+            .map_err(Into::into)
+    }
+
+    /// Simulates the transaction, returning its logs.
+    pub fn simulate(self) -> Result<Vec<String>, ClientError> {
+        let rpc_client = RpcClient::new_with_commitment(self.cluster.clone(), self.options);
+        let tx = self.signed_transaction(&rpc_client)?;
+        let result = rpc_client.simulate_transaction(&tx)?.value;
+        let logs = result.logs.unwrap_or_default();
+        match result.err {
+            None => Ok(logs),
+            Some(err) => Err(ClientError::SimulationError(format!(
+                "{}\n{}",
+                err,
+                logs.join("\n")
+            ))),
+        }
+    }
+
+    /// Simulates the transaction, returning the value returned by the last
+    /// instruction to the program, i.e., the `T` of a handler returning
+    /// `Result<T>`. Nothing is committed, so this can be used to read values
+    /// computed by a program without sending a transaction. Errors with
+    /// `ClientError::ReturnDataNotFound` if the instruction didn't return
+    /// anything, as the runtime doesn't log empty return data.
+    pub fn view<T: AnchorDeserialize>(self) -> Result<T, ClientError> {
+        let program_id = self.program_id;
+        let logs = self.simulate()?;
+        let data = parse_return_data(&program_id, &logs)?.ok_or(ClientError::ReturnDataNotFound)?;
+        T::try_from_slice(&data).map_err(|e| ClientError::ReturnDataDecodeError(e.to_string()))
+    }
+
+    fn signed_transaction(self, rpc_client: &RpcClient) -> Result<Transaction, ClientError> {
         let accounts = match self.namespace {
             RequestNamespace::State { new } => {
                 let mut accounts = match new {
@@ -446,51 +583,12 @@ impl<'a> RequestBuilder<'a> {
         let mut signers = self.signers;
         signers.push(&self.payer);
 
-        let rpc_client = RpcClient::new_with_commitment(self.cluster, self.options);
-
-        let tx = {
-            let (recent_hash, _fee_calc) = rpc_client.get_recent_blockhash()?;
-            Transaction::new_signed_with_payer(
-                &instructions,
-                Some(&self.payer.pubkey()),
-                &signers,
-                recent_hash,
-            )
-        };
-
-        rpc_client
-            .send_and_confirm_transaction(&tx)
-            .map_err(Into::into)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn new_execution() {
-        let mut logs: &[String] =
-            &["Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw invoke [1]".to_string()];
-        let exe = Execution::new(&mut logs).unwrap();
-        assert_eq!(
-            exe.stack[0],
-            "7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw".to_string()
-        );
-    }
-
-    #[test]
-    fn handle_system_log_pop() {
-        let log = "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw success";
-        let (program, did_pop) = handle_system_log("asdf", log);
-        assert_eq!(program, None);
-        assert_eq!(did_pop, true);
-    }
-
-    #[test]
-    fn handle_system_log_no_pop() {
-        let log = "Program 7swsTUiQ6KUK4uFYquQKg4epFRsBnvbrTf2fZQCa2sTJ qwer";
-        let (program, did_pop) = handle_system_log("asdf", log);
-        assert_eq!(program, None);
-        assert_eq!(did_pop, false);
+        let (recent_hash, _fee_calc) = rpc_client.get_recent_blockhash()?;
+        Ok(Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.payer.pubkey()),
+            &signers,
+            recent_hash,
+        ))
     }
 }
@@ -1,5 +1,6 @@
 extern crate proc_macro;
 
+use anchor_syn::codegen::idl_build;
 use quote::quote;
 use syn::parse_macro_input;
 
@@ -97,12 +98,21 @@ pub fn account(
         format!("{:?}", discriminator).parse().unwrap()
     };
 
+    // Zero copy structs implement `IdlBuild` via `#[zero_copy]`.
+    let idl_build_impl = match is_zero_copy {
+        true => quote! {},
+        false => idl_build::generate_struct(&account_strct),
+    };
+    let idl_build_print = idl_build::generate_account(&account_strct);
+
     proc_macro::TokenStream::from({
         if is_zero_copy {
             quote! {
                 #[zero_copy]
                 #account_strct
 
+                #idl_build_print
+
                 unsafe impl anchor_lang::__private::bytemuck::Pod for #account_name {}
                 unsafe impl anchor_lang::__private::bytemuck::Zeroable for #account_name {}
 
@@ -142,6 +152,9 @@ pub fn account(
                 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
                 #account_strct
 
+                #idl_build_impl
+                #idl_build_print
+
                 impl anchor_lang::AccountSerialize for #account_name {
                     fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> std::result::Result<(), ProgramError> {
                         writer.write_all(&#discriminator).map_err(|_| ProgramError::InvalidAccountData)?;
@@ -309,6 +322,20 @@ pub fn derive_zero_copy_accessor(item: proc_macro::TokenStream) -> proc_macro::T
     })
 }
 
+/// Implements `anchor_lang::idl_build::IdlBuild` for a user defined type, when
+/// the program is built with the `idl-build` feature, so that it can be used
+/// in the IDL built by `anchor idl build`. Types marked with `#[account]`,
+/// `#[zero_copy]` or `#[event]` don't need this derive.
+#[proc_macro_derive(IdlBuild)]
+pub fn derive_idl_build(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let tts = match parse_macro_input!(item as syn::Item) {
+        syn::Item::Struct(strct) => idl_build::generate_struct(&strct),
+        syn::Item::Enum(enm) => idl_build::generate_enum(&enm),
+        _ => panic!("IdlBuild can only be derived for structs and enums"),
+    };
+    proc_macro::TokenStream::from(tts)
+}
+
 /// A data structure that can be used as an internal field for a zero copy
 /// deserialized account, i.e., a struct marked with `#[account(zero_copy)]`.
 ///
@@ -325,10 +352,13 @@ pub fn zero_copy(
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let account_strct = parse_macro_input!(item as syn::ItemStruct);
+    let idl_build = idl_build::generate_zero_copy_struct(&account_strct);
 
     proc_macro::TokenStream::from(quote! {
             #[derive(anchor_lang::__private::ZeroCopyAccessor, Copy, Clone)]
             #[repr(packed)]
             #account_strct
+
+            #idl_build
     })
 }
@@ -0,0 +1,28 @@
+extern crate proc_macro;
+
+use anchor_syn::codegen::idl_build;
+use quote::quote;
+use syn::parse_macro_input;
+
+/// Exports a constant to the IDL, so that clients don't have to duplicate
+/// seeds, fee rates, limits, etc. The constant is otherwise left as is.
+///
+/// ```ignore
+/// #[constant]
+/// pub const SEED: &[u8] = b"seed";
+/// ```
+#[proc_macro_attribute]
+pub fn constant(
+    _args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let item = parse_macro_input!(input as syn::ItemConst);
+
+    let idl_build = idl_build::generate_constant(&item);
+
+    proc_macro::TokenStream::from(quote! {
+        #item
+
+        #idl_build
+    })
+}
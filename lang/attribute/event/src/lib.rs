@@ -1,5 +1,6 @@
 extern crate proc_macro;
 
+use anchor_syn::codegen::idl_build;
 use quote::quote;
 use syn::parse_macro_input;
 
@@ -25,6 +26,8 @@ pub fn event(
         format!("{:?}", discriminator).parse().unwrap()
     };
 
+    let idl_build = idl_build::generate_event(&event_strct);
+
     proc_macro::TokenStream::from(quote! {
         #[derive(anchor_lang::__private::EventIndex, AnchorSerialize, AnchorDeserialize)]
         #event_strct
@@ -42,6 +45,8 @@ pub fn event(
                 #discriminator
             }
         }
+
+        #idl_build
     })
 }
 
@@ -6,12 +6,17 @@ use syn::parse_macro_input;
 
 /// The `#[program]` attribute defines the module containing all instruction
 /// handlers defining all entries into a Solana program.
+///
+/// With `#[program(return_data)]`, handlers returning `anchor_lang::Result<T>`,
+/// rather than `ProgramResult`, return `T` to their caller via
+/// `anchor_lang::return_data`, which requires Solana 1.9 or later.
 #[proc_macro_attribute]
 pub fn program(
-    _args: proc_macro::TokenStream,
+    args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
+    let return_data = args.to_string() == "return_data";
     let program_mod = parse_macro_input!(input as syn::ItemMod);
-    let code = program_codegen::generate(program_parser::parse(program_mod));
+    let code = program_codegen::generate(program_parser::parse(program_mod, return_data));
     proc_macro::TokenStream::from(code)
 }
@@ -14,6 +14,22 @@
 //! multiple transactions via the `Write` instruction to continuously append to
 //! the account's IDL data buffer.
 //!
+//! Buffers that are no longer needed, e.g., after an upgrade or an abandoned
+//! write, can be closed with `IdlInstruction::Close` to reclaim their rent.
+//!
+//! The size of the canonical IDL account is fixed at creation and the runtime
+//! doesn't allow programs to change the size of their accounts, so it's
+//! resized by recreating it with `IdlInstruction::Resize`, in two
+//! transactions, since an account closed in a transaction is only deleted
+//! once the transaction ends. The first moves the IDL into the resize account,
+//! which has the new size and is at another address derived from the
+//! program's ID, and closes the IDL account. The second recreates the IDL
+//! account with the resize account's size and contents and closes the resize
+//! account. The authority pays for the rent of the new accounts and receives
+//! the lamports of the closed ones. While the resize account exists, the IDL
+//! account can't be recreated with `IdlInstruction::Create`, so that no one
+//! else can take it over.
+//!
 //! Note that IDL account instructions are automatically inserted into all
 //! Anchor programs. To remove them, one can use the `no-idl` feature.
 
@@ -39,10 +55,38 @@ pub enum IdlInstruction {
     SetBuffer,
     // Sets a new authority on the IdlAccount.
     SetAuthority { new_authority: Pubkey },
+    // Closes an IDL buffer, transferring its lamports to the destination.
+    Close,
+    // Moves the IDL account into a resize account with room for `data_len`
+    // bytes of IDL data, or, if already moved, moves it back.
+    Resize { data_len: u64 },
 }
 
-// Accounts for the Create instruction.
-pub type IdlCreateAccounts<'info> = crate::ctor::Ctor<'info>;
+// Accounts for the Create instruction. Same as `Ctor`, plus the resize
+// account, which must not exist. It's required, rather than optional, since
+// while the IDL is resized its account doesn't exist, and could otherwise be
+// recreated by anyone who omits the resize account.
+#[derive(Accounts)]
+pub struct IdlCreateAccounts<'info> {
+    // Payer of the transaction.
+    #[account(signer)]
+    pub from: AccountInfo<'info>,
+    // The deterministically defined "state" account being created via
+    // `create_account_with_seed`.
+    #[account(mut)]
+    pub to: AccountInfo<'info>,
+    // The program-derived-address signing off on the account creation.
+    // Seeds = &[] + bump seed.
+    pub base: AccountInfo<'info>,
+    // The system program.
+    pub system_program: AccountInfo<'info>,
+    // The program whose state is being constructed.
+    pub program: AccountInfo<'info>,
+    // Rent sysvar.
+    pub rent: Sysvar<'info, Rent>,
+    // The resize account.
+    pub resize: AccountInfo<'info>,
+}
 
 // Accounts for Idl instructions.
 #[derive(Accounts)]
@@ -76,6 +120,39 @@ pub struct IdlSetBuffer<'info> {
     pub authority: AccountInfo<'info>,
 }
 
+// Accounts for closing an idl buffer.
+#[derive(Accounts)]
+pub struct IdlCloseBuffer<'info> {
+    #[account(mut, has_one = authority)]
+    pub buffer: ProgramAccount<'info, IdlAccount>,
+    #[account(signer, "authority.key != &Pubkey::new_from_array([0u8; 32])")]
+    pub authority: AccountInfo<'info>,
+    // Receives the buffer's lamports.
+    #[account(mut)]
+    pub sol_destination: AccountInfo<'info>,
+}
+
+// Accounts for resizing the canonical IdlAccount.
+#[derive(Accounts)]
+pub struct IdlResize<'info> {
+    // The canonical idl account.
+    #[account(mut)]
+    pub idl: AccountInfo<'info>,
+    // The resize account.
+    #[account(mut)]
+    pub resize: AccountInfo<'info>,
+    // Pays for the rent of the new account and receives the lamports of the
+    // closed one.
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+    // The program-derived-address signing off on the account creation.
+    // Seeds = &[] + bump seed.
+    pub base: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub program: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
 // The account holding a program's IDL. This is stored on chain so that clients
 // can fetch it and generate a client with nothing but a program's ID.
 //
@@ -99,4 +176,15 @@ impl IdlAccount {
     pub fn seed() -> &'static str {
         "anchor:idl"
     }
+
+    // Address of the account holding the IDL while the canonical account is
+    // being resized.
+    pub fn resize_address(program_id: &Pubkey) -> Pubkey {
+        let program_signer = Pubkey::find_program_address(&[], program_id).0;
+        Pubkey::create_with_seed(&program_signer, IdlAccount::resize_seed(), program_id)
+            .expect("Seed is always valid")
+    }
+    pub fn resize_seed() -> &'static str {
+        "anchor:idl:resize"
+    }
 }
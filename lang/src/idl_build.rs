@@ -0,0 +1,261 @@
+//! Builds the IDL from compiled type information, as an alternative to
+//! parsing the program's source, which can't resolve type aliases or types
+//! defined in other crates.
+//!
+//! When a program is built with the `idl-build` feature, the `#[program]`,
+//! `#[account]`, `#[event]`, `#[error]` and `#[constant]` macros generate
+//! tests printing their part of the IDL, using the
+//! [`IdlBuild`](./trait.IdlBuild.html) implementations of the types involved.
+//! `anchor idl build` runs these tests and merges their output into the
+//! program's IDL.
+//!
+//! Types defined in the program other than accounts and events implement
+//! `IdlBuild` via `#[derive(IdlBuild)]`, and so must types from other crates
+//! used in the IDL.
+
+use solana_program::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+
+pub use anchor_syn::idl::*;
+pub use serde_json;
+
+/// Type definitions by name.
+pub type IdlTypeDefs = BTreeMap<String, IdlTypeDef>;
+
+/// A type that can be described in the IDL.
+pub trait IdlBuild {
+    /// Returns the type as referred to in the IDL.
+    fn idl_type() -> IdlType;
+
+    /// Inserts the definition of the type, if it's user defined, and of all
+    /// the types it's composed of.
+    fn insert_idl_type_defs(_defs: &mut IdlTypeDefs) {}
+}
+
+/// A struct deriving `Accounts`.
+pub trait IdlBuildAccounts {
+    fn idl_accounts() -> Vec<IdlAccountItem>;
+}
+
+macro_rules! impl_idl_build {
+    ($($ty:ty => $idl_ty:ident),* $(,)?) => {
+        $(
+            impl IdlBuild for $ty {
+                fn idl_type() -> IdlType {
+                    IdlType::$idl_ty
+                }
+            }
+        )*
+    };
+}
+
+impl_idl_build!(
+    bool => Bool,
+    u8 => U8,
+    i8 => I8,
+    u16 => U16,
+    i16 => I16,
+    u32 => U32,
+    i32 => I32,
+    f32 => F32,
+    u64 => U64,
+    i64 => I64,
+    f64 => F64,
+    u128 => U128,
+    i128 => I128,
+    String => String,
+    Pubkey => PublicKey,
+);
+
+impl<T: IdlBuild> IdlBuild for Vec<T> {
+    fn idl_type() -> IdlType {
+        match T::idl_type() {
+            IdlType::U8 => IdlType::Bytes,
+            ty => IdlType::Vec(Box::new(ty)),
+        }
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+// Unsized types, for `#[constant]`s such as `&[u8]` and `&str`, which are
+// encoded as their owned counterparts.
+impl<T: IdlBuild + ?Sized> IdlBuild for &T {
+    fn idl_type() -> IdlType {
+        T::idl_type()
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+impl<T: IdlBuild> IdlBuild for [T] {
+    fn idl_type() -> IdlType {
+        Vec::<T>::idl_type()
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+impl IdlBuild for str {
+    fn idl_type() -> IdlType {
+        IdlType::String
+    }
+}
+
+impl<T: IdlBuild> IdlBuild for Option<T> {
+    fn idl_type() -> IdlType {
+        IdlType::Option(Box::new(T::idl_type()))
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+// Borsh serializes boxes as their contents.
+impl<T: IdlBuild> IdlBuild for Box<T> {
+    fn idl_type() -> IdlType {
+        T::idl_type()
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+impl<T: IdlBuild, const N: usize> IdlBuild for [T; N] {
+    fn idl_type() -> IdlType {
+        match T::idl_type() {
+            IdlType::U8 => IdlType::ByteArray(N),
+            ty => IdlType::Array(Box::new(ty), N),
+        }
+    }
+
+    fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+        T::insert_idl_type_defs(defs)
+    }
+}
+
+macro_rules! impl_idl_build_map {
+    ($($map:ident => $idl_ty:ident),* $(,)?) => {
+        $(
+            impl<K: IdlBuild, V: IdlBuild> IdlBuild for $map<K, V> {
+                fn idl_type() -> IdlType {
+                    IdlType::$idl_ty(Box::new(K::idl_type()), Box::new(V::idl_type()))
+                }
+
+                fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+                    K::insert_idl_type_defs(defs);
+                    V::insert_idl_type_defs(defs);
+                }
+            }
+        )*
+    };
+}
+
+impl_idl_build_map!(HashMap => HashMap, BTreeMap => BTreeMap);
+
+macro_rules! impl_idl_build_tuple {
+    ($(($($name:ident),*)),* $(,)?) => {
+        $(
+            impl<$($name: IdlBuild),*> IdlBuild for ($($name,)*) {
+                fn idl_type() -> IdlType {
+                    IdlType::Tuple(vec![$($name::idl_type()),*])
+                }
+
+                #[allow(unused_variables)]
+                fn insert_idl_type_defs(defs: &mut IdlTypeDefs) {
+                    $($name::insert_idl_type_defs(defs);)*
+                }
+            }
+        )*
+    };
+}
+
+impl_idl_build_tuple!(
+    (),
+    (T0),
+    (T0, T1),
+    (T0, T1, T2),
+    (T0, T1, T2, T3),
+    (T0, T1, T2, T3, T4),
+    (T0, T1, T2, T3, T4, T5),
+    (T0, T1, T2, T3, T4, T5, T6),
+    (T0, T1, T2, T3, T4, T5, T6, T7),
+);
+
+/// Returns the fragment for the `#[account]` `T`, along with the types it
+/// uses.
+pub fn account_fragment<T: IdlBuild>() -> IdlFragment {
+    let mut defs = IdlTypeDefs::new();
+    T::insert_idl_type_defs(&mut defs);
+    let name = match T::idl_type() {
+        IdlType::Defined(name) => name,
+        _ => unreachable!("Accounts are user defined types"),
+    };
+    let account = defs.remove(&name).expect("Account type must be defined");
+    IdlFragment {
+        accounts: vec![account],
+        types: defs.into_iter().map(|(_, ty_def)| ty_def).collect(),
+        ..Default::default()
+    }
+}
+
+/// The value of a `#[constant]`, formatted as a Rust literal for the IDL by
+/// `(&ConstValue(&VALUE)).idl_const_value(source)`, with `Debug` if the type
+/// implements it, as the constant's source otherwise.
+#[doc(hidden)]
+pub struct ConstValue<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait DebugConstValue {
+    fn idl_const_value(&self, source: &str) -> String;
+}
+
+impl<T: std::fmt::Debug + ?Sized> DebugConstValue for ConstValue<'_, T> {
+    fn idl_const_value(&self, _source: &str) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[doc(hidden)]
+pub trait SourceConstValue {
+    fn idl_const_value(&self, source: &str) -> String;
+}
+
+impl<T: ?Sized> SourceConstValue for &ConstValue<'_, T> {
+    fn idl_const_value(&self, source: &str) -> String {
+        source.to_string()
+    }
+}
+
+/// Returns the constant for the IDL, given its value formatted with
+/// `ConstValue`. Public keys are formatted as base 58 strings.
+pub fn idl_const(name: &str, ty: IdlType, value: String, docs: Option<Vec<String>>) -> IdlConst {
+    let value = match ty {
+        IdlType::PublicKey => format!("{:?}", value),
+        _ => value,
+    };
+    IdlConst {
+        name: name.to_string(),
+        ty,
+        value,
+        docs,
+    }
+}
+
+/// Prints the fragment for `anchor idl build` to collect.
+pub fn print_fragment(fragment: &IdlFragment) {
+    println!("{}", IDL_FRAGMENT_BEGIN);
+    println!(
+        "{}",
+        serde_json::to_string(fragment).expect("Must serialize")
+    );
+    println!("{}", IDL_FRAGMENT_END);
+}
@@ -39,8 +39,11 @@ mod ctor;
 mod error;
 #[doc(hidden)]
 pub mod idl;
+#[cfg(feature = "idl-build")]
+pub mod idl_build;
 mod loader;
 mod program_account;
+pub mod return_data;
 mod state;
 mod sysvar;
 mod vec;
@@ -53,7 +56,8 @@ pub use crate::program_account::ProgramAccount;
 pub use crate::state::ProgramState;
 pub use crate::sysvar::Sysvar;
 pub use anchor_attribute_access_control::access_control;
-pub use anchor_attribute_account::{account, associated, zero_copy};
+pub use anchor_attribute_account::{account, associated, zero_copy, IdlBuild};
+pub use anchor_attribute_constant::constant;
 pub use anchor_attribute_error::error;
 pub use anchor_attribute_event::{emit, event};
 pub use anchor_attribute_interface::interface;
@@ -64,6 +68,12 @@ pub use anchor_derive_accounts::Accounts;
 pub use borsh::{BorshDeserialize as AnchorDeserialize, BorshSerialize as AnchorSerialize};
 pub use solana_program;
 
+/// The result of an instruction handler. With `#[program(return_data)]`,
+/// handlers returning a value, i.e., `Result<T>` for any `T` other than `()`,
+/// deliver it to their caller via `anchor_lang::return_data`. It's not in the
+/// prelude, so that it doesn't conflict with other `Result` types.
+pub type Result<T, E = ProgramError> = std::result::Result<T, E>;
+
 /// A data structure of validated accounts that can be deserialized from the
 /// input to a Solana program. Implementations of this trait should perform any
 /// and all requisite constraint checks on accounts to ensure the accounts
@@ -204,11 +214,11 @@ pub trait Bump {
 /// All programs should include it via `anchor_lang::prelude::*;`.
 pub mod prelude {
     pub use super::{
-        access_control, account, associated, emit, error, event, interface, program, state,
-        zero_copy, AccountDeserialize, AccountSerialize, Accounts, AccountsExit, AccountsInit,
-        AnchorDeserialize, AnchorSerialize, Context, CpiAccount, CpiContext, CpiState,
-        CpiStateContext, Loader, ProgramAccount, ProgramState, Sysvar, ToAccountInfo,
-        ToAccountInfos, ToAccountMetas,
+        access_control, account, associated, constant, emit, error, event, interface, program,
+        state, zero_copy, AccountDeserialize, AccountSerialize, Accounts, AccountsExit,
+        AccountsInit, AnchorDeserialize, AnchorSerialize, Context, CpiAccount, CpiContext,
+        CpiState, CpiStateContext, IdlBuild, Loader, ProgramAccount, ProgramState, Sysvar,
+        ToAccountInfo, ToAccountInfos, ToAccountMetas,
     };
 
     pub use borsh;
@@ -0,0 +1,101 @@
+//! Return data is a per-transaction buffer a program can write to, for its
+//! caller to read once the program returns. Anchor uses it to return the
+//! value of instructions defined as `Result<T>`, which is delivered to CPI
+//! callers by the generated `cpi` client and logged by the runtime, as
+//! `Program return: <program-id> <base64-data>`, for off-chain callers.
+//!
+//! Requires a runtime with the return data syscalls, i.e., Solana 1.9 or
+//! later, so programs opt in with `#[program(return_data)]`. Off-chain, e.g.,
+//! when testing a program natively, the return data is kept per thread.
+
+use crate::{AnchorDeserialize, AnchorSerialize};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+/// Maximum size of the return data, in bytes.
+pub const MAX_RETURN_DATA: usize = 1024;
+
+#[cfg(not(target_arch = "bpf"))]
+thread_local! {
+    static RETURN_DATA: std::cell::RefCell<Option<(Pubkey, Vec<u8>)>> =
+        std::cell::RefCell::new(None);
+}
+
+/// Sets the return data of the currently executing program, `program_id`.
+/// On-chain, the runtime attributes the data to the executing program
+/// regardless. Setting more than `MAX_RETURN_DATA` bytes aborts the program.
+pub fn set_return_data(program_id: &Pubkey, data: &[u8]) {
+    #[cfg(target_arch = "bpf")]
+    {
+        extern "C" {
+            fn sol_set_return_data(data: *const u8, length: u64);
+        }
+        let _ = program_id;
+        unsafe { sol_set_return_data(data.as_ptr(), data.len() as u64) };
+    }
+    #[cfg(not(target_arch = "bpf"))]
+    {
+        assert!(
+            data.len() <= MAX_RETURN_DATA,
+            "Return data too large: {} bytes",
+            data.len()
+        );
+        let return_data = match data.is_empty() {
+            true => None,
+            false => Some((*program_id, data.to_vec())),
+        };
+        RETURN_DATA.with(|r| *r.borrow_mut() = return_data);
+    }
+}
+
+/// Returns the return data along with the program that set it, if any.
+///
+/// Return data is cleared before every CPI, but not after, so after invoking
+/// a program the return data may have been set by another program further
+/// down the call stack.
+pub fn get_return_data() -> Option<(Pubkey, Vec<u8>)> {
+    #[cfg(target_arch = "bpf")]
+    {
+        extern "C" {
+            fn sol_get_return_data(data: *mut u8, length: u64, program_id: *mut Pubkey) -> u64;
+        }
+        let mut data = [0u8; MAX_RETURN_DATA];
+        let mut program_id = Pubkey::default();
+        let size =
+            unsafe { sol_get_return_data(data.as_mut_ptr(), data.len() as u64, &mut program_id) };
+        match size {
+            0 => None,
+            size => Some((
+                program_id,
+                data[..std::cmp::min(size as usize, MAX_RETURN_DATA)].to_vec(),
+            )),
+        }
+    }
+    #[cfg(not(target_arch = "bpf"))]
+    RETURN_DATA.with(|r| r.borrow().clone())
+}
+
+/// Borsh serializes `value` as the return data of the currently executing
+/// program, `program_id`.
+pub fn set<T: AnchorSerialize>(program_id: &Pubkey, value: &T) -> ProgramResult {
+    let data = value
+        .try_to_vec()
+        .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    set_return_data(program_id, &data);
+    Ok(())
+}
+
+/// Deserializes the value returned by `program_id`, which must be the program
+/// that was just invoked. Returns `IncorrectProgramId` if the return data was
+/// set by another program.
+pub fn get<T: AnchorDeserialize>(program_id: &Pubkey) -> Result<T, ProgramError> {
+    let data = match get_return_data() {
+        Some((id, data)) if id == *program_id => data,
+        Some(_) => return Err(ProgramError::IncorrectProgramId),
+        // Values serialized to zero bytes, e.g., unit structs, don't set any
+        // return data.
+        None => vec![],
+    };
+    T::try_from_slice(&data).map_err(|e| ProgramError::BorshIoError(e.to_string()))
+}
@@ -1,3 +1,4 @@
+use crate::codegen::idl_build;
 use crate::{
     AccountField, AccountsStruct, CompositeField, Constraint, ConstraintAssociated,
     ConstraintBelongsTo, ConstraintExecutable, ConstraintLiteral, ConstraintOwner,
@@ -280,6 +281,13 @@ pub fn generate(accs: AccountsStruct) -> proc_macro2::TokenStream {
             .collect()
     };
 
+    // The IDL of accounts is built by `Field::idl_account`, which requires
+    // the `idl` feature, enabled by the `Accounts` derive.
+    #[cfg(feature = "idl")]
+    let idl_build = idl_build::generate_accounts(&accs, &combined_generics, &strct_generics);
+    #[cfg(not(feature = "idl"))]
+    let idl_build = quote! {};
+
     quote! {
         /// An internal, Anchor generated module. This is used (as an
         /// implementation detail), to generate a struct for a given
@@ -359,6 +367,8 @@ pub fn generate(accs: AccountsStruct) -> proc_macro2::TokenStream {
                 Ok(())
             }
         }
+
+        #idl_build
     }
 }
 
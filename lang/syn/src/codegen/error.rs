@@ -1,3 +1,4 @@
+use crate::codegen::idl_build;
 use crate::Error;
 use quote::quote;
 
@@ -32,6 +33,8 @@ pub fn generate(error: Error) -> proc_macro2::TokenStream {
         })
         .collect();
 
+    let idl_build = idl_build::generate_error(&error);
+
     quote! {
         /// Anchor generated Result to be used as the return type for the
         /// program.
@@ -79,5 +82,7 @@ pub fn generate(error: Error) -> proc_macro2::TokenStream {
                 err.into()
             }
         }
+
+        #idl_build
     }
 }
@@ -0,0 +1,556 @@
+//! Code generated for the `idl-build` feature, building the IDL from compiled
+//! type information rather than parsing the program's source.
+//!
+//! Each type used in the IDL implements `anchor_lang::idl_build::IdlBuild`,
+//! and each macro contributing to the IDL generates a test printing its
+//! `IdlFragment`, which `anchor idl build` runs and collects.
+
+use crate::parser;
+use crate::{AccountField, AccountsStruct, Error, Program};
+use heck::{MixedCase, SnakeCase};
+use quote::{format_ident, quote, ToTokens};
+use std::collections::HashMap;
+
+// Implements `IdlBuild` for a user defined struct.
+pub fn generate_struct(strct: &syn::ItemStruct) -> proc_macro2::TokenStream {
+    generate_struct_with_repr(strct, parser::zero_copy_repr(&strct.attrs))
+}
+
+// Implements `IdlBuild` for a `#[zero_copy]` struct, whose `#[repr(packed)]`
+// is added by the macro.
+pub fn generate_zero_copy_struct(strct: &syn::ItemStruct) -> proc_macro2::TokenStream {
+    generate_struct_with_repr(strct, Some("packed"))
+}
+
+fn generate_struct_with_repr(
+    strct: &syn::ItemStruct,
+    repr: Option<&str>,
+) -> proc_macro2::TokenStream {
+    let (fields, field_tys) = generate_fields(&strct.fields, true);
+    let ty_def = quote! {
+        anchor_lang::idl_build::IdlTypeDefTy::Struct {
+            fields: #fields,
+        }
+    };
+    generate_impl(
+        &strct.ident,
+        &strct.generics,
+        ty_def,
+        &field_tys,
+        docs(&strct.attrs),
+        repr,
+    )
+}
+
+// Implements `IdlBuild` for a user defined enum.
+pub fn generate_enum(enm: &syn::ItemEnum) -> proc_macro2::TokenStream {
+    let mut field_tys: Vec<&syn::Type> = vec![];
+    let variants: Vec<proc_macro2::TokenStream> = enm
+        .variants
+        .iter()
+        .map(|variant| {
+            let name = variant.ident.to_string();
+            let (fields, tys) = generate_fields(&variant.fields, false);
+            field_tys.extend(tys);
+            quote! {
+                anchor_lang::idl_build::EnumVariant {
+                    name: #name.to_string(),
+                    fields: #fields,
+                }
+            }
+        })
+        .collect();
+    let ty_def = quote! {
+        anchor_lang::idl_build::IdlTypeDefTy::Enum {
+            variants: vec![#(#variants),*],
+        }
+    };
+    generate_impl(
+        &enm.ident,
+        &enm.generics,
+        ty_def,
+        &field_tys,
+        docs(&enm.attrs),
+        None,
+    )
+}
+
+// Returns the `Option<EnumFields>` of a struct or enum variant, along with
+// the field types.
+fn generate_fields(
+    fields: &syn::Fields,
+    mixed_case: bool,
+) -> (proc_macro2::TokenStream, Vec<&syn::Type>) {
+    match fields {
+        syn::Fields::Unit => (quote! { None }, vec![]),
+        syn::Fields::Unnamed(fields) => {
+            let tys: Vec<&syn::Type> = fields.unnamed.iter().map(|f| &f.ty).collect();
+            let fields = quote! {
+                Some(anchor_lang::idl_build::EnumFields::Tuple(vec![
+                    #(<#tys as anchor_lang::idl_build::IdlBuild>::idl_type()),*
+                ]))
+            };
+            (fields, tys)
+        }
+        syn::Fields::Named(fields) => {
+            let names = fields.named.iter().map(|f| {
+                let name = f.ident.as_ref().unwrap().to_string();
+                match mixed_case {
+                    true => name.to_mixed_case(),
+                    false => name,
+                }
+            });
+            let tys: Vec<&syn::Type> = fields.named.iter().map(|f| &f.ty).collect();
+            let field_docs = fields.named.iter().map(|f| docs(&f.attrs));
+            let fields = quote! {
+                Some(anchor_lang::idl_build::EnumFields::Named(vec![
+                    #(anchor_lang::idl_build::IdlField {
+                        name: #names.to_string(),
+                        ty: <#tys as anchor_lang::idl_build::IdlBuild>::idl_type(),
+                        docs: #field_docs,
+                    }),*
+                ]))
+            };
+            (fields, tys)
+        }
+    }
+}
+
+// For generic types, the type definition is built by substituting each type
+// parameter with a marker type, whose IDL type is `IdlType::Generic`. So
+// that markers satisfy the bounds of the type, they implement the Borsh and
+// common std traits. Zero copy types, i.e., those with a `repr`, leave their
+// layout to be computed once all type definitions are collected.
+fn generate_impl(
+    name: &syn::Ident,
+    generics: &syn::Generics,
+    ty_def: proc_macro2::TokenStream,
+    field_tys: &[&syn::Type],
+    docs: proc_macro2::TokenStream,
+    repr: Option<&str>,
+) -> proc_macro2::TokenStream {
+    let name_str = name.to_string();
+    if generics.const_params().next().is_some() {
+        return unsupported("Const generics aren't supported by the idl-build feature");
+    }
+    let params: Vec<&syn::Ident> = generics.type_params().map(|p| &p.ident).collect();
+    let param_strs: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+    let markers: HashMap<String, syn::Ident> = params
+        .iter()
+        .map(|p| (p.to_string(), format_ident!("__AnchorIdlGeneric{}", p)))
+        .collect();
+    let marker_idents = param_strs.iter().map(|p| &markers[p]);
+    let ty_def = replace_idents(ty_def, &markers);
+    let field_tys = field_tys
+        .iter()
+        .map(|ty| replace_idents(quote! { #ty }, &markers));
+
+    let idl_type = match params.is_empty() {
+        true => quote! {
+            anchor_lang::idl_build::IdlType::Defined(#name_str.to_string())
+        },
+        false => quote! {
+            anchor_lang::idl_build::IdlType::DefinedWithTypeArgs {
+                name: #name_str.to_string(),
+                args: vec![#(<#params as anchor_lang::idl_build::IdlBuild>::idl_type()),*],
+            }
+        },
+    };
+    let (serialization, repr) = match repr {
+        None => (quote! { Borsh }, quote! { None }),
+        Some("packed") => (
+            quote! { Bytemuck },
+            quote! { Some(anchor_lang::idl_build::IdlRepr::Packed) },
+        ),
+        Some(_) => (
+            quote! { Bytemuck },
+            quote! { Some(anchor_lang::idl_build::IdlRepr::C) },
+        ),
+    };
+    let mut bounded = generics.clone();
+    for param in bounded.type_params_mut() {
+        param
+            .bounds
+            .push(syn::parse_quote!(anchor_lang::idl_build::IdlBuild));
+    }
+    let (impl_generics, _, where_clause) = bounded.split_for_impl();
+    let (_, ty_generics, _) = generics.split_for_impl();
+
+    quote! {
+        #[cfg(feature = "idl-build")]
+        impl #impl_generics anchor_lang::idl_build::IdlBuild for #name #ty_generics #where_clause {
+            fn idl_type() -> anchor_lang::idl_build::IdlType {
+                #idl_type
+            }
+
+            fn insert_idl_type_defs(defs: &mut anchor_lang::idl_build::IdlTypeDefs) {
+                #(<#params as anchor_lang::idl_build::IdlBuild>::insert_idl_type_defs(defs);)*
+                // Guard against recursive types.
+                if defs.contains_key(#name_str) {
+                    return;
+                }
+                #(
+                    #[derive(
+                        anchor_lang::AnchorSerialize, anchor_lang::AnchorDeserialize, Clone, Copy,
+                        Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+                    )]
+                    struct #marker_idents;
+                    impl anchor_lang::idl_build::IdlBuild for #marker_idents {
+                        fn idl_type() -> anchor_lang::idl_build::IdlType {
+                            anchor_lang::idl_build::IdlType::Generic(#param_strs.to_string())
+                        }
+                    }
+                )*
+                defs.insert(
+                    #name_str.to_string(),
+                    anchor_lang::idl_build::IdlTypeDef {
+                        name: #name_str.to_string(),
+                        ty: #ty_def,
+                        docs: #docs,
+                        generics: vec![#(#param_strs.to_string()),*],
+                        serialization: anchor_lang::idl_build::IdlSerialization::#serialization,
+                        repr: #repr,
+                        layout: None,
+                    },
+                );
+                #(<#field_tys as anchor_lang::idl_build::IdlBuild>::insert_idl_type_defs(defs);)*
+            }
+        }
+    }
+}
+
+// Replaces every identifier in `tokens` found in `idents`.
+fn replace_idents(
+    tokens: proc_macro2::TokenStream,
+    idents: &HashMap<String, syn::Ident>,
+) -> proc_macro2::TokenStream {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            proc_macro2::TokenTree::Ident(ident) => match idents.get(&ident.to_string()) {
+                Some(replacement) => proc_macro2::TokenTree::Ident(replacement.clone()),
+                None => proc_macro2::TokenTree::Ident(ident),
+            },
+            proc_macro2::TokenTree::Group(group) => {
+                let mut replaced = proc_macro2::Group::new(
+                    group.delimiter(),
+                    replace_idents(group.stream(), idents),
+                );
+                replaced.set_span(group.span());
+                proc_macro2::TokenTree::Group(replaced)
+            }
+            tt => tt,
+        })
+        .collect()
+}
+
+// Prints the `#[account]` struct, along with the types it uses. The struct
+// must implement `IdlBuild`, see `generate_struct`.
+pub fn generate_account(strct: &syn::ItemStruct) -> proc_macro2::TokenStream {
+    let name = &strct.ident;
+    generate_print(
+        &format!("account_{}", name.to_string().to_snake_case()),
+        quote! {
+            anchor_lang::idl_build::account_fragment::<#name>()
+        },
+    )
+}
+
+// Prints the `#[event]` struct, along with the types it uses.
+pub fn generate_event(strct: &syn::ItemStruct) -> proc_macro2::TokenStream {
+    let name = strct.ident.to_string();
+    let fields = match &strct.fields {
+        syn::Fields::Named(fields) => &fields.named,
+        _ => return unsupported("Event fields must be named"),
+    };
+    let field_names = fields.iter().map(|f| f.ident.as_ref().unwrap().to_string());
+    let field_tys: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let indexes = fields.iter().map(|f| {
+        f.attrs
+            .first()
+            .map(|attr| attr.path.is_ident("index"))
+            .unwrap_or(false)
+    });
+    generate_print(
+        &format!("event_{}", name.to_snake_case()),
+        quote! {
+            let mut defs = anchor_lang::idl_build::IdlTypeDefs::new();
+            #(<#field_tys as anchor_lang::idl_build::IdlBuild>::insert_idl_type_defs(&mut defs);)*
+            anchor_lang::idl_build::IdlFragment {
+                events: vec![anchor_lang::idl_build::IdlEvent {
+                    name: #name.to_string(),
+                    fields: vec![
+                        #(anchor_lang::idl_build::IdlEventField {
+                            name: #field_names.to_string(),
+                            ty: <#field_tys as anchor_lang::idl_build::IdlBuild>::idl_type(),
+                            index: #indexes,
+                        }),*
+                    ],
+                }],
+                types: defs.into_iter().map(|(_, ty_def)| ty_def).collect(),
+                ..Default::default()
+            }
+        },
+    )
+}
+
+// Prints the `#[constant]`, along with the types it uses. The value is the
+// one computed by the compiler, see `anchor_lang::idl_build::ConstValue`.
+pub fn generate_constant(item: &syn::ItemConst) -> proc_macro2::TokenStream {
+    let ident = &item.ident;
+    let name = ident.to_string();
+    let ty = &item.ty;
+    let source = parser::tts_to_source(item.expr.to_token_stream());
+    let docs = docs(&item.attrs);
+    generate_print(
+        &format!("constant_{}", name.to_snake_case()),
+        quote! {
+            use anchor_lang::idl_build::{DebugConstValue as _, SourceConstValue as _};
+            let mut defs = anchor_lang::idl_build::IdlTypeDefs::new();
+            <#ty as anchor_lang::idl_build::IdlBuild>::insert_idl_type_defs(&mut defs);
+            let value = (&anchor_lang::idl_build::ConstValue(&#ident)).idl_const_value(#source);
+            anchor_lang::idl_build::IdlFragment {
+                constants: vec![anchor_lang::idl_build::idl_const(
+                    #name,
+                    <#ty as anchor_lang::idl_build::IdlBuild>::idl_type(),
+                    value,
+                    #docs,
+                )],
+                types: defs.into_iter().map(|(_, ty_def)| ty_def).collect(),
+                ..Default::default()
+            }
+        },
+    )
+}
+
+// Prints the error codes of the `#[error]` enum.
+pub fn generate_error(error: &Error) -> proc_macro2::TokenStream {
+    let errors = error.codes.iter().map(|code| {
+        let id = 100 + code.id;
+        let name = code.ident.to_string();
+        let msg = match &code.msg {
+            None => quote! { None },
+            Some(msg) => quote! { Some(#msg.to_string()) },
+        };
+        quote! {
+            anchor_lang::idl_build::IdlErrorCode {
+                code: #id,
+                name: #name.to_string(),
+                msg: #msg,
+            }
+        }
+    });
+    generate_print(
+        &format!("error_{}", error.ident.to_string().to_snake_case()),
+        quote! {
+            anchor_lang::idl_build::IdlFragment {
+                errors: vec![#(#errors),*],
+                ..Default::default()
+            }
+        },
+    )
+}
+
+// Prints the program's name and instructions, along with the types of the
+// instruction arguments.
+pub fn generate_program(program: &Program) -> proc_macro2::TokenStream {
+    if program.state.is_some() {
+        return unsupported("#[state] isn't supported by the idl-build feature");
+    }
+    let name = program.name.to_string();
+    let program_docs = docs(&program.program_mod.attrs);
+    let mut arg_tys: Vec<&syn::Type> = vec![];
+    let ixs: Vec<proc_macro2::TokenStream> = program
+        .ixs
+        .iter()
+        .map(|ix| {
+            let ix_name = ix.ident.to_string().to_mixed_case();
+            let ix_docs = docs(&ix.raw_method.attrs);
+            let anchor = &ix.anchor_ident;
+            let args = ix.args.iter().map(|arg| {
+                let arg_name = arg.name.to_string().to_mixed_case();
+                let ty = &arg.raw_arg.ty;
+                arg_tys.push(ty);
+                quote! {
+                    anchor_lang::idl_build::IdlField {
+                        name: #arg_name.to_string(),
+                        ty: <#ty as anchor_lang::idl_build::IdlBuild>::idl_type(),
+                        docs: None,
+                    }
+                }
+            });
+            let args: Vec<proc_macro2::TokenStream> = args.collect();
+            let returns = match &ix.returns {
+                None => quote! { None },
+                Some(ty) => {
+                    arg_tys.push(ty);
+                    quote! { Some(<#ty as anchor_lang::idl_build::IdlBuild>::idl_type()) }
+                }
+            };
+            quote! {
+                anchor_lang::idl_build::IdlIx {
+                    name: #ix_name.to_string(),
+                    docs: #ix_docs,
+                    accounts: <#anchor as anchor_lang::idl_build::IdlBuildAccounts>::idl_accounts(),
+                    args: vec![#(#args),*],
+                    returns: #returns,
+                }
+            }
+        })
+        .collect();
+    generate_print(
+        "program",
+        quote! {
+            let mut defs = anchor_lang::idl_build::IdlTypeDefs::new();
+            #(<#arg_tys as anchor_lang::idl_build::IdlBuild>::insert_idl_type_defs(&mut defs);)*
+            anchor_lang::idl_build::IdlFragment {
+                name: Some(#name.to_string()),
+                docs: #program_docs,
+                instructions: vec![#(#ixs),*],
+                types: defs.into_iter().map(|(_, ty_def)| ty_def).collect(),
+                ..Default::default()
+            }
+        },
+    )
+}
+
+// Implements `IdlBuildAccounts` for a struct deriving `Accounts`.
+#[cfg(feature = "idl")]
+pub fn generate_accounts(
+    accs: &AccountsStruct,
+    combined_generics: &proc_macro2::TokenStream,
+    strct_generics: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let name = &accs.ident;
+    let accounts = accs.fields.iter().map(|f| match f {
+        AccountField::AccountsStruct(comp_f) => {
+            let name = comp_f.ident.to_string().to_mixed_case();
+            let ty = &comp_f.raw_field.ty;
+            let docs = docs(&comp_f.raw_field.attrs);
+            quote! {
+                anchor_lang::idl_build::IdlAccountItem::IdlAccounts(
+                    anchor_lang::idl_build::IdlAccounts {
+                        name: #name.to_string(),
+                        docs: #docs,
+                        accounts: <#ty as anchor_lang::idl_build::IdlBuildAccounts>::idl_accounts(),
+                    }
+                )
+            }
+        }
+        // Built as in the parsed IDL, and embedded as JSON.
+        AccountField::Field(f) => {
+            let acc = serde_json::to_string(&f.idl_account()).expect("Must serialize");
+            quote! {
+                anchor_lang::idl_build::IdlAccountItem::IdlAccount(
+                    anchor_lang::idl_build::serde_json::from_str(#acc).unwrap()
+                )
+            }
+        }
+    });
+    quote! {
+        #[cfg(feature = "idl-build")]
+        impl#combined_generics anchor_lang::idl_build::IdlBuildAccounts for #name#strct_generics {
+            fn idl_accounts() -> Vec<anchor_lang::idl_build::IdlAccountItem> {
+                vec![#(#accounts),*]
+            }
+        }
+    }
+}
+
+// Generates the test printing the fragment built by the `fragment` block.
+fn generate_print(name: &str, fragment: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let fn_name = format_ident!("__anchor_private_print_idl_{}", name);
+    quote! {
+        #[cfg(feature = "idl-build")]
+        #[test]
+        fn #fn_name() {
+            let fragment = { #fragment };
+            anchor_lang::idl_build::print_fragment(&fragment);
+        }
+    }
+}
+
+fn docs(attrs: &[syn::Attribute]) -> proc_macro2::TokenStream {
+    match parser::docs(attrs) {
+        None => quote! { None },
+        Some(docs) => quote! { Some(vec![#(#docs.to_string()),*]) },
+    }
+}
+
+fn unsupported(msg: &str) -> proc_macro2::TokenStream {
+    quote! {
+        #[cfg(feature = "idl-build")]
+        compile_error!(#msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn struct_impl() {
+        let strct: syn::ItemStruct = syn::parse_quote! {
+            pub struct Data {
+                pub some_field: u64,
+                pub other: Vec<Other>,
+            }
+        };
+        let item_impl: syn::ItemImpl = syn::parse2(generate_struct(&strct)).unwrap();
+        assert_eq!(
+            parser::tts_to_string(&item_impl.trait_.as_ref().unwrap().1),
+            "anchor_lang :: idl_build :: IdlBuild"
+        );
+        let body = parser::tts_to_string(&item_impl);
+        assert!(body.contains("\"someField\""));
+        // The types of the fields are inserted along with the struct.
+        assert!(body.contains(
+            "< Vec < Other > as anchor_lang :: idl_build :: IdlBuild > :: insert_idl_type_defs"
+        ));
+    }
+
+    #[test]
+    fn printed_fragments() {
+        let strct: syn::ItemStruct = syn::parse_quote! {
+            pub struct MyAccount {
+                pub data: u64,
+            }
+        };
+        let print_fn: syn::ItemFn = syn::parse2(generate_account(&strct)).unwrap();
+        assert_eq!(
+            print_fn.sig.ident,
+            "__anchor_private_print_idl_account_my_account"
+        );
+        assert!(print_fn.attrs.iter().any(|attr| attr.path.is_ident("test")));
+
+        let strct: syn::ItemStruct = syn::parse_quote! {
+            pub struct MyEvent(u64);
+        };
+        assert!(generate_event(&strct).to_string().contains("compile_error"));
+    }
+
+    #[test]
+    fn generic_struct_impl() {
+        let strct: syn::ItemStruct = syn::parse_quote! {
+            pub struct Pair<T> {
+                pub first: T,
+                pub second: Wrapper<Option<T>>,
+            }
+        };
+        let body = generate_struct(&strct).to_string();
+        // Type parameters are replaced by markers in the type definition.
+        assert!(body.contains(
+            "< Wrapper < Option < __AnchorIdlGenericT > > as anchor_lang :: idl_build :: IdlBuild >"
+        ));
+        assert!(body.contains("impl < T : anchor_lang :: idl_build :: IdlBuild >"));
+
+        let strct: syn::ItemStruct = syn::parse_quote! {
+            pub struct Buf<const N: usize> {
+                pub data: [u8; N],
+            }
+        };
+        assert!(generate_struct(&strct)
+            .to_string()
+            .contains("compile_error"));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod accounts;
 pub mod error;
+pub mod idl_build;
 pub mod program;
@@ -1,7 +1,9 @@
+use crate::codegen::idl_build;
 use crate::parser;
 use crate::{IxArg, Program, State, StateIx};
 use heck::{CamelCase, SnakeCase};
-use quote::quote;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
 
 // Namespace for calculating state instruction sighash signatures.
 const SIGHASH_STATE_NAMESPACE: &str = "state";
@@ -10,7 +12,8 @@ const SIGHASH_STATE_NAMESPACE: &str = "state";
 // not affecting program state.
 const SIGHASH_GLOBAL_NAMESPACE: &str = "global";
 
-pub fn generate(program: Program) -> proc_macro2::TokenStream {
+pub fn generate(mut program: Program) -> proc_macro2::TokenStream {
+    let return_data_check = generate_return_data_check(&mut program);
     let mod_name = &program.name;
     let dispatch = generate_dispatch(&program);
     let handlers_non_inlined = generate_non_inlined_handlers(&program);
@@ -18,8 +21,11 @@ pub fn generate(program: Program) -> proc_macro2::TokenStream {
     let ixs = generate_ixs(&program);
     let cpi = generate_cpi(&program);
     let accounts = generate_accounts(&program);
+    let idl_build = idl_build::generate_program(&program);
 
     quote! {
+        #return_data_check
+
         // TODO: remove once we allow segmented paths in `Accounts` structs.
         use #mod_name::*;
 
@@ -110,7 +116,30 @@ pub fn generate(program: Program) -> proc_macro2::TokenStream {
         #methods
 
         #cpi
+
+        #idl_build
+    }
+}
+
+// Return data requires Solana 1.9 or later, so instructions can only return
+// values with `#[program(return_data)]`. Without it, each value returning
+// instruction is an error and is otherwise generated as returning nothing.
+fn generate_return_data_check(program: &mut Program) -> proc_macro2::TokenStream {
+    if program.return_data {
+        return quote! {};
     }
+    program
+        .ixs
+        .iter_mut()
+        .filter_map(|ix| ix.returns.take())
+        .map(|ty| {
+            quote_spanned! { ty.span() =>
+                compile_error!(
+                    "Instructions returning a value require `#[program(return_data)]`, which requires Solana 1.9 or later"
+                );
+            }
+        })
+        .collect()
 }
 
 pub fn generate_dispatch(program: &Program) -> proc_macro2::TokenStream {
@@ -345,6 +374,16 @@ pub fn generate_non_inlined_handlers(program: &Program) -> proc_macro2::TokenStr
                         __idl_set_buffer(program_id, &mut accounts)?;
                         accounts.exit(program_id)?;
                     },
+                    anchor_lang::idl::IdlInstruction::Close => {
+                        let mut accounts = anchor_lang::idl::IdlCloseBuffer::try_accounts(program_id, &mut accounts)?;
+                        __idl_close_buffer(program_id, &mut accounts)?;
+                        accounts.exit(program_id)?;
+                    },
+                    anchor_lang::idl::IdlInstruction::Resize { data_len } => {
+                        let mut accounts = anchor_lang::idl::IdlResize::try_accounts(program_id, &mut accounts)?;
+                        __idl_resize(program_id, &mut accounts, data_len)?;
+                        accounts.exit(program_id)?;
+                    },
                 }
                 Ok(())
             }
@@ -366,6 +405,13 @@ pub fn generate_non_inlined_handlers(program: &Program) -> proc_macro2::TokenStr
                 if program_id != accounts.program.key {
                     return Err(anchor_lang::solana_program::program_error::ProgramError::Custom(98)); // todo proper error
                 }
+                // The IDL account is being resized, so only its authority may
+                // recreate it.
+                if accounts.resize.key != &anchor_lang::idl::IdlAccount::resize_address(program_id)
+                    || accounts.resize.lamports() != 0
+                {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::AccountAlreadyInitialized);
+                }
                 // Create the IDL's account.
                 let from = accounts.from.key;
                 let (base, nonce) = Pubkey::find_program_address(&[], program_id);
@@ -456,6 +502,113 @@ pub fn generate_non_inlined_handlers(program: &Program) -> proc_macro2::TokenStr
                 accounts.idl.data = accounts.buffer.data.clone();
                 Ok(())
             }
+
+            // Closes a buffer. The canonical IDL account can't be closed, nor
+            // can the resize account, which holds the IDL while it's resized.
+            #[inline(never)]
+            pub fn __idl_close_buffer(
+                program_id: &Pubkey,
+                accounts: &mut anchor_lang::idl::IdlCloseBuffer,
+            ) -> ProgramResult {
+                let buffer = accounts.buffer.to_account_info();
+                if buffer.key == &anchor_lang::idl::IdlAccount::address(program_id)
+                    || buffer.key == &anchor_lang::idl::IdlAccount::resize_address(program_id)
+                {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::InvalidArgument);
+                }
+                let dst_starting_lamports = accounts.sol_destination.lamports();
+                **accounts.sol_destination.lamports.borrow_mut() = dst_starting_lamports
+                    .checked_add(buffer.lamports())
+                    .ok_or(anchor_lang::solana_program::program_error::ProgramError::InvalidArgument)?;
+                **buffer.lamports.borrow_mut() = 0;
+                Ok(())
+            }
+
+            // Moves the canonical IDL account into the resize account, with
+            // room for `data_len` bytes of IDL data, or, if it was already
+            // moved, recreates the IDL account with the resize account's size
+            // and contents. The authority pays for the new account and
+            // receives the lamports of the closed one.
+            #[inline(never)]
+            pub fn __idl_resize(
+                program_id: &Pubkey,
+                accounts: &mut anchor_lang::idl::IdlResize,
+                data_len: u64,
+            ) -> ProgramResult {
+                if program_id != accounts.program.key
+                    || accounts.idl.key != &anchor_lang::idl::IdlAccount::address(program_id)
+                    || accounts.resize.key != &anchor_lang::idl::IdlAccount::resize_address(program_id)
+                {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::InvalidArgument);
+                }
+                // Space: account discriminator || authority pubkey || vec len || vec data
+                let space = 8 + 32 + 4 + data_len as usize;
+                let is_moved = accounts.idl.lamports() == 0;
+                let (src, dst, seed) = match is_moved {
+                    false => (&accounts.idl, &accounts.resize, anchor_lang::idl::IdlAccount::resize_seed()),
+                    true => (&accounts.resize, &accounts.idl, anchor_lang::idl::IdlAccount::seed()),
+                };
+                if src.owner != program_id {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::IncorrectProgramId);
+                }
+                let idl_account = {
+                    let src_data = src.try_borrow_data()?;
+                    let mut src_data_slice: &[u8] = &src_data;
+                    anchor_lang::idl::IdlAccount::try_deserialize(&mut src_data_slice)?
+                };
+                if &idl_account.authority != accounts.authority.key {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::Custom(1)); // todo: error codes
+                }
+                // The IDL must fit and, when moving it back, the size must be
+                // the one given when moving it.
+                if idl_account.data.len() > data_len as usize || (is_moved && src.data_len() != space) {
+                    return Err(anchor_lang::solana_program::program_error::ProgramError::InvalidArgument);
+                }
+
+                // Create the destination account.
+                let (base, nonce) = Pubkey::find_program_address(&[], program_id);
+                let lamports = accounts.rent.minimum_balance(space);
+                let seeds = &[&[nonce][..]];
+                let ix = anchor_lang::solana_program::system_instruction::create_account_with_seed(
+                    accounts.authority.key,
+                    dst.key,
+                    &base,
+                    seed,
+                    lamports,
+                    space as u64,
+                    program_id,
+                );
+                anchor_lang::solana_program::program::invoke_signed(
+                    &ix,
+                    &[
+                        accounts.authority.clone(),
+                        dst.clone(),
+                        accounts.base.clone(),
+                        accounts.system_program.clone(),
+                    ],
+                    &[seeds],
+                )?;
+
+                // Copy the IDL.
+                {
+                    let mut data = dst.try_borrow_mut_data()?;
+                    let dst_data: &mut [u8] = &mut data;
+                    let mut cursor = std::io::Cursor::new(dst_data);
+                    idl_account.try_serialize(&mut cursor)?;
+                }
+
+                // Close the source account.
+                let authority_starting_lamports = accounts.authority.lamports();
+                **accounts.authority.lamports.borrow_mut() = authority_starting_lamports
+                    .checked_add(src.lamports())
+                    .ok_or(anchor_lang::solana_program::program_error::ProgramError::InvalidArgument)?;
+                **src.lamports.borrow_mut() = 0;
+                for byte in src.try_borrow_mut_data()?.iter_mut() {
+                    *byte = 0;
+                }
+
+                Ok(())
+            }
         }
     };
     // Constructor handler.
@@ -829,6 +982,25 @@ pub fn generate_non_inlined_handlers(program: &Program) -> proc_macro2::TokenStr
             let ix_arg_names: Vec<&syn::Ident> = ix.args.iter().map(|arg| &arg.name).collect();
             let ix_name = &ix.raw_method.sig.ident;
             let anchor = &ix.anchor_ident;
+            // Instructions returning a value set it as the return data, once
+            // the accounts are persisted.
+            let handler = quote! {
+                #program_name::#ix_name(
+                    Context::new(program_id, &mut accounts, remaining_accounts),
+                    #(#ix_arg_names),*
+                )
+            };
+            let handler = match &ix.returns {
+                None => quote! {
+                    #handler?;
+                    accounts.exit(program_id)
+                },
+                Some(_) => quote! {
+                    let ret = #handler?;
+                    accounts.exit(program_id)?;
+                    anchor_lang::return_data::set(program_id, &ret)
+                },
+            };
 
             quote! {
                 #[inline(never)]
@@ -839,11 +1011,7 @@ pub fn generate_non_inlined_handlers(program: &Program) -> proc_macro2::TokenStr
                 ) -> ProgramResult {
                     let mut remaining_accounts: &[AccountInfo] = accounts;
                     let mut accounts = #anchor::try_accounts(program_id, &mut remaining_accounts)?;
-                    #program_name::#ix_name(
-                        Context::new(program_id, &mut accounts, remaining_accounts),
-                        #(#ix_arg_names),*
-                    )?;
-                    accounts.exit(program_id)
+                    #handler
                 }
             }
         })
@@ -1309,11 +1477,30 @@ fn generate_cpi(program: &Program) -> proc_macro2::TokenStream {
                 let sighash_arr = sighash(SIGHASH_GLOBAL_NAMESPACE, &name);
                 let sighash_tts: proc_macro2::TokenStream =
                     format!("{:?}", sighash_arr).parse().unwrap();
+                // Instructions returning a value return it to the caller,
+                // deserialized from the return data of the invoked program.
+                let invoke = quote! {
+                    anchor_lang::solana_program::program::invoke_signed(
+                        &ix,
+                        &acc_infos,
+                        ctx.signer_seeds,
+                    )
+                };
+                let (ret_ty, invoke) = match &ix.returns {
+                    None => (quote! { ProgramResult }, invoke),
+                    Some(ty) => (
+                        quote! { std::result::Result<#ty, ProgramError> },
+                        quote! {
+                            #invoke?;
+                            anchor_lang::return_data::get(ctx.program.key)
+                        },
+                    ),
+                };
                 quote! {
                     pub fn #method_name<'a, 'b, 'c, 'info>(
                         ctx: CpiContext<'a, 'b, 'c, 'info, #accounts_ident<'info>>,
                         #(#args),*
-                    ) -> ProgramResult {
+                    ) -> #ret_ty {
                         let ix = {
                             let ix = instruction::#ix_variant;
                             let mut ix_data = AnchorSerialize::try_to_vec(&ix)
@@ -1329,11 +1516,7 @@ fn generate_cpi(program: &Program) -> proc_macro2::TokenStream {
                         };
                         let mut acc_infos = ctx.accounts.to_account_infos();
                         acc_infos.push(ctx.program.clone());
-                        anchor_lang::solana_program::program::invoke_signed(
-                            &ix,
-                            &acc_infos,
-                            ctx.signer_seeds,
-                        )
+                        #invoke
                     }
                 }
             };
@@ -1377,3 +1560,32 @@ fn sighash_ctor() -> [u8; 8] {
     sighash.copy_from_slice(&crate::hash::hash(preimage.as_bytes()).to_bytes()[..8]);
     sighash
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_require_return_data() {
+        let program_mod: syn::ItemMod = syn::parse_quote! {
+            pub mod returns {
+                use super::*;
+
+                pub fn unit(ctx: Context<Empty>) -> Result<()> {
+                    Ok(())
+                }
+
+                pub fn amount(ctx: Context<Empty>) -> Result<u64> {
+                    Ok(1)
+                }
+            }
+        };
+        let code = generate(parser::program::parse(program_mod.clone(), false)).to_string();
+        assert_eq!(code.matches("compile_error").count(), 1);
+        assert!(!code.contains("anchor_lang :: return_data"));
+
+        let code = generate(parser::program::parse(program_mod, true)).to_string();
+        assert!(!code.contains("compile_error"));
+        assert!(code.contains("anchor_lang :: return_data"));
+    }
+}
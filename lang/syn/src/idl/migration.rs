@@ -0,0 +1,139 @@
+// Migrations of IDLs of previous specs to the current one.
+
+use super::{Idl, IDL_SPEC};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+// Migrates the JSON of an IDL from a spec, if any, to the next.
+type Migration = (Option<&'static str>, &'static str, fn(&mut Value));
+
+// Migrations from each spec to the next, in order. IDLs predating the `spec`
+// field have none.
+const MIGRATIONS: &[Migration] = &[(None, "0.1.0", byte_arrays)];
+
+// Upgrades the JSON of an IDL of the current or any previous spec to the
+// current `Idl`.
+pub fn migrate(mut idl: Value) -> Result<Idl> {
+    let obj = idl
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid IDL: expected an object"))?;
+    let mut spec = match obj.get("spec") {
+        None => None,
+        Some(Value::String(spec)) => Some(spec.clone()),
+        Some(spec) => return Err(anyhow!("Invalid IDL spec: {}", spec)),
+    };
+    for (from, to, migration) in MIGRATIONS {
+        if spec.as_deref() == *from {
+            migration(&mut idl);
+            spec = Some(to.to_string());
+        }
+    }
+    match spec.as_deref() {
+        Some(IDL_SPEC) => {}
+        Some(spec) => {
+            return Err(anyhow!(
+                "Unsupported IDL spec {}, the latest supported is {}",
+                spec,
+                IDL_SPEC
+            ))
+        }
+        None => unreachable!("IDLs without a spec are migrated"),
+    }
+    idl["spec"] = json!(IDL_SPEC);
+    serde_json::from_value(idl).map_err(|e| anyhow!("Invalid IDL: {}", e))
+}
+
+// `[u8; N]` was `{"array": ["u8", N]}`, rather than `{"byteArray": N}`.
+fn byte_arrays(value: &mut Value) {
+    let byte_array_len = match &*value {
+        Value::Object(obj) if obj.len() == 1 => match obj.get("array") {
+            Some(Value::Array(array)) if array.len() == 2 && array[0] == "u8" => {
+                Some(array[1].clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(len) = byte_array_len {
+        *value = json!({ "byteArray": len });
+        return;
+    }
+    match value {
+        Value::Object(obj) => obj
+            .iter_mut()
+            .filter(|(key, _)| *key != "metadata")
+            .for_each(|(_, v)| byte_arrays(v)),
+        Value::Array(items) => items.iter_mut().for_each(byte_arrays),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idl::{schema, IdlType};
+
+    #[test]
+    fn legacy_idl() {
+        // An IDL of Anchor 0.6.0, which predates `spec` and `byteArray`s.
+        let legacy = json!({
+            "version": "0.0.0",
+            "name": "test",
+            "instructions": [{
+                "name": "initialize",
+                "accounts": [],
+                "args": [{ "name": "data", "type": { "array": ["u8", 32] } }],
+            }],
+            "types": [{
+                "name": "T",
+                "type": {
+                    "kind": "struct",
+                    "fields": [
+                        { "name": "bytes", "type": { "vec": { "array": ["u8", 4] } } },
+                        { "name": "words", "type": { "array": ["u16", 2] } },
+                    ],
+                },
+            }],
+            "metadata": { "array": ["u8", 1] },
+        });
+        assert!(!schema::validate(&legacy).is_empty());
+        let idl = migrate(legacy).unwrap();
+        assert_eq!(idl.spec, IDL_SPEC);
+        assert_eq!(idl.instructions[0].args[0].ty, IdlType::ByteArray(32));
+        let value = serde_json::to_value(&idl).unwrap();
+        assert_eq!(
+            value["types"][0]["type"]["fields"],
+            json!([
+                { "name": "bytes", "type": { "vec": { "byteArray": 4 } } },
+                { "name": "words", "type": { "array": ["u16", 2] } },
+            ])
+        );
+        // Metadata is left as is.
+        assert_eq!(value["metadata"], json!({ "array": ["u8", 1] }));
+        assert!(schema::validate(&value).is_empty());
+
+        // IDLs of the current spec are unchanged.
+        assert_eq!(migrate(value).unwrap(), idl);
+    }
+
+    #[test]
+    fn unsupported_idls() {
+        let idl = json!({
+            "version": "0.0.0",
+            "spec": "99.0.0",
+            "name": "test",
+            "instructions": [],
+        });
+        assert_eq!(
+            migrate(idl).unwrap_err().to_string(),
+            format!(
+                "Unsupported IDL spec 99.0.0, the latest supported is {}",
+                IDL_SPEC
+            )
+        );
+        assert!(migrate(json!({ "spec": 1, "name": "test" })).is_err());
+        assert!(migrate(json!([])).is_err());
+        // Valid JSON, but not an IDL.
+        assert!(migrate(json!({ "name": "test" })).is_err());
+    }
+}
@@ -0,0 +1,749 @@
+use serde::{Deserialize, Serialize};
+
+mod migration;
+pub mod schema;
+
+pub use migration::migrate;
+
+// Version of the IDL format, described by `idl.schema.json`. Any change to the
+// format bumps it, along with a migration from the previous spec.
+pub const IDL_SPEC: &str = "0.1.0";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Idl {
+    // Version of the program.
+    pub version: String,
+    // Version of the IDL format, i.e., `IDL_SPEC` for IDLs generated by this
+    // version of Anchor.
+    pub spec: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    pub instructions: Vec<IdlIx>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub state: Option<IdlState>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub accounts: Vec<IdlTypeDef>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub types: Vec<IdlTypeDef>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub events: Option<Vec<IdlEvent>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub errors: Option<Vec<IdlErrorCode>>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub constants: Vec<IdlConst>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+// Delimiters of the IDL fragments printed by the `idl-build` feature.
+pub const IDL_FRAGMENT_BEGIN: &str = "--- anchor idl fragment begin ---";
+pub const IDL_FRAGMENT_END: &str = "--- anchor idl fragment end ---";
+
+// Part of an IDL, generated by the `#[program]`, `#[account]`, `#[event]`,
+// `#[error]` and `#[constant]` macros from compiled type information when the
+// program is built with the `idl-build` feature. `anchor idl build` merges all
+// fragments of a program into its IDL.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct IdlFragment {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub instructions: Vec<IdlIx>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub accounts: Vec<IdlTypeDef>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub types: Vec<IdlTypeDef>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub events: Vec<IdlEvent>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub errors: Vec<IdlErrorCode>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub constants: Vec<IdlConst>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlState {
+    #[serde(rename = "struct")]
+    pub strct: IdlTypeDef,
+    pub methods: Vec<IdlStateMethod>,
+}
+
+pub type IdlStateMethod = IdlIx;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlIx {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    pub accounts: Vec<IdlAccountItem>,
+    pub args: Vec<IdlField>,
+    // The type of the value returned by the instruction, if any.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub returns: Option<IdlType>,
+}
+
+// A single struct deriving `Accounts`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdlAccounts {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    pub accounts: Vec<IdlAccountItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum IdlAccountItem {
+    IdlAccount(IdlAccount),
+    IdlAccounts(IdlAccounts),
+}
+
+// A single field in the accounts struct.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdlAccount {
+    pub name: String,
+    pub is_mut: bool,
+    pub is_signer: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    // Accounts whose addresses are stored in the fields of the same name of
+    // this account's data, i.e., `has_one` constraints.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub relations: Vec<String>,
+    // The account that must own this one, i.e., an `owner` constraint.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "is_false", default)]
+    pub is_executable: bool,
+    // The program whose state account this must be, i.e., a `state`
+    // constraint.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pda: Option<IdlPda>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub init: Option<IdlInit>,
+    // Constraints given as expressions, e.g., `"a.b == c"`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub constraints: Vec<String>,
+}
+
+// A program derived address of the program, i.e., a `seeds` or `associated`
+// constraint.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdlPda {
+    pub seeds: Vec<IdlSeed>,
+    // The bump seed is found with `find_program_address`, rather than given
+    // as the last of the `seeds`.
+    #[serde(skip_serializing_if = "is_false", default)]
+    pub find_bump: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum IdlSeed {
+    Const { value: Vec<u8> },
+    // The address of an account of the same accounts struct.
+    Account { path: String },
+    // Any other seed, as written in the source.
+    Expr { expr: String },
+}
+
+// An account created by the instruction, i.e., an `init` constraint.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdlInit {
+    // The account paying for the account's creation, when created by the
+    // program. Otherwise, it's created by the client in the same transaction.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub payer: Option<String>,
+    // The space allocated, as written in the source. Defaults to the size of
+    // the account's type.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub space: Option<String>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlEvent {
+    pub name: String,
+    pub fields: Vec<IdlEventField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlEventField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+    pub index: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlTypeDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefTy,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+    // Names of the type parameters, referred to by `IdlType::Generic`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub generics: Vec<String>,
+    #[serde(skip_serializing_if = "IdlSerialization::is_borsh", default)]
+    pub serialization: IdlSerialization,
+    // Memory representation of zero copy types.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub repr: Option<IdlRepr>,
+    // Computed from `repr`, see `set_layouts`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub layout: Option<IdlLayout>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlSerialization {
+    Borsh,
+    // Zero copy types, cast from the account's bytes.
+    Bytemuck,
+}
+
+impl Default for IdlSerialization {
+    fn default() -> Self {
+        IdlSerialization::Borsh
+    }
+}
+
+impl IdlSerialization {
+    pub fn is_borsh(&self) -> bool {
+        *self == IdlSerialization::Borsh
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlRepr {
+    C,
+    Packed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlLayout {
+    pub size: usize,
+    pub align: usize,
+    // One for each field, in order.
+    pub fields: Vec<IdlFieldLayout>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlFieldLayout {
+    pub offset: usize,
+    pub size: usize,
+    // Bytes of padding following the field.
+    pub padding: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum IdlTypeDefTy {
+    // Unit structs have no fields.
+    Struct {
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        fields: Option<EnumFields>,
+    },
+    Enum {
+        variants: Vec<EnumVariant>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EnumVariant {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fields: Option<EnumFields>,
+}
+
+// Fields of a struct or enum variant.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum EnumFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum IdlType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    U64,
+    I64,
+    F64,
+    U128,
+    I128,
+    // 256 bit integers, serialized as 32 little endian bytes.
+    U256,
+    I256,
+    Bytes,
+    String,
+    PublicKey,
+    Defined(String),
+    // A generic user defined type, instantiated with the given type
+    // arguments.
+    DefinedWithTypeArgs {
+        name: String,
+        args: Vec<IdlType>,
+    },
+    // A type parameter of the enclosing type definition.
+    Generic(String),
+    Option(Box<IdlType>),
+    Vec(Box<IdlType>),
+    Array(Box<IdlType>, usize),
+    // `[u8; N]`.
+    ByteArray(usize),
+    Tuple(Vec<IdlType>),
+    // Maps are serialized as a `u32` length followed by the key value pairs.
+    HashMap(Box<IdlType>, Box<IdlType>),
+    #[serde(rename = "btreeMap")]
+    BTreeMap(Box<IdlType>, Box<IdlType>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlTypePublicKey;
+
+impl std::str::FromStr for IdlType {
+    type Err = anyhow::Error;
+
+    // Parses a Rust type, e.g., `Option<Vec<u64>>`. `Box<T>` is parsed as
+    // `T`, since Borsh serializes boxes as their contents.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Eliminate whitespace.
+        let mut s = s.to_string();
+        s.retain(|c| !c.is_whitespace());
+        let r = match s.as_str() {
+            "" => return Err(anyhow::anyhow!("Empty type")),
+            "bool" => IdlType::Bool,
+            "u8" => IdlType::U8,
+            "i8" => IdlType::I8,
+            "u16" => IdlType::U16,
+            "i16" => IdlType::I16,
+            "u32" => IdlType::U32,
+            "i32" => IdlType::I32,
+            "f32" => IdlType::F32,
+            "u64" => IdlType::U64,
+            "i64" => IdlType::I64,
+            "f64" => IdlType::F64,
+            "u128" => IdlType::U128,
+            "i128" => IdlType::I128,
+            "u256" => IdlType::U256,
+            "i256" => IdlType::I256,
+            "String" => IdlType::String,
+            "Pubkey" => IdlType::PublicKey,
+            _ => {
+                if let Some(inner) = s.strip_prefix('(') {
+                    let inner = inner
+                        .strip_suffix(')')
+                        .ok_or_else(|| anyhow::anyhow!("Invalid tuple: {}", s))?;
+                    let tys = split_top_level(inner, ',')?
+                        .into_iter()
+                        // Allow a trailing comma, e.g., `(u8,)`.
+                        .filter(|ty| !ty.is_empty())
+                        .map(|ty| IdlType::from_str(&ty))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    IdlType::Tuple(tys)
+                } else if let Some(inner) = s.strip_prefix('[') {
+                    let inner = inner
+                        .strip_suffix(']')
+                        .ok_or_else(|| anyhow::anyhow!("Invalid array: {}", s))?;
+                    let (ty, len) = match split_top_level(inner, ';')?.as_slice() {
+                        [ty, len] => (IdlType::from_str(ty)?, len.to_string()),
+                        _ => return Err(anyhow::anyhow!("Invalid array: {}", s)),
+                    };
+                    let len = len
+                        .parse::<usize>()
+                        .map_err(|_| anyhow::anyhow!("Invalid array length: {}", s))?;
+                    match ty {
+                        IdlType::U8 => IdlType::ByteArray(len),
+                        ty => IdlType::Array(Box::new(ty), len),
+                    }
+                } else {
+                    let (path, args) = split_generics(&s)?;
+                    let name = path.rsplit("::").next().unwrap_or(path);
+                    let arg = |idx: usize| IdlType::from_str(&args[idx]).map(Box::new);
+                    match (name, args.len()) {
+                        ("Option", 1) => IdlType::Option(arg(0)?),
+                        ("Vec", 1) => match *arg(0)? {
+                            IdlType::U8 => IdlType::Bytes,
+                            ty => IdlType::Vec(Box::new(ty)),
+                        },
+                        ("Box", 1) => *arg(0)?,
+                        ("HashMap", 2) => IdlType::HashMap(arg(0)?, arg(1)?),
+                        ("BTreeMap", 2) => IdlType::BTreeMap(arg(0)?, arg(1)?),
+                        ("Option", _)
+                        | ("Vec", _)
+                        | ("Box", _)
+                        | ("HashMap", _)
+                        | ("BTreeMap", _) => {
+                            return Err(anyhow::anyhow!("Invalid type arguments: {}", s))
+                        }
+                        _ => {
+                            // Lifetimes don't affect the layout.
+                            let args = args
+                                .iter()
+                                .filter(|arg| !arg.starts_with('\''))
+                                .map(|arg| IdlType::from_str(arg))
+                                .collect::<Result<Vec<_>, _>>()?;
+                            match args.is_empty() {
+                                true => IdlType::Defined(path.to_string()),
+                                false => IdlType::DefinedWithTypeArgs {
+                                    name: path.to_string(),
+                                    args,
+                                },
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        Ok(r)
+    }
+}
+
+// Splits `s` at every `sep` that isn't nested in brackets.
+fn split_top_level(s: &str, sep: char) -> anyhow::Result<Vec<String>> {
+    let mut parts = vec![];
+    let mut depth = 0i32;
+    let mut part = String::new();
+    for c in s.chars() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(std::mem::take(&mut part));
+                continue;
+            }
+            _ => {}
+        }
+        if depth < 0 {
+            return Err(anyhow::anyhow!("Unbalanced brackets: {}", s));
+        }
+        part.push(c);
+    }
+    if depth != 0 {
+        return Err(anyhow::anyhow!("Unbalanced brackets: {}", s));
+    }
+    parts.push(part);
+    Ok(parts)
+}
+
+// Splits a type path from its generic arguments, e.g., `HashMap<u8, u64>`
+// into `HashMap` and `[u8, u64]`.
+fn split_generics(s: &str) -> anyhow::Result<(&str, Vec<String>)> {
+    let (path, args) = match s.find('<') {
+        None => (s, vec![]),
+        Some(idx) => {
+            let args = s[idx + 1..]
+                .strip_suffix('>')
+                .ok_or_else(|| anyhow::anyhow!("Invalid type: {}", s))?;
+            (&s[..idx], split_top_level(args, ',')?)
+        }
+    };
+    let is_path = !path.is_empty()
+        && path
+            .split("::")
+            .all(|seg| !seg.is_empty() && seg.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    if !is_path || args.iter().any(|arg| arg.is_empty()) {
+        return Err(anyhow::anyhow!("Invalid type: {}", s));
+    }
+    Ok((path, args))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlErrorCode {
+    pub code: u32,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub msg: Option<String>,
+}
+
+// A `#[constant]` of the program. The value is a Rust literal, e.g.,
+// `b"seed"` or `3600` for `60 * 60`, or, if the constant couldn't be
+// evaluated when generating the IDL, its expression as written in the source.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlConst {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub docs: Option<Vec<String>>,
+}
+
+// Sets the layout of every zero copy type definition, following the rules of
+// its `repr`, with the alignments of the BPF target, e.g., 8 for `u128`.
+// Types containing anything but integers, floats, public keys, arrays and
+// other zero copy types are left without a layout.
+pub fn set_layouts(idl: &mut Idl) {
+    let defs: Vec<IdlTypeDef> = idl
+        .accounts
+        .iter()
+        .chain(idl.types.iter())
+        .chain(idl.state.as_ref().map(|s| &s.strct))
+        .cloned()
+        .collect();
+    let ty_defs = idl
+        .accounts
+        .iter_mut()
+        .chain(idl.types.iter_mut())
+        .chain(idl.state.as_mut().map(|s| &mut s.strct));
+    for ty_def in ty_defs {
+        ty_def.layout = type_def_layout(ty_def, &defs, 0);
+    }
+}
+
+// Zero copy types can't be recursive, but a malformed IDL could be.
+const MAX_LAYOUT_DEPTH: usize = 64;
+
+fn type_def_layout(ty_def: &IdlTypeDef, defs: &[IdlTypeDef], depth: usize) -> Option<IdlLayout> {
+    if ty_def.serialization != IdlSerialization::Bytemuck || depth > MAX_LAYOUT_DEPTH {
+        return None;
+    }
+    let tys: Vec<&IdlType> = match &ty_def.ty {
+        IdlTypeDefTy::Struct { fields: None } => vec![],
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Named(fields)),
+        } => fields.iter().map(|f| &f.ty).collect(),
+        IdlTypeDefTy::Struct {
+            fields: Some(EnumFields::Tuple(tys)),
+        } => tys.iter().collect(),
+        IdlTypeDefTy::Enum { .. } => return None,
+    };
+    let packed = ty_def.repr == Some(IdlRepr::Packed);
+    let mut fields: Vec<IdlFieldLayout> = vec![];
+    let mut align = 1;
+    let mut end = 0;
+    for ty in tys {
+        let (size, field_align) = type_layout(ty, defs, depth + 1)?;
+        let offset = match packed {
+            true => end,
+            false => align_up(end, field_align),
+        };
+        if let Some(prev) = fields.last_mut() {
+            prev.padding = offset - end;
+        }
+        fields.push(IdlFieldLayout {
+            offset,
+            size,
+            padding: 0,
+        });
+        align = align.max(field_align);
+        end = offset + size;
+    }
+    if packed {
+        align = 1;
+    }
+    let size = align_up(end, align);
+    if let Some(last) = fields.last_mut() {
+        last.padding = size - end;
+    }
+    Some(IdlLayout {
+        size,
+        align,
+        fields,
+    })
+}
+
+// Returns the size and alignment of the type.
+fn type_layout(ty: &IdlType, defs: &[IdlTypeDef], depth: usize) -> Option<(usize, usize)> {
+    let layout = match ty {
+        IdlType::Bool | IdlType::U8 | IdlType::I8 => (1, 1),
+        IdlType::U16 | IdlType::I16 => (2, 2),
+        IdlType::U32 | IdlType::I32 | IdlType::F32 => (4, 4),
+        IdlType::U64 | IdlType::I64 | IdlType::F64 => (8, 8),
+        IdlType::U128 | IdlType::I128 => (16, 8),
+        IdlType::U256 | IdlType::I256 => (32, 8),
+        IdlType::PublicKey => (32, 1),
+        IdlType::ByteArray(len) => (*len, 1),
+        IdlType::Array(ty, len) => {
+            let (size, align) = type_layout(ty, defs, depth)?;
+            (size * len, align)
+        }
+        IdlType::Defined(name) => {
+            let ty_def = defs.iter().find(|ty_def| &ty_def.name == name)?;
+            let layout = type_def_layout(ty_def, defs, depth + 1)?;
+            (layout.size, layout.align)
+        }
+        _ => return None,
+    };
+    Some(layout)
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rich_types() {
+        let ty = |s: &str| s.parse::<IdlType>().unwrap();
+        assert_eq!(ty("f32"), IdlType::F32);
+        assert_eq!(ty("std::boxed::Box<u64>"), IdlType::U64);
+        assert_eq!(
+            ty("(u8, String,)"),
+            IdlType::Tuple(vec![IdlType::U8, IdlType::String])
+        );
+        assert_eq!(
+            ty("std::collections::HashMap<u8, Vec<i8>>"),
+            IdlType::HashMap(
+                Box::new(IdlType::U8),
+                Box::new(IdlType::Vec(Box::new(IdlType::I8)))
+            )
+        );
+        assert_eq!(
+            ty("[Option<u8>; 2]"),
+            IdlType::Array(Box::new(IdlType::Option(Box::new(IdlType::U8))), 2)
+        );
+        assert_eq!(ty("[u8; 3]"), IdlType::ByteArray(3));
+        assert_eq!(ty("Vec<u8>"), IdlType::Bytes);
+
+        let value = serde_json::to_value(ty("BTreeMap<u16, (bool, [u8; 4])>")).unwrap();
+        assert_eq!(
+            value,
+            json!({ "btreeMap": ["u16", { "tuple": ["bool", { "byteArray": 4 }] }] })
+        );
+        assert_eq!(
+            serde_json::from_value::<IdlType>(value).unwrap(),
+            ty("BTreeMap<u16, (bool, [u8; 4])>")
+        );
+        assert_eq!(serde_json::to_value(IdlType::U256).unwrap(), json!("u256"));
+    }
+
+    #[test]
+    fn invalid_types() {
+        for ty in &[
+            "",
+            "[u8; N]",
+            "[u8]",
+            "Option<u8",
+            "Vec<u8, u16>",
+            "HashMap<u8>",
+            "&str",
+            "(u8, [u16; 2)",
+        ] {
+            assert!(ty.parse::<IdlType>().is_err(), "{}", ty);
+        }
+    }
+
+    // An IDL with the given types, written as JSON.
+    fn types_idl(types: serde_json::Value) -> Idl {
+        serde_json::from_value(json!({
+            "version": "0.0.0",
+            "spec": IDL_SPEC,
+            "name": "test",
+            "instructions": [],
+            "types": types,
+        }))
+        .unwrap()
+    }
+
+    fn struct_def(name: &str, repr: Option<&str>, fields: &[&str]) -> serde_json::Value {
+        let fields: Vec<serde_json::Value> = fields
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| json!({ "name": format!("f{}", i), "type": ty.parse::<IdlType>().unwrap() }))
+            .collect();
+        let mut def = json!({ "name": name, "type": { "kind": "struct", "fields": fields } });
+        if let Some(repr) = repr {
+            def["serialization"] = json!("bytemuck");
+            def["repr"] = json!(repr);
+        }
+        def
+    }
+
+    fn field_layouts(layout: &IdlLayout) -> Vec<(usize, usize, usize)> {
+        layout
+            .fields
+            .iter()
+            .map(|f| (f.offset, f.size, f.padding))
+            .collect()
+    }
+
+    #[test]
+    fn packed_layout() {
+        let mut idl = types_idl(json!([
+            struct_def(
+                "Foo",
+                Some("packed"),
+                &["Pubkey", "u64", "u128", "[u8; 32]"]
+            ),
+            struct_def("Globals", Some("packed"), &["Pubkey", "[Foo; 250]"]),
+        ]));
+        set_layouts(&mut idl);
+        let foo = idl.types[0].layout.as_ref().unwrap();
+        assert_eq!((foo.size, foo.align), (88, 1));
+        assert_eq!(
+            field_layouts(foo),
+            vec![(0, 32, 0), (32, 8, 0), (40, 16, 0), (56, 32, 0)]
+        );
+        let globals = idl.types[1].layout.as_ref().unwrap();
+        assert_eq!(globals.size, 32 + 250 * 88);
+    }
+
+    #[test]
+    fn repr_c_layout() {
+        let mut idl = types_idl(json!([
+            struct_def("Inner", Some("c"), &["u8", "u64", "u16"]),
+            struct_def("Outer", Some("c"), &["bool", "Inner", "u128", "[u16; 3]"]),
+            struct_def("Dynamic", Some("c"), &["Vec<u8>"]),
+            struct_def("Borsh", None, &["u64"]),
+            struct_def("Nested", Some("c"), &["Borsh"]),
+        ]));
+        set_layouts(&mut idl);
+        let inner = idl.types[0].layout.as_ref().unwrap();
+        assert_eq!((inner.size, inner.align), (24, 8));
+        assert_eq!(field_layouts(inner), vec![(0, 1, 7), (8, 8, 0), (16, 2, 6)]);
+        let outer = idl.types[1].layout.as_ref().unwrap();
+        assert_eq!((outer.size, outer.align), (56, 8));
+        assert_eq!(
+            field_layouts(outer),
+            vec![(0, 1, 7), (8, 24, 0), (32, 16, 0), (48, 6, 2)]
+        );
+        // Only fixed size zero copy types have a layout.
+        assert_eq!(idl.types[2].layout, None);
+        assert_eq!(idl.types[3].layout, None);
+        assert_eq!(idl.types[4].layout, None);
+    }
+
+    #[test]
+    fn recursive_layout() {
+        let mut idl = types_idl(json!([struct_def("A", Some("c"), &["[A; 1]"])]));
+        set_layouts(&mut idl);
+        assert_eq!(idl.types[0].layout, None);
+    }
+}
@@ -0,0 +1,510 @@
+// Validation of IDLs against `idl.schema.json`, the JSON Schema (draft 7) of
+// the current spec. Only the keywords used by the schema are supported.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const IDL_SCHEMA: &str = include_str!("../../idl.schema.json");
+
+// A value of the IDL not matching the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    // Path to the value, e.g., `instructions[0].args[1].type`, empty for the
+    // IDL itself.
+    pub path: String,
+    pub msg: String,
+    depth: usize,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}", self.msg),
+            false => write!(f, "{}: {}", self.path, self.msg),
+        }
+    }
+}
+
+// Validates the JSON of an IDL against the schema, returning all the values
+// not matching it.
+pub fn validate(idl: &Value) -> Vec<SchemaError> {
+    let schema: Value = serde_json::from_str(IDL_SCHEMA).expect("Schema must be valid JSON");
+    let mut errors = vec![];
+    Validator { root: &schema }.validate(&schema, idl, &Path::default(), &mut errors);
+    errors
+}
+
+#[derive(Default)]
+struct Path {
+    path: String,
+    depth: usize,
+}
+
+impl Path {
+    fn key(&self, key: &str) -> Path {
+        Path {
+            path: match self.path.is_empty() {
+                true => key.to_string(),
+                false => format!("{}.{}", self.path, key),
+            },
+            depth: self.depth + 1,
+        }
+    }
+
+    fn index(&self, index: usize) -> Path {
+        Path {
+            path: format!("{}[{}]", self.path, index),
+            depth: self.depth + 1,
+        }
+    }
+
+    fn error(&self, msg: String) -> SchemaError {
+        SchemaError {
+            path: self.path.clone(),
+            msg,
+            depth: self.depth,
+        }
+    }
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl<'a> Validator<'a> {
+    fn validate(&self, schema: &Value, value: &Value, path: &Path, errors: &mut Vec<SchemaError>) {
+        let schema = schema.as_object().expect("Schemas must be objects");
+        for (keyword, arg) in schema {
+            match keyword.as_str() {
+                "$schema" | "$id" | "title" | "description" | "definitions" => {}
+                "$ref" => self.validate(self.resolve(arg), value, path, errors),
+                "type" => {
+                    let ty = arg.as_str().expect("Invalid type");
+                    if !is_type(value, ty) {
+                        errors.push(path.error(format!(
+                            "expected {}, found {}",
+                            ty,
+                            describe(value)
+                        )));
+                    }
+                }
+                "enum" => {
+                    let variants = arg.as_array().expect("Invalid enum");
+                    if !variants.contains(value) {
+                        let variants: Vec<String> = variants.iter().map(Value::to_string).collect();
+                        errors.push(path.error(format!(
+                            "expected one of {}, found {}",
+                            variants.join(", "),
+                            describe(value)
+                        )));
+                    }
+                }
+                "const" => {
+                    if arg != value {
+                        errors.push(path.error(format!(
+                            "expected {}, found {}",
+                            arg,
+                            describe(value)
+                        )));
+                    }
+                }
+                "properties" => {
+                    if let Value::Object(obj) = value {
+                        for (key, schema) in properties(arg) {
+                            if let Some(v) = obj.get(key) {
+                                self.validate(schema, v, &path.key(key), errors);
+                            }
+                        }
+                    }
+                }
+                "required" => {
+                    if let Value::Object(obj) = value {
+                        for key in arg.as_array().expect("Invalid required") {
+                            let key = key.as_str().expect("Invalid required");
+                            if !obj.contains_key(key) {
+                                errors.push(path.error(format!("missing property {}", key)));
+                            }
+                        }
+                    }
+                }
+                "additionalProperties" => {
+                    if let Value::Object(obj) = value {
+                        let known = schema.get("properties").map(properties);
+                        for (key, v) in obj {
+                            if known.map(|known| known.contains_key(key)).unwrap_or(false) {
+                                continue;
+                            }
+                            match arg {
+                                // At the depth of the object, as it's the
+                                // object that doesn't match.
+                                Value::Bool(false) => errors.push(SchemaError {
+                                    path: path.key(key).path,
+                                    msg: "unexpected property".to_string(),
+                                    depth: path.depth,
+                                }),
+                                schema => self.validate(schema, v, &path.key(key), errors),
+                            }
+                        }
+                    }
+                }
+                "items" => {
+                    if let Value::Array(items) = value {
+                        for (idx, item) in items.iter().enumerate() {
+                            // An array of schemas validates the items at the
+                            // same positions.
+                            let schema = match arg {
+                                Value::Array(schemas) => match schemas.get(idx) {
+                                    Some(schema) => schema,
+                                    None => continue,
+                                },
+                                schema => schema,
+                            };
+                            self.validate(schema, item, &path.index(idx), errors);
+                        }
+                    }
+                }
+                "minItems" | "maxItems" => {
+                    if let Value::Array(items) = value {
+                        let bound = arg.as_u64().expect("Invalid item count") as usize;
+                        let (ok, qualifier) = match keyword.as_str() {
+                            "minItems" => (items.len() >= bound, "at least"),
+                            _ => (items.len() <= bound, "at most"),
+                        };
+                        if !ok {
+                            errors.push(path.error(format!(
+                                "expected {} {} items, found {}",
+                                qualifier,
+                                bound,
+                                items.len()
+                            )));
+                        }
+                    }
+                }
+                "minimum" | "maximum" => {
+                    if let Some(n) = value.as_f64() {
+                        let bound = arg.as_f64().expect("Invalid bound");
+                        let (ok, qualifier) = match keyword.as_str() {
+                            "minimum" => (n >= bound, "at least"),
+                            _ => (n <= bound, "at most"),
+                        };
+                        if !ok {
+                            errors.push(
+                                path.error(format!(
+                                    "expected {} {}, found {}",
+                                    qualifier, arg, value
+                                )),
+                            );
+                        }
+                    }
+                }
+                "anyOf" => self.validate_any_of(arg, value, path, errors),
+                _ => panic!("Unsupported schema keyword: {}", keyword),
+            }
+        }
+    }
+
+    // When no schema matches, reports the errors of the one the value got
+    // furthest into, e.g., the `vec` type for `{"vec": "u9"}`, or else with
+    // the fewest errors, unless there's no single such schema.
+    fn validate_any_of(
+        &self,
+        arg: &Value,
+        value: &Value,
+        path: &Path,
+        errors: &mut Vec<SchemaError>,
+    ) {
+        let mut candidates = vec![];
+        for schema in arg.as_array().expect("Invalid anyOf") {
+            let mut schema_errors = vec![];
+            self.validate(schema, value, path, &mut schema_errors);
+            if schema_errors.is_empty() {
+                return;
+            }
+            candidates.push(schema_errors);
+        }
+        let rank = |errors: &Vec<SchemaError>| (usize::MAX - max_depth(errors), errors.len());
+        candidates.sort_by_key(rank);
+        let is_best = match candidates.as_slice() {
+            [best, next, ..] => rank(best) < rank(next),
+            _ => true,
+        };
+        match candidates.into_iter().next() {
+            Some(best) if is_best => errors.extend(best),
+            _ => errors.push(path.error(format!(
+                "found {}, which doesn't match any of the allowed schemas",
+                describe(value)
+            ))),
+        }
+    }
+
+    fn resolve(&self, reference: &Value) -> &'a Value {
+        let reference = reference.as_str().expect("Invalid $ref");
+        reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+            .unwrap_or_else(|| panic!("Unresolved $ref: {}", reference))
+    }
+}
+
+fn properties(arg: &Value) -> &Map<String, Value> {
+    arg.as_object().expect("Invalid properties")
+}
+
+fn is_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "number" => value.is_number(),
+        "null" => value.is_null(),
+        _ => panic!("Unsupported type: {}", ty),
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "an object".to_string(),
+        v => v.to_string(),
+    }
+}
+
+fn max_depth(errors: &[SchemaError]) -> usize {
+    errors.iter().map(|e| e.depth).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // The keywords and types supported by `Validator::validate` and `is_type`.
+    const KEYWORDS: &[&str] = &[
+        "$schema",
+        "$id",
+        "title",
+        "description",
+        "definitions",
+        "$ref",
+        "type",
+        "enum",
+        "const",
+        "properties",
+        "required",
+        "additionalProperties",
+        "items",
+        "minItems",
+        "maxItems",
+        "minimum",
+        "maximum",
+        "anyOf",
+    ];
+    const TYPES: &[&str] = &[
+        "object", "array", "string", "boolean", "integer", "number", "null",
+    ];
+
+    // Paths of the `lib.rs` of all programs in `examples/`.
+    fn example_programs() -> Vec<std::path::PathBuf> {
+        let mut dirs =
+            vec![std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples")];
+        let mut programs = vec![];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy();
+                if !path.is_dir() || name == "node_modules" || name == "target" {
+                    continue;
+                }
+                let lib = path.join("src/lib.rs");
+                match path.parent().unwrap().ends_with("programs") && lib.exists() {
+                    true => programs.push(lib),
+                    false => dirs.push(path),
+                }
+            }
+        }
+        programs
+    }
+
+    fn errors(schema: Value, value: Value) -> Vec<String> {
+        let mut errors = vec![];
+        Validator { root: &schema }.validate(&schema, &value, &Path::default(), &mut errors);
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    // Every keyword of the schema and its subschemas, e.g., in `definitions`,
+    // must be supported, so that no part of the schema is silently skipped
+    // or panics only for some IDLs.
+    fn unsupported(schema: &Value, path: &str, found: &mut Vec<String>) {
+        let schema = schema.as_object().expect("Schemas must be objects");
+        for (keyword, arg) in schema {
+            let path = format!("{}/{}", path, keyword);
+            if !KEYWORDS.contains(&keyword.as_str()) {
+                found.push(path.clone());
+            }
+            match (keyword.as_str(), arg) {
+                ("type", Value::String(ty)) if !TYPES.contains(&ty.as_str()) => {
+                    found.push(format!("{}/{}", path, ty))
+                }
+                ("properties", Value::Object(schemas))
+                | ("definitions", Value::Object(schemas)) => {
+                    for (key, schema) in schemas {
+                        unsupported(schema, &format!("{}/{}", path, key), found);
+                    }
+                }
+                ("items", Value::Array(schemas)) | ("anyOf", Value::Array(schemas)) => {
+                    for (idx, schema) in schemas.iter().enumerate() {
+                        unsupported(schema, &format!("{}/{}", path, idx), found);
+                    }
+                }
+                ("items", Value::Object(_)) | ("additionalProperties", Value::Object(_)) => {
+                    unsupported(arg, &path, found)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn schema_keywords() {
+        let schema: Value = serde_json::from_str(IDL_SCHEMA).unwrap();
+        let mut found = vec![];
+        unsupported(&schema, "#", &mut found);
+        assert!(found.is_empty(), "Unsupported schema keywords: {:?}", found);
+
+        let mut found = vec![];
+        unsupported(
+            &json!({
+                "definitions": { "t": { "type": "object", "patternProperties": {} } },
+                "items": [{ "type": "tuple" }],
+            }),
+            "#",
+            &mut found,
+        );
+        assert_eq!(
+            found,
+            vec!["#/definitions/t/patternProperties", "#/items/0/type/tuple"]
+        );
+    }
+
+    #[test]
+    fn example_idls() {
+        let programs = example_programs();
+        assert!(programs.len() > 10);
+        for path in programs {
+            let idl = serde_json::to_value(crate::parser::file::parse(&path).unwrap()).unwrap();
+            let errors = validate(&idl);
+            assert!(errors.is_empty(), "{}: {:?}", path.display(), errors);
+        }
+    }
+
+    #[test]
+    fn invalid_idl() {
+        let idl = json!({
+            "version": "0.0.0",
+            "name": "test",
+            "instructions": [{
+                "name": "initialize",
+                "accounts": [{ "name": "authority", "isMut": false }],
+                "args": [{ "name": "data", "type": { "vec": "u9" } }],
+            }],
+            "types": [{
+                "name": "T",
+                "type": { "kind": "struct", "fields": [] },
+                "serialization": "json",
+            }],
+            "extra": 1,
+        });
+        let errors: Vec<String> = validate(&idl).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "extra: unexpected property",
+                "instructions[0].accounts[0]: missing property isSigner",
+                "instructions[0].args[0].type.vec: found \"u9\", which doesn't match any of the \
+                 allowed schemas",
+                "types[0].serialization: expected one of \"borsh\", \"bytemuck\", found \"json\"",
+                "missing property spec",
+            ]
+        );
+        assert_eq!(
+            validate(&json!([])),
+            vec![SchemaError {
+                path: "".to_string(),
+                msg: "expected object, found an array".to_string(),
+                depth: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn keywords() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "kind": { "const": "pair" },
+                "items": {
+                    "type": "array",
+                    "items": [{ "type": "integer", "minimum": 0 }, { "enum": ["a", "b"] }],
+                    "minItems": 2,
+                    "maxItems": 2,
+                },
+                "ref": { "$ref": "#/definitions/flag" },
+            },
+            "additionalProperties": { "type": "string" },
+            "required": ["kind"],
+            "definitions": { "flag": { "type": "boolean" } },
+        });
+        assert!(errors(
+            schema.clone(),
+            json!({ "kind": "pair", "items": [1, "a"], "ref": true, "other": "x" })
+        )
+        .is_empty());
+        assert_eq!(
+            errors(
+                schema,
+                json!({ "items": [-1, "c", 2], "ref": 1, "other": 1 })
+            ),
+            vec![
+                "other: expected string, found 1",
+                "items[0]: expected at least 0, found -1",
+                "items[1]: expected one of \"a\", \"b\", found \"c\"",
+                "items: expected at most 2 items, found 3",
+                "ref: expected boolean, found 1",
+                "missing property kind",
+            ]
+        );
+    }
+
+    #[test]
+    fn any_of() {
+        let schema = json!({
+            "anyOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": { "vec": { "type": "string" } },
+                    "required": ["vec"],
+                },
+                {
+                    "type": "object",
+                    "properties": { "option": { "type": "string" } },
+                    "required": ["option"],
+                },
+            ],
+        });
+        assert!(errors(schema.clone(), json!("u8")).is_empty());
+        assert!(errors(schema.clone(), json!({ "vec": "u8" })).is_empty());
+        // The errors of the schema the value got furthest into.
+        assert_eq!(
+            errors(schema.clone(), json!({ "vec": 1 })),
+            vec!["vec: expected string, found 1"]
+        );
+        // No single best schema.
+        assert_eq!(
+            errors(schema, json!({})),
+            vec!["found an object, which doesn't match any of the allowed schemas"]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 //! DSL syntax tokens.
 
 #[cfg(feature = "idl")]
-use crate::idl::{IdlAccount, IdlAccountItem, IdlAccounts};
+use crate::idl::{IdlAccount, IdlAccountItem, IdlAccounts, IdlInit, IdlPda, IdlSeed};
 use anyhow::Result;
 #[cfg(feature = "idl")]
 use heck::MixedCase;
@@ -23,6 +23,8 @@ pub struct Program {
     pub ixs: Vec<Ix>,
     pub name: syn::Ident,
     pub program_mod: syn::ItemMod,
+    // Whether instructions may return values, i.e., `#[program(return_data)]`.
+    pub return_data: bool,
 }
 
 // State struct singleton.
@@ -59,6 +61,8 @@ pub struct Ix {
     pub args: Vec<IxArg>,
     // The ident for the struct deriving Accounts.
     pub anchor_ident: syn::Ident,
+    // The `T` of a handler returning `Result<T>`, if it returns a value.
+    pub returns: Option<syn::Type>,
 }
 
 #[derive(Debug)]
@@ -130,19 +134,95 @@ impl AccountsStruct {
                     let accounts = accs_strct.idl_accounts(global_accs);
                     IdlAccountItem::IdlAccounts(IdlAccounts {
                         name: comp_f.ident.to_string().to_mixed_case(),
+                        docs: parser::docs(&comp_f.raw_field.attrs),
                         accounts,
                     })
                 }
-                AccountField::Field(acc) => IdlAccountItem::IdlAccount(IdlAccount {
-                    name: acc.ident.to_string().to_mixed_case(),
-                    is_mut: acc.is_mut,
-                    is_signer: acc.is_signer,
-                }),
+                AccountField::Field(acc) => IdlAccountItem::IdlAccount(acc.idl_account()),
             })
             .collect::<Vec<_>>()
     }
 }
 
+// Classifies each seed of a `seeds = [..]` constraint, e.g., `b"anchor"` is
+// a constant and `authority.key.as_ref()` the address of an account.
+#[cfg(feature = "idl")]
+fn idl_seeds(seeds: &proc_macro2::Group) -> Vec<IdlSeed> {
+    let seeds: syn::ExprArray = match syn::parse2(quote! { #seeds }) {
+        Ok(seeds) => seeds,
+        Err(_) => {
+            return vec![IdlSeed::Expr {
+                expr: parser::tts_to_source(seeds.stream()),
+            }]
+        }
+    };
+    seeds
+        .elems
+        .iter()
+        .map(|seed| {
+            idl_seed(seed).unwrap_or_else(|| IdlSeed::Expr {
+                expr: parser::tts_to_source(quote! { #seed }),
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "idl")]
+fn idl_seed(seed: &syn::Expr) -> Option<IdlSeed> {
+    match seed {
+        // `&seed`, `seed[..]`, `seed.as_ref()` and friends.
+        syn::Expr::Reference(r) => idl_seed(&r.expr),
+        syn::Expr::Paren(p) => idl_seed(&p.expr),
+        syn::Expr::Index(i) if matches!(&*i.index, syn::Expr::Range(_)) => idl_seed(&i.expr),
+        syn::Expr::MethodCall(m)
+            if m.args.is_empty() && (m.method == "as_ref" || m.method == "as_bytes") =>
+        {
+            idl_seed(&m.receiver)
+        }
+        syn::Expr::Lit(syn::ExprLit { lit, .. }) => match lit {
+            syn::Lit::ByteStr(b) => Some(IdlSeed::Const { value: b.value() }),
+            syn::Lit::Str(s) => Some(IdlSeed::Const {
+                value: s.value().into_bytes(),
+            }),
+            _ => None,
+        },
+        // E.g., `&[1]`.
+        syn::Expr::Array(a) => a
+            .elems
+            .iter()
+            .map(|elem| match elem {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(i),
+                    ..
+                }) => i.base10_parse::<u8>().ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .map(|value| IdlSeed::Const { value }),
+        // `account.key`, `account.to_account_info().key` or `account.key()`.
+        syn::Expr::Field(f) if matches!(&f.member, syn::Member::Named(m) if m == "key") => {
+            seed_account(&f.base)
+        }
+        syn::Expr::MethodCall(m) if m.args.is_empty() && m.method == "key" => {
+            seed_account(&m.receiver)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "idl")]
+fn seed_account(expr: &syn::Expr) -> Option<IdlSeed> {
+    match expr {
+        syn::Expr::Path(p) => p.path.get_ident().map(|ident| IdlSeed::Account {
+            path: ident.to_string().to_mixed_case(),
+        }),
+        syn::Expr::MethodCall(m) if m.args.is_empty() && m.method == "to_account_info" => {
+            seed_account(&m.receiver)
+        }
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 pub enum AccountField {
     // Use a `String` instead of the `AccountsStruct` because all
@@ -171,6 +251,8 @@ pub struct Field {
     pub is_mut: bool,
     pub is_signer: bool,
     pub is_init: bool,
+    // Doc comments of the field.
+    pub docs: Option<Vec<String>>,
     // TODO: move associated out of the constraints and put into tis own
     //       field + struct.
     // Used by the associated attribute only.
@@ -182,6 +264,75 @@ pub struct Field {
 }
 
 impl Field {
+    #[cfg(feature = "idl")]
+    pub fn idl_account(&self) -> IdlAccount {
+        let name = |ident: &proc_macro2::Ident| ident.to_string().to_mixed_case();
+        let mut acc = IdlAccount {
+            name: name(&self.ident),
+            is_mut: self.is_mut,
+            is_signer: self.is_signer,
+            docs: self.docs.clone(),
+            relations: vec![],
+            owner: None,
+            is_executable: false,
+            state: None,
+            pda: None,
+            init: match self.is_init {
+                true => Some(IdlInit {
+                    payer: None,
+                    space: None,
+                }),
+                false => None,
+            },
+            constraints: vec![],
+        };
+        for c in &self.constraints {
+            match c {
+                Constraint::BelongsTo(c) => acc.relations.push(name(&c.join_target)),
+                Constraint::Owner(c) => acc.owner = Some(name(&c.owner_target)),
+                Constraint::Executable(_) => acc.is_executable = true,
+                Constraint::State(c) => acc.state = Some(name(&c.program_target)),
+                Constraint::Literal(c) => acc
+                    .constraints
+                    .push(parser::tts_to_source(c.tokens.clone())),
+                Constraint::Seeds(c) => {
+                    acc.pda = Some(IdlPda {
+                        seeds: idl_seeds(&c.seeds),
+                        find_bump: false,
+                    })
+                }
+                Constraint::Associated(c) => {
+                    // See `generate_associated_pubkey`.
+                    let mut seeds = vec![
+                        IdlSeed::Const {
+                            value: b"anchor".to_vec(),
+                        },
+                        IdlSeed::Account {
+                            path: name(&c.associated_target),
+                        },
+                    ];
+                    seeds.extend(
+                        self.associated_seeds
+                            .iter()
+                            .map(|seed| IdlSeed::Account { path: name(seed) }),
+                    );
+                    acc.pda = Some(IdlPda {
+                        seeds,
+                        find_bump: true,
+                    });
+                    if c.is_init {
+                        acc.init = Some(IdlInit {
+                            payer: Some(name(self.payer.as_ref().unwrap_or(&c.associated_target))),
+                            space: self.space.as_ref().map(|space| space.to_string()),
+                        });
+                    }
+                }
+                Constraint::Signer(_) | Constraint::RentExempt(_) => {}
+            }
+        }
+        acc
+    }
+
     pub fn typed_ident(&self) -> proc_macro2::TokenStream {
         let name = &self.ident;
 
@@ -1,3 +1,4 @@
+use crate::parser;
 use crate::{
     AccountField, AccountsStruct, CompositeField, Constraint, ConstraintAssociated,
     ConstraintBelongsTo, ConstraintExecutable, ConstraintLiteral, ConstraintOwner,
@@ -58,6 +59,7 @@ fn parse_field(f: &syn::Field, anchor: Option<&syn::Attribute>) -> AccountField
                 payer,
                 space,
                 associated_seeds,
+                docs: parser::docs(&f.attrs),
             })
         }
         false => AccountField::AccountsStruct(CompositeField {
@@ -1,27 +1,56 @@
 use crate::idl::*;
 use crate::parser::{self, accounts, error, program};
 use crate::{AccountsStruct, StateIx};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use heck::MixedCase;
 use quote::ToTokens;
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::Read;
+use std::convert::TryFrom;
 use std::iter::FromIterator;
 use std::path::Path;
 
 const DERIVE_NAME: &str = "Accounts";
 
-// Parse an entire interface file.
+// Parse an entire interface file, along with all the modules it declares,
+// i.e., the whole crate when given the crate root.
 pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
-    let mut file = File::open(&filename)?;
+    let mut crate_consts = vec![];
+    let f = parse_crate(filename.as_ref(), &[], &mut crate_consts)?;
 
-    let mut src = String::new();
-    file.read_to_string(&mut src).expect("Unable to read file");
-
-    let f = syn::parse_file(&src).expect("Unable to parse file");
+    let program_mod = parse_program_mod(&f);
+    crate_consts.extend(mod_consts(&program_mod, &[program_mod.ident.to_string()]));
+    let consts: Consts = crate_consts
+        .iter()
+        .map(|c| {
+            let path = [&c.module[..], &[c.item.ident.to_string()][..]].concat();
+            (path, (&c.module[..], &*c.item.expr))
+        })
+        .collect();
+    let constants = parse_consts(&crate_consts)
+        .iter()
+        .map(|c| -> Result<IdlConst> {
+            // Integers must fit into the constant's type.
+            let ty = parser::tts_to_string(&c.item.ty);
+            let value = match eval_const(&c.item.expr, &c.module, &consts, 0) {
+                Some(value) if int_range(&ty).is_some() => value.cast(&ty),
+                value => value,
+            };
+            let value = match value {
+                Some(value) => value.to_source(),
+                None => parser::tts_to_source(c.item.expr.to_token_stream()),
+            };
+            Ok(IdlConst {
+                name: c.item.ident.to_string(),
+                ty: const_ty(&c.item.ty)?,
+                value,
+                docs: parser::docs(&c.item.attrs),
+            })
+        })
+        .collect::<Result<Vec<IdlConst>>>()?;
 
-    let p = program::parse(parse_program_mod(&f));
+    // The IDL declares the values instructions return regardless, since the
+    // program doesn't compile without `#[program(return_data)]` otherwise.
+    let p = program::parse(program_mod, true);
 
     let accs = parse_account_derives(&f);
 
@@ -35,7 +64,7 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
                     .map(|(_impl_block, methods)| {
                         methods
                             .iter()
-                            .map(|method: &StateIx| {
+                            .map(|method: &StateIx| -> Result<IdlStateMethod> {
                                 let name = method.ident.to_string().to_mixed_case();
                                 let args = method
                                     .args
@@ -43,24 +72,28 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
                                     .map(|arg| {
                                         let mut tts = proc_macro2::TokenStream::new();
                                         arg.raw_arg.ty.to_tokens(&mut tts);
-                                        let ty = tts.to_string().parse().unwrap();
-                                        IdlField {
+                                        let ty = tts.to_string().parse()?;
+                                        Ok(IdlField {
                                             name: arg.name.to_string().to_mixed_case(),
                                             ty,
-                                        }
+                                            docs: None,
+                                        })
                                     })
-                                    .collect::<Vec<_>>();
+                                    .collect::<Result<Vec<_>>>()?;
                                 let accounts_strct =
                                     accs.get(&method.anchor_ident.to_string()).unwrap();
                                 let accounts = accounts_strct.idl_accounts(&accs);
-                                IdlStateMethod {
+                                Ok(IdlStateMethod {
                                     name,
+                                    docs: parser::docs(&method.raw_method.attrs),
                                     args,
                                     accounts,
-                                }
+                                    returns: None,
+                                })
                             })
-                            .collect::<Vec<_>>()
+                            .collect::<Result<Vec<_>>>()
                     })
+                    .transpose()?
                     .unwrap_or_default();
                 let ctor = {
                     let name = "new".to_string();
@@ -81,21 +114,24 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
                             syn::FnArg::Typed(arg_typed) => {
                                 let mut tts = proc_macro2::TokenStream::new();
                                 arg_typed.ty.to_tokens(&mut tts);
-                                let ty = tts.to_string().parse().unwrap();
-                                IdlField {
+                                let ty = tts.to_string().parse()?;
+                                Ok(IdlField {
                                     name: parser::tts_to_string(&arg_typed.pat).to_mixed_case(),
                                     ty,
-                                }
+                                    docs: None,
+                                })
                             }
                             _ => panic!("Invalid syntax"),
                         })
-                        .collect();
+                        .collect::<Result<Vec<_>>>()?;
                     let accounts_strct = accs.get(&anchor_ident.to_string()).unwrap();
                     let accounts = accounts_strct.idl_accounts(&accs);
                     IdlStateMethod {
                         name,
+                        docs: parser::docs(&ctor.attrs),
                         args,
                         accounts,
+                        returns: None,
                     }
                 };
 
@@ -109,18 +145,32 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
                             .map(|f: &syn::Field| {
                                 let mut tts = proc_macro2::TokenStream::new();
                                 f.ty.to_tokens(&mut tts);
-                                let ty = tts.to_string().parse().unwrap();
-                                IdlField {
+                                let ty = tts.to_string().parse()?;
+                                Ok(IdlField {
                                     name: f.ident.as_ref().unwrap().to_string().to_mixed_case(),
                                     ty,
-                                }
+                                    docs: parser::docs(&f.attrs),
+                                })
                             })
-                            .collect::<Vec<IdlField>>(),
+                            .collect::<Result<Vec<IdlField>>>()?,
                         _ => panic!("State must be a struct"),
                     };
                     IdlTypeDef {
                         name: state.name,
-                        ty: IdlTypeDefTy::Struct { fields },
+                        ty: IdlTypeDefTy::Struct {
+                            fields: Some(EnumFields::Named(fields)),
+                        },
+                        docs: parser::docs(&state.strct.attrs),
+                        generics: vec![],
+                        serialization: match state.is_zero_copy {
+                            true => IdlSerialization::Bytemuck,
+                            false => IdlSerialization::Borsh,
+                        },
+                        repr: match state.is_zero_copy {
+                            true => Some(IdlRepr::Packed),
+                            false => None,
+                        },
+                        layout: None,
                     }
                 };
 
@@ -143,34 +193,42 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
     let instructions = p
         .ixs
         .iter()
-        .map(|ix| {
+        .map(|ix| -> Result<IdlIx> {
             let args = ix
                 .args
                 .iter()
                 .map(|arg| {
                     let mut tts = proc_macro2::TokenStream::new();
                     arg.raw_arg.ty.to_tokens(&mut tts);
-                    let ty = tts.to_string().parse().unwrap();
-                    IdlField {
+                    let ty = tts.to_string().parse()?;
+                    Ok(IdlField {
                         name: arg.name.to_string().to_mixed_case(),
                         ty,
-                    }
+                        docs: None,
+                    })
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
             // todo: don't unwrap
             let accounts_strct = accs.get(&ix.anchor_ident.to_string()).unwrap();
             let accounts = accounts_strct.idl_accounts(&accs);
-            IdlIx {
+            let returns = ix
+                .returns
+                .as_ref()
+                .map(|ty| parser::tts_to_string(ty).parse())
+                .transpose()?;
+            Ok(IdlIx {
                 name: ix.ident.to_string().to_mixed_case(),
+                docs: parser::docs(&ix.raw_method.attrs),
                 accounts,
                 args,
-            }
+                returns,
+            })
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>>>()?;
 
     let events = parse_events(&f)
         .iter()
-        .map(|e: &&syn::ItemStruct| {
+        .map(|e: &&syn::ItemStruct| -> Result<IdlEvent> {
             let fields = match &e.fields {
                 syn::Fields::Named(n) => n,
                 _ => panic!("Event fields must be named"),
@@ -178,25 +236,25 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
             let fields = fields
                 .named
                 .iter()
-                .map(|f: &syn::Field| {
+                .map(|f: &syn::Field| -> Result<IdlEventField> {
                     let index = match f.attrs.iter().next() {
                         None => false,
                         Some(i) => parser::tts_to_string(&i.path) == "index",
                     };
-                    IdlEventField {
+                    Ok(IdlEventField {
                         name: f.ident.clone().unwrap().to_string(),
-                        ty: parser::tts_to_string(&f.ty).to_string().parse().unwrap(),
+                        ty: parser::tts_to_string(&f.ty).to_string().parse()?,
                         index,
-                    }
+                    })
                 })
-                .collect::<Vec<IdlEventField>>();
+                .collect::<Result<Vec<IdlEventField>>>()?;
 
-            IdlEvent {
+            Ok(IdlEvent {
                 name: e.ident.to_string(),
                 fields,
-            }
+            })
         })
-        .collect::<Vec<IdlEvent>>();
+        .collect::<Result<Vec<IdlEvent>>>()?;
 
     // All user defined types.
     let mut accounts = vec![];
@@ -221,9 +279,11 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
         }
     }
 
-    Ok(Idl {
+    let mut idl = Idl {
         version: "0.0.0".to_string(),
+        spec: IDL_SPEC.to_string(),
         name: p.name.to_string(),
+        docs: parser::docs(&p.program_mod.attrs),
         state,
         instructions,
         types,
@@ -234,10 +294,255 @@ pub fn parse(filename: impl AsRef<Path>) -> Result<Idl> {
             Some(events)
         },
         errors: error_codes,
+        constants,
         metadata: None,
+    };
+    resolve_defined_tys(&mut idl, &parse_use_renames(&f));
+    set_layouts(&mut idl);
+
+    Ok(idl)
+}
+
+// Parses the file and, recursively, the files of all modules it declares,
+// e.g., `mod state;`, into a single file containing the items of every
+// module. The program module itself is kept as is. The constants of every
+// module are appended to `consts`, since their values depend on the module
+// they're defined in, which is lost by flattening.
+fn parse_crate(
+    filename: &Path,
+    module: &[String],
+    consts: &mut Vec<CrateConst>,
+) -> Result<syn::File> {
+    let src = std::fs::read_to_string(filename)
+        .map_err(|e| anyhow!("Unable to read {}: {}", filename.display(), e))?;
+    let f = syn::parse_file(&src)
+        .map_err(|e| anyhow!("Unable to parse {}: {}", filename.display(), e))?;
+
+    // Paths of modules declared in `lib.rs`, `main.rs` and `mod.rs` files
+    // are relative to the file's directory. Otherwise, they're relative to
+    // a directory named after the file, e.g., `src/state/` for `src/state.rs`.
+    let parent = filename.parent().unwrap_or_else(|| Path::new(""));
+    let is_mod_rs = matches!(
+        filename.file_name().and_then(|n| n.to_str()),
+        Some("lib.rs") | Some("main.rs") | Some("mod.rs")
+    );
+    let dir = match is_mod_rs {
+        true => parent.to_path_buf(),
+        false => parent.join(filename.file_stem().unwrap()),
+    };
+
+    Ok(syn::File {
+        shebang: f.shebang,
+        attrs: f.attrs,
+        items: flatten_mods(f.items, module, &dir, parent, consts)?,
+    })
+}
+
+// Appends the items of all submodules to `items`. `module` is the path of
+// the items' module, `dir` the directory of the submodules' files, and
+// `path_dir` the directory `#[path]` attributes are relative to.
+fn flatten_mods(
+    items: Vec<syn::Item>,
+    module: &[String],
+    dir: &Path,
+    path_dir: &Path,
+    consts: &mut Vec<CrateConst>,
+) -> Result<Vec<syn::Item>> {
+    let mut flattened = vec![];
+    for item in items {
+        if let syn::Item::Const(item_const) = &item {
+            consts.push(CrateConst {
+                module: module.to_vec(),
+                item: item_const.clone(),
+            });
+        }
+        if let syn::Item::Mod(item_mod) = &item {
+            if !is_program_mod(item_mod) && !is_cfg_test(&item_mod.attrs) {
+                let name = item_mod.ident.to_string();
+                let path = mod_path(item_mod);
+                let submodule = [module, std::slice::from_ref(&name)].concat();
+                let mod_items = match &item_mod.content {
+                    // Inline module, e.g., `mod state { .. }`.
+                    Some((_, mod_items)) => {
+                        let mod_dir = match path {
+                            Some(path) => path_dir.join(path),
+                            None => dir.join(&name),
+                        };
+                        flatten_mods(mod_items.clone(), &submodule, &mod_dir, &mod_dir, consts)?
+                    }
+                    // Module declared in another file, e.g., `mod state;`.
+                    None => {
+                        let filename = match path {
+                            Some(path) => path_dir.join(path),
+                            None => {
+                                let filename = dir.join(format!("{}.rs", name));
+                                match filename.exists() {
+                                    true => filename,
+                                    false => dir.join(&name).join("mod.rs"),
+                                }
+                            }
+                        };
+                        parse_crate(&filename, &submodule, consts)?.items
+                    }
+                };
+                flattened.extend(mod_items);
+            }
+        }
+        flattened.push(item);
+    }
+    Ok(flattened)
+}
+
+fn is_program_mod(item_mod: &syn::ItemMod) -> bool {
+    item_mod
+        .attrs
+        .iter()
+        .any(|attr| attr.path.segments.last().unwrap().ident == "program")
+}
+
+// Returns true if the item is only compiled for tests, i.e., `#[cfg(test)]`.
+fn is_cfg_test(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cfg"))
+        .any(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => {
+                list.nested.len() == 1
+                    && matches!(
+                        list.nested.first(),
+                        Some(syn::NestedMeta::Meta(syn::Meta::Path(path))) if path.is_ident("test")
+                    )
+            }
+            _ => false,
+        })
+}
+
+// Returns the `#[path = ".."]` attribute of the module, if any.
+fn mod_path(item_mod: &syn::ItemMod) -> Option<String> {
+    item_mod
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("path"))
+        .find_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(path),
+                ..
+            })) => Some(path.value()),
+            _ => None,
+        })
+}
+
+// Returns all renaming imports, e.g., `use state::Data as Config;`, as a map
+// from the new name to the imported name.
+fn parse_use_renames(f: &syn::File) -> HashMap<String, String> {
+    fn visit(tree: &syn::UseTree, renames: &mut HashMap<String, String>) {
+        match tree {
+            syn::UseTree::Path(p) => visit(&p.tree, renames),
+            syn::UseTree::Group(g) => g.items.iter().for_each(|t| visit(t, renames)),
+            syn::UseTree::Rename(r) => {
+                renames.insert(r.rename.to_string(), r.ident.to_string());
+            }
+            syn::UseTree::Name(_) | syn::UseTree::Glob(_) => {}
+        }
+    }
+    let mut renames = HashMap::new();
+    for item in &f.items {
+        if let syn::Item::Use(item_use) = item {
+            visit(&item_use.tree, &mut renames);
+        }
+    }
+    renames
+}
+
+// Resolves references to types defined in the crate, which may be written
+// as paths, e.g., `crate::state::Data`, or through renaming imports, to the
+// name of the type definition.
+fn resolve_defined_tys(idl: &mut Idl, renames: &HashMap<String, String>) {
+    let defined: HashSet<String> = idl
+        .accounts
+        .iter()
+        .chain(idl.types.iter())
+        .map(|ty_def| ty_def.name.clone())
+        .collect();
+    let resolve = |name: &str| -> Option<String> {
+        let name = name.rsplit("::").next().unwrap();
+        if defined.contains(name) {
+            return Some(name.to_string());
+        }
+        renames
+            .get(name)
+            .filter(|name| defined.contains(*name))
+            .cloned()
+    };
+
+    let mut tys: Vec<&mut IdlType> = vec![];
+    for ix in &mut idl.instructions {
+        tys.extend(ix.args.iter_mut().map(|f| &mut f.ty));
+        tys.extend(ix.returns.as_mut());
+    }
+    if let Some(state) = &mut idl.state {
+        tys.extend(ty_def_tys(&mut state.strct));
+        for method in &mut state.methods {
+            tys.extend(method.args.iter_mut().map(|f| &mut f.ty));
+        }
+    }
+    for ty_def in idl.accounts.iter_mut().chain(idl.types.iter_mut()) {
+        tys.extend(ty_def_tys(ty_def));
+    }
+    for event in idl.events.iter_mut().flatten() {
+        tys.extend(event.fields.iter_mut().map(|f| &mut f.ty));
+    }
+    tys.extend(idl.constants.iter_mut().map(|c| &mut c.ty));
+    for ty in tys {
+        resolve_idl_type(ty, &resolve);
+    }
+}
+
+fn ty_def_tys(ty_def: &mut IdlTypeDef) -> Vec<&mut IdlType> {
+    match &mut ty_def.ty {
+        IdlTypeDefTy::Struct { fields } => fields_tys(fields),
+        IdlTypeDefTy::Enum { variants } => variants
+            .iter_mut()
+            .flat_map(|v| fields_tys(&mut v.fields))
+            .collect(),
+    }
+}
+
+fn fields_tys(fields: &mut Option<EnumFields>) -> Vec<&mut IdlType> {
+    match fields {
+        None => vec![],
+        Some(EnumFields::Named(fields)) => fields.iter_mut().map(|f| &mut f.ty).collect(),
+        Some(EnumFields::Tuple(tys)) => tys.iter_mut().collect(),
+    }
+}
+
+fn resolve_idl_type(ty: &mut IdlType, resolve: &impl Fn(&str) -> Option<String>) {
+    visit_idl_type(ty, &mut |ty| match ty {
+        IdlType::Defined(name) | IdlType::DefinedWithTypeArgs { name, .. } => {
+            if let Some(resolved) = resolve(name) {
+                *name = resolved;
+            }
+        }
+        _ => {}
     })
 }
 
+// Calls `f` on the type and, after, on all the types it's composed of.
+fn visit_idl_type(ty: &mut IdlType, f: &mut impl FnMut(&mut IdlType)) {
+    f(ty);
+    match ty {
+        IdlType::Option(ty) | IdlType::Vec(ty) | IdlType::Array(ty, _) => visit_idl_type(ty, f),
+        IdlType::Tuple(tys) | IdlType::DefinedWithTypeArgs { args: tys, .. } => {
+            tys.iter_mut().for_each(|ty| visit_idl_type(ty, f))
+        }
+        IdlType::HashMap(key, value) | IdlType::BTreeMap(key, value) => {
+            visit_idl_type(key, f);
+            visit_idl_type(value, f);
+        }
+        _ => {}
+    }
+}
+
 // Parse the main program mod.
 fn parse_program_mod(f: &syn::File) -> syn::ItemMod {
     let mods = f
@@ -313,6 +618,356 @@ fn parse_events(f: &syn::File) -> Vec<&syn::ItemStruct> {
         .collect()
 }
 
+// Parse all `#[constant]` items, in the crate or in the program module.
+// A constant of the crate, along with the path of the module defining it,
+// e.g., `["state"]` for `crate::state::MAX_LEN`.
+struct CrateConst {
+    module: Vec<String>,
+    item: syn::ItemConst,
+}
+
+// The expressions of all constants of the crate, along with the path of the
+// module defining them, keyed by their path, e.g., `["state", "MAX_LEN"]`.
+type Consts<'a> = HashMap<Vec<String>, (&'a [String], &'a syn::Expr)>;
+
+// The constants exported to the IDL, i.e., those marked with `#[constant]`.
+fn parse_consts(consts: &[CrateConst]) -> Vec<&CrateConst> {
+    consts
+        .iter()
+        .filter(|c| {
+            c.item.attrs.iter().any(|attr| {
+                let segment = attr.path.segments.last().unwrap();
+                segment.ident == "constant"
+            })
+        })
+        .collect()
+}
+
+// The constants defined directly in the inline module, whose path is `module`.
+fn mod_consts(item_mod: &syn::ItemMod, module: &[String]) -> Vec<CrateConst> {
+    item_mod
+        .content
+        .iter()
+        .flat_map(|(_, items)| items.iter())
+        .filter_map(|i| match i {
+            syn::Item::Const(item_const) => Some(CrateConst {
+                module: module.to_vec(),
+                item: item_const.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// Returns the constant the path refers to, from within `module`. `crate`,
+// `self` and `super` are followed. Other paths are looked up in `module`,
+// and then in its ancestors, as programs usually `use super::*`.
+fn resolve_const<'a>(
+    path: &[String],
+    module: &[String],
+    consts: &Consts<'a>,
+) -> Option<(&'a [String], &'a syn::Expr)> {
+    let lookup = |module: &[String], path: &[String]| consts.get(&[module, path].concat()).copied();
+    match path.first()?.as_str() {
+        "crate" => lookup(&[], &path[1..]),
+        "self" => lookup(module, &path[1..]),
+        "super" => {
+            let supers = path.iter().take_while(|s| *s == "super").count();
+            let parent = &module[..module.len().checked_sub(supers)?];
+            lookup(parent, &path[supers..])
+        }
+        _ => (0..=module.len())
+            .rev()
+            .find_map(|len| lookup(&module[..len], path)),
+    }
+}
+
+/// The value of a constant expression, as evaluated when parsing the IDL.
+/// The `value` of an `IdlConst` is read back with `ConstValue::from_idl`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Bool(bool),
+    Int(i128),
+    /// Integers above `i128::MAX`.
+    BigInt(u128),
+    Float(f64),
+    Str(String),
+    Char(char),
+    /// Byte strings, e.g., `b"seed"`.
+    Bytes(Vec<u8>),
+    Array(Vec<ConstValue>),
+    Tuple(Vec<ConstValue>),
+    Some(Box<ConstValue>),
+    None,
+}
+
+// Constants may refer to each other, but not recursively.
+const MAX_CONST_DEPTH: usize = 32;
+// Longest array written as `[value; len]` that's evaluated.
+const MAX_CONST_REPEAT: i128 = 1024;
+
+// Evaluates a constant expression of the given module, e.g., `60 * 60` or
+// `u64::MAX`, so that clients only have to read literals. Supports literals,
+// arithmetic and casts of numbers, the `MIN` and `MAX` of integer types,
+// references to other constants of the crate, and arrays, tuples and options
+// of all these. Returns `None` for anything else, e.g., function calls.
+fn eval_const(
+    expr: &syn::Expr,
+    module: &[String],
+    consts: &Consts,
+    depth: usize,
+) -> Option<ConstValue> {
+    if depth > MAX_CONST_DEPTH {
+        return None;
+    }
+    let eval = |expr: &syn::Expr| eval_const(expr, module, consts, depth + 1);
+    match expr {
+        syn::Expr::Lit(lit) => match &lit.lit {
+            syn::Lit::Bool(b) => Some(ConstValue::Bool(b.value)),
+            syn::Lit::Int(n) => n.base10_parse::<u128>().ok().map(ConstValue::from_u128),
+            syn::Lit::Float(f) => f.base10_parse::<f64>().ok().map(ConstValue::Float),
+            syn::Lit::Str(s) => Some(ConstValue::Str(s.value())),
+            syn::Lit::Char(c) => Some(ConstValue::Char(c.value())),
+            syn::Lit::Byte(b) => Some(ConstValue::Int(b.value() as i128)),
+            syn::Lit::ByteStr(s) => Some(ConstValue::Bytes(s.value())),
+            syn::Lit::Verbatim(_) => None,
+        },
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => eval(expr)?.neg(),
+        syn::Expr::Binary(binary) => eval(&binary.left)?.binary(&binary.op, eval(&binary.right)?),
+        syn::Expr::Cast(cast) => eval(&cast.expr)?.cast(&parser::tts_to_string(&cast.ty)),
+        syn::Expr::Path(path) => {
+            let segments: Vec<String> = path
+                .path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            match segments.as_slice() {
+                [.., ty, bound]
+                    if (bound == "MIN" || bound == "MAX") && int_range(ty).is_some() =>
+                {
+                    let (min, max) = int_range(ty)?;
+                    match bound.as_str() {
+                        "MIN" => Some(ConstValue::Int(min)),
+                        _ => Some(ConstValue::from_u128(max)),
+                    }
+                }
+                [.., name] if name == "None" => Some(ConstValue::None),
+                path => {
+                    let (module, expr) = resolve_const(path, module, consts)?;
+                    eval_const(expr, module, consts, depth + 1)
+                }
+            }
+        }
+        syn::Expr::Call(call) => match (&*call.func, call.args.len()) {
+            (syn::Expr::Path(func), 1) if func.path.segments.last()?.ident == "Some" => {
+                Some(ConstValue::Some(Box::new(eval(call.args.first()?)?)))
+            }
+            _ => None,
+        },
+        syn::Expr::Array(array) => array
+            .elems
+            .iter()
+            .map(eval)
+            .collect::<Option<_>>()
+            .map(ConstValue::Array),
+        syn::Expr::Repeat(repeat) => match eval(&repeat.len)? {
+            ConstValue::Int(len) if len <= MAX_CONST_REPEAT => {
+                Some(ConstValue::Array(vec![eval(&repeat.expr)?; len as usize]))
+            }
+            _ => None,
+        },
+        syn::Expr::Tuple(tuple) => tuple
+            .elems
+            .iter()
+            .map(eval)
+            .collect::<Option<_>>()
+            .map(ConstValue::Tuple),
+        syn::Expr::Paren(paren) => eval(&paren.expr),
+        syn::Expr::Group(group) => eval(&group.expr),
+        syn::Expr::Reference(reference) => eval(&reference.expr),
+        _ => None,
+    }
+}
+
+// Returns the `MIN` and `MAX` of the integer type.
+fn int_range(ty: &str) -> Option<(i128, u128)> {
+    let range = match ty {
+        "u8" => (0, u8::MAX as u128),
+        "u16" => (0, u16::MAX as u128),
+        "u32" => (0, u32::MAX as u128),
+        // BPF is a 64 bit target.
+        "u64" | "usize" => (0, u64::MAX as u128),
+        "u128" => (0, u128::MAX),
+        "i8" => (i8::MIN as i128, i8::MAX as u128),
+        "i16" => (i16::MIN as i128, i16::MAX as u128),
+        "i32" => (i32::MIN as i128, i32::MAX as u128),
+        "i64" | "isize" => (i64::MIN as i128, i64::MAX as u128),
+        "i128" => (i128::MIN, i128::MAX as u128),
+        _ => return None,
+    };
+    Some(range)
+}
+
+impl ConstValue {
+    /// Reads the `value` of an `IdlConst`, e.g., `b"seed"` or `(1, [2, 3])`.
+    /// Returns `None` if it's not a literal, i.e., if the constant couldn't be
+    /// evaluated when parsing the IDL, since it can't be evaluated outside of
+    /// the program either.
+    pub fn from_idl(value: &str) -> Option<Self> {
+        let expr: syn::Expr = syn::parse_str(value).ok()?;
+        eval_const(&expr, &[], &Consts::new(), 0)
+    }
+
+    fn from_u128(n: u128) -> Self {
+        match n > i128::MAX as u128 {
+            true => ConstValue::BigInt(n),
+            false => ConstValue::Int(n as i128),
+        }
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        match self {
+            ConstValue::Int(n) if *n >= 0 => Some(*n as u128),
+            ConstValue::BigInt(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn neg(self) -> Option<Self> {
+        match self {
+            ConstValue::Int(n) => n.checked_neg().map(ConstValue::Int),
+            ConstValue::Float(f) => Some(ConstValue::Float(-f)),
+            _ => None,
+        }
+    }
+
+    fn binary(self, op: &syn::BinOp, rhs: Self) -> Option<Self> {
+        use syn::BinOp::*;
+        match (self, rhs) {
+            (ConstValue::Int(a), ConstValue::Int(b)) => match op {
+                Add(_) => a.checked_add(b),
+                Sub(_) => a.checked_sub(b),
+                Mul(_) => a.checked_mul(b),
+                Div(_) => a.checked_div(b),
+                Rem(_) => a.checked_rem(b),
+                BitAnd(_) => Some(a & b),
+                BitOr(_) => Some(a | b),
+                BitXor(_) => Some(a ^ b),
+                Shl(_) => a.checked_shl(u32::try_from(b).ok()?),
+                Shr(_) => a.checked_shr(u32::try_from(b).ok()?),
+                _ => None,
+            }
+            .map(ConstValue::Int),
+            (ConstValue::Float(a), ConstValue::Float(b)) => match op {
+                Add(_) => Some(a + b),
+                Sub(_) => Some(a - b),
+                Mul(_) => Some(a * b),
+                Div(_) => Some(a / b),
+                Rem(_) => Some(a % b),
+                _ => None,
+            }
+            .map(ConstValue::Float),
+            // Unsigned arithmetic above `i128::MAX`.
+            (a, b) => {
+                let (a, b) = (a.to_u128()?, b.to_u128()?);
+                match op {
+                    Add(_) => a.checked_add(b),
+                    Sub(_) => a.checked_sub(b),
+                    Mul(_) => a.checked_mul(b),
+                    Div(_) => a.checked_div(b),
+                    Rem(_) => a.checked_rem(b),
+                    BitAnd(_) => Some(a & b),
+                    BitOr(_) => Some(a | b),
+                    BitXor(_) => Some(a ^ b),
+                    Shl(_) => a.checked_shl(u32::try_from(b).ok()?),
+                    Shr(_) => a.checked_shr(u32::try_from(b).ok()?),
+                    _ => None,
+                }
+                .map(ConstValue::from_u128)
+            }
+        }
+    }
+
+    // Casts the number to the given primitive type. Casts that would change
+    // the value, e.g., truncating ones, aren't evaluated.
+    fn cast(self, ty: &str) -> Option<Self> {
+        match (self, ty) {
+            (ConstValue::Float(f), "f32") | (ConstValue::Float(f), "f64") => {
+                Some(ConstValue::Float(f))
+            }
+            (ConstValue::Int(n), "f32") | (ConstValue::Int(n), "f64") => {
+                Some(ConstValue::Float(n as f64))
+            }
+            (n, ty) => {
+                let (min, max) = int_range(ty)?;
+                let fits = match &n {
+                    ConstValue::Int(n) => *n >= min && (*n < 0 || *n as u128 <= max),
+                    ConstValue::BigInt(n) => *n <= max,
+                    _ => false,
+                };
+                match fits {
+                    true => Some(n),
+                    false => None,
+                }
+            }
+        }
+    }
+
+    // Formats the value as a Rust literal.
+    fn to_source(&self) -> String {
+        let join = |values: &[ConstValue]| {
+            values
+                .iter()
+                .map(ConstValue::to_source)
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            ConstValue::Bool(b) => b.to_string(),
+            ConstValue::Int(n) => n.to_string(),
+            ConstValue::BigInt(n) => n.to_string(),
+            ConstValue::Float(f) => format!("{:?}", f),
+            // `Debug` escapes as Rust literals do.
+            ConstValue::Str(s) => format!("{:?}", s),
+            ConstValue::Char(c) => format!("{:?}", c),
+            ConstValue::Bytes(bytes) => format!(
+                "b\"{}\"",
+                bytes
+                    .iter()
+                    .flat_map(|b| std::ascii::escape_default(*b))
+                    .map(char::from)
+                    .collect::<String>()
+            ),
+            ConstValue::Array(values) => format!("[{}]", join(values)),
+            ConstValue::Tuple(values) if values.len() == 1 => format!("({},)", join(values)),
+            ConstValue::Tuple(values) => format!("({})", join(values)),
+            ConstValue::Some(value) => format!("Some({})", value.to_source()),
+            ConstValue::None => "None".to_string(),
+        }
+    }
+}
+
+// Constants may be references, e.g., `&[u8]` or `&str`, which are encoded
+// as their owned counterparts.
+fn const_ty(ty: &syn::Type) -> Result<IdlType> {
+    match ty {
+        syn::Type::Reference(r) => match &*r.elem {
+            syn::Type::Slice(slice) => match const_ty(&slice.elem)? {
+                IdlType::U8 => Ok(IdlType::Bytes),
+                ty => Ok(IdlType::Vec(Box::new(ty))),
+            },
+            syn::Type::Path(path) if path.path.is_ident("str") => Ok(IdlType::String),
+            elem => const_ty(elem),
+        },
+        _ => parser::tts_to_string(ty).parse(),
+    }
+}
+
 fn parse_accounts(f: &syn::File) -> Vec<&syn::ItemStruct> {
     f.items
         .iter()
@@ -360,7 +1015,8 @@ fn parse_account_derives(f: &syn::File) -> HashMap<String, AccountsStruct> {
 
 // Parse all user defined types in the file.
 fn parse_ty_defs(f: &syn::File) -> Result<Vec<IdlTypeDef>> {
-    f.items
+    let ty_defs = f
+        .items
         .iter()
         .filter_map(|i| match i {
             syn::Item::Struct(item_strct) => {
@@ -370,72 +1026,807 @@ fn parse_ty_defs(f: &syn::File) -> Result<Vec<IdlTypeDef>> {
                     }
                 }
                 if let syn::Visibility::Public(_) = &item_strct.vis {
-                    let name = item_strct.ident.to_string();
-                    let fields = match &item_strct.fields {
-                        syn::Fields::Named(fields) => fields
-                            .named
-                            .iter()
-                            .map(|f: &syn::Field| {
-                                let mut tts = proc_macro2::TokenStream::new();
-                                f.ty.to_tokens(&mut tts);
-                                Ok(IdlField {
-                                    name: f.ident.as_ref().unwrap().to_string().to_mixed_case(),
-                                    ty: tts.to_string().parse()?,
-                                })
-                            })
-                            .collect::<Result<Vec<IdlField>>>(),
-                        _ => panic!("Only named structs are allowed."),
-                    };
-
-                    return Some(fields.map(|fields| IdlTypeDef {
-                        name,
-                        ty: IdlTypeDefTy::Struct { fields },
-                    }));
+                    return Some(parse_struct(item_strct));
                 }
                 None
             }
-            syn::Item::Enum(enm) => {
-                let name = enm.ident.to_string();
-                let variants = enm
-                    .variants
-                    .iter()
-                    .map(|variant: &syn::Variant| {
-                        let name = variant.ident.to_string();
-                        let fields = match &variant.fields {
-                            syn::Fields::Unit => None,
-                            syn::Fields::Unnamed(fields) => {
-                                let fields: Vec<IdlType> =
-                                    fields.unnamed.iter().map(to_idl_type).collect();
-                                Some(EnumFields::Tuple(fields))
-                            }
-                            syn::Fields::Named(fields) => {
-                                let fields: Vec<IdlField> = fields
-                                    .named
-                                    .iter()
-                                    .map(|f: &syn::Field| {
-                                        let name = f.ident.as_ref().unwrap().to_string();
-                                        let ty = to_idl_type(f);
-                                        IdlField { name, ty }
-                                    })
-                                    .collect();
-                                Some(EnumFields::Named(fields))
-                            }
-                        };
-                        EnumVariant { name, fields }
-                    })
-                    .collect::<Vec<EnumVariant>>();
-                Some(Ok(IdlTypeDef {
-                    name,
-                    ty: IdlTypeDefTy::Enum { variants },
-                }))
-            }
+            syn::Item::Enum(enm) => Some(parse_enum(enm)),
             _ => None,
         })
-        .collect()
+        .collect::<Result<Vec<IdlTypeDef>>>()?;
+
+    // The items of all modules are flattened into one namespace, as types
+    // are referred to by name in the IDL, so names must be unique.
+    let mut names = HashSet::new();
+    for ty_def in &ty_defs {
+        if !names.insert(&ty_def.name) {
+            return Err(anyhow!(
+                "Type {} is defined in more than one module, so it's ambiguous in the IDL",
+                ty_def.name
+            ));
+        }
+    }
+    Ok(ty_defs)
+}
+
+fn parse_struct(strct: &syn::ItemStruct) -> Result<IdlTypeDef> {
+    let fields = match &strct.fields {
+        syn::Fields::Named(fields) => Some(EnumFields::Named(
+            fields
+                .named
+                .iter()
+                .map(|f: &syn::Field| {
+                    Ok(IdlField {
+                        name: f.ident.as_ref().unwrap().to_string().to_mixed_case(),
+                        ty: to_idl_type(f)?,
+                        docs: parser::docs(&f.attrs),
+                    })
+                })
+                .collect::<Result<Vec<IdlField>>>()?,
+        )),
+        syn::Fields::Unnamed(fields) => Some(EnumFields::Tuple(
+            fields
+                .unnamed
+                .iter()
+                .map(to_idl_type)
+                .collect::<Result<Vec<IdlType>>>()?,
+        )),
+        syn::Fields::Unit => None,
+    };
+    let repr = parser::zero_copy_repr(&strct.attrs).map(|repr| match repr {
+        "packed" => IdlRepr::Packed,
+        _ => IdlRepr::C,
+    });
+    generic_ty_def(
+        IdlTypeDef {
+            name: strct.ident.to_string(),
+            ty: IdlTypeDefTy::Struct { fields },
+            docs: parser::docs(&strct.attrs),
+            generics: vec![],
+            serialization: match repr {
+                Some(_) => IdlSerialization::Bytemuck,
+                None => IdlSerialization::Borsh,
+            },
+            repr,
+            layout: None,
+        },
+        &strct.generics,
+    )
+}
+
+fn parse_enum(enm: &syn::ItemEnum) -> Result<IdlTypeDef> {
+    let variants = enm
+        .variants
+        .iter()
+        .map(|variant: &syn::Variant| -> Result<EnumVariant> {
+            let name = variant.ident.to_string();
+            let fields = match &variant.fields {
+                syn::Fields::Unit => None,
+                syn::Fields::Unnamed(fields) => {
+                    let fields = fields
+                        .unnamed
+                        .iter()
+                        .map(to_idl_type)
+                        .collect::<Result<Vec<IdlType>>>()?;
+                    Some(EnumFields::Tuple(fields))
+                }
+                syn::Fields::Named(fields) => {
+                    let fields = fields
+                        .named
+                        .iter()
+                        .map(|f: &syn::Field| {
+                            let name = f.ident.as_ref().unwrap().to_string();
+                            let ty = to_idl_type(f)?;
+                            Ok(IdlField {
+                                name,
+                                ty,
+                                docs: parser::docs(&f.attrs),
+                            })
+                        })
+                        .collect::<Result<Vec<IdlField>>>()?;
+                    Some(EnumFields::Named(fields))
+                }
+            };
+            Ok(EnumVariant { name, fields })
+        })
+        .collect::<Result<Vec<EnumVariant>>>()?;
+    generic_ty_def(
+        IdlTypeDef {
+            name: enm.ident.to_string(),
+            ty: IdlTypeDefTy::Enum { variants },
+            docs: parser::docs(&enm.attrs),
+            generics: vec![],
+            serialization: IdlSerialization::Borsh,
+            repr: None,
+            layout: None,
+        },
+        &enm.generics,
+    )
+}
+
+// Sets the type parameters of the type definition, replacing references to
+// them, which are parsed as user defined types, with `IdlType::Generic`.
+fn generic_ty_def(mut ty_def: IdlTypeDef, generics: &syn::Generics) -> Result<IdlTypeDef> {
+    for param in &generics.params {
+        match param {
+            syn::GenericParam::Type(ty) => ty_def.generics.push(ty.ident.to_string()),
+            syn::GenericParam::Lifetime(_) => {}
+            syn::GenericParam::Const(c) => {
+                return Err(anyhow!(
+                    "Const generics aren't supported: {}::{}",
+                    ty_def.name,
+                    c.ident
+                ))
+            }
+        }
+    }
+    let params = ty_def.generics.clone();
+    for ty in ty_def_tys(&mut ty_def) {
+        visit_idl_type(ty, &mut |ty| {
+            if let IdlType::Defined(name) = ty {
+                if params.contains(name) {
+                    *ty = IdlType::Generic(name.clone());
+                }
+            }
+        });
+    }
+    Ok(ty_def)
 }
 
-fn to_idl_type(f: &syn::Field) -> IdlType {
+fn to_idl_type(f: &syn::Field) -> Result<IdlType> {
     let mut tts = proc_macro2::TokenStream::new();
     f.ty.to_tokens(&mut tts);
-    tts.to_string().parse().unwrap()
+    tts.to_string().parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parses the IDL of the program in `examples/` with the given path.
+    pub fn example(path: &str) -> Idl {
+        let path = format!("{}/../../examples/{}", env!("CARGO_MANIFEST_DIR"), path);
+        parse(path).unwrap()
+    }
+
+    // Writes the files to a temporary crate, removed once parsed, and parses
+    // the IDL of its `lib.rs`.
+    pub fn parse_files(name: &str, files: &[(&str, &str)]) -> Result<Idl> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("anchor-syn-{}", name))
+            .tempdir()
+            .unwrap();
+        for (path, src) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, src).unwrap();
+        }
+        parse(dir.path().join("lib.rs"))
+    }
+
+    pub fn type_def<'a>(idl: &'a Idl, name: &str) -> &'a IdlTypeDef {
+        idl.accounts
+            .iter()
+            .chain(idl.types.iter())
+            .find(|ty_def| ty_def.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn types_from_submodules() {
+        // Defined in `src/pc.rs`, not `src/lib.rs`.
+        let idl = example("pyth/programs/pyth/src/lib.rs");
+        assert_eq!(type_def(&idl, "PriceInfo").name, "PriceInfo");
+        assert_eq!(type_def(&idl, "CorpAction").name, "CorpAction");
+        // `#[repr(C)]` alone doesn't make a type zero copy.
+        assert_eq!(
+            type_def(&idl, "PriceInfo").serialization,
+            IdlSerialization::Borsh
+        );
+        assert_eq!(type_def(&idl, "PriceInfo").repr, None);
+    }
+
+    #[test]
+    fn nested_path_and_renamed_modules() {
+        let idl = parse_files(
+            "modules",
+            &[
+                (
+                    "lib.rs",
+                    r#"
+                    mod state;
+                    #[path = "other/renamed.rs"]
+                    mod config;
+                    #[cfg(test)]
+                    mod tests;
+                    #[cfg(feature = "testing")]
+                    mod testing;
+
+                    use config::Settings as Config;
+
+                    #[program]
+                    pub mod modules {
+                        use super::*;
+
+                        pub fn initialize(
+                            ctx: Context<Initialize>,
+                            data: crate::state::Data,
+                            config: Config,
+                        ) -> ProgramResult {
+                            Ok(())
+                        }
+                    }
+
+                    #[derive(Accounts)]
+                    pub struct Initialize {}
+                    "#,
+                ),
+                (
+                    "state/mod.rs",
+                    "mod inner; pub struct Data { pub inner: inner::Inner }",
+                ),
+                ("state/inner.rs", "pub struct Inner { pub x: u8 }"),
+                ("other/renamed.rs", "pub struct Settings { pub y: u16 }"),
+                ("testing.rs", "pub struct Fixture { pub z: u8 }"),
+            ],
+        )
+        .unwrap();
+        let args: Vec<&IdlType> = idl.instructions[0].args.iter().map(|a| &a.ty).collect();
+        assert_eq!(
+            args,
+            vec![
+                &IdlType::Defined("Data".to_string()),
+                &IdlType::Defined("Settings".to_string()),
+            ]
+        );
+        assert_eq!(
+            type_def(&idl, "Data").ty,
+            IdlTypeDefTy::Struct {
+                fields: Some(EnumFields::Named(vec![IdlField {
+                    name: "inner".to_string(),
+                    ty: IdlType::Defined("Inner".to_string()),
+                    docs: None,
+                }])),
+            }
+        );
+        assert_eq!(type_def(&idl, "Inner").name, "Inner");
+        // Only `#[cfg(test)]` modules are skipped.
+        assert_eq!(type_def(&idl, "Fixture").name, "Fixture");
+    }
+
+    #[test]
+    fn duplicate_type_names() {
+        let err = parse_files(
+            "duplicates",
+            &[
+                (
+                    "lib.rs",
+                    r#"
+                    mod a;
+                    mod b;
+
+                    #[program]
+                    pub mod duplicates {
+                        use super::*;
+
+                        pub fn initialize(ctx: Context<Initialize>) -> ProgramResult {
+                            Ok(())
+                        }
+                    }
+
+                    #[derive(Accounts)]
+                    pub struct Initialize {}
+                    "#,
+                ),
+                ("a.rs", "pub struct Data { pub x: u8 }"),
+                ("b.rs", "pub enum Data { X }"),
+            ],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Type Data"));
+    }
+
+    #[test]
+    fn doc_comments() {
+        let idl = example("lockup/programs/lockup/src/lib.rs");
+        let fields = match &type_def(&idl, "Vesting").ty {
+            IdlTypeDefTy::Struct {
+                fields: Some(EnumFields::Named(fields)),
+            } => fields,
+            _ => panic!("Vesting must be a struct"),
+        };
+        assert_eq!(
+            fields[0].docs,
+            Some(vec!["The owner of this Vesting account.".to_string()])
+        );
+        assert_eq!(
+            fields[4].docs,
+            Some(vec![
+                "The outstanding SRM deposit backing this vesting account. All".to_string(),
+                "withdrawals will deduct this balance.".to_string(),
+            ])
+        );
+
+        let idl = parse_files(
+            "docs",
+            &[(
+                "lib.rs",
+                r#"
+                /// The program.
+                #[program]
+                pub mod docs {
+                    use super::*;
+
+                    /// Initializes
+                    ///   the account.
+                    pub fn initialize(ctx: Context<Initialize>) -> ProgramResult {
+                        Ok(())
+                    }
+                }
+
+                #[derive(Accounts)]
+                pub struct Initialize {}
+
+                /// Sides.
+                pub enum Side {
+                    /// Not exported.
+                    Bid,
+                    Ask { /** The size. */ size: u64 },
+                }
+                "#,
+            )],
+        )
+        .unwrap();
+        assert_eq!(idl.docs, Some(vec!["The program.".to_string()]));
+        assert_eq!(
+            idl.instructions[0].docs,
+            Some(vec!["Initializes".to_string(), "the account.".to_string()])
+        );
+        let side = type_def(&idl, "Side");
+        assert_eq!(side.docs, Some(vec!["Sides.".to_string()]));
+        assert_eq!(
+            side.ty,
+            IdlTypeDefTy::Enum {
+                variants: vec![
+                    EnumVariant {
+                        name: "Bid".to_string(),
+                        fields: None,
+                    },
+                    EnumVariant {
+                        name: "Ask".to_string(),
+                        fields: Some(EnumFields::Named(vec![IdlField {
+                            name: "size".to_string(),
+                            ty: IdlType::U64,
+                            docs: Some(vec!["The size.".to_string()]),
+                        }])),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn generic_tuple_and_unit_structs() {
+        let idl = parse_files(
+            "generics",
+            &[(
+                "lib.rs",
+                r#"
+                #[program]
+                pub mod generics {
+                    use super::*;
+                }
+
+                pub struct Pair<'a, T> {
+                    pub first: T,
+                    pub second: Wrapper<Option<T>>,
+                }
+
+                pub struct Wrapper<T>(T, u8);
+
+                pub struct Unit;
+
+                pub struct Generics {
+                    pub pair: Pair<'static, u16>,
+                    pub unit: Option<Unit>,
+                }
+                "#,
+            )],
+        )
+        .unwrap();
+        let pair = type_def(&idl, "Pair");
+        assert_eq!(pair.generics, vec!["T".to_string()]);
+        assert_eq!(
+            pair.ty,
+            IdlTypeDefTy::Struct {
+                fields: Some(EnumFields::Named(vec![
+                    IdlField {
+                        name: "first".to_string(),
+                        ty: IdlType::Generic("T".to_string()),
+                        docs: None,
+                    },
+                    IdlField {
+                        name: "second".to_string(),
+                        ty: IdlType::DefinedWithTypeArgs {
+                            name: "Wrapper".to_string(),
+                            args: vec![IdlType::Option(Box::new(IdlType::Generic(
+                                "T".to_string()
+                            )))],
+                        },
+                        docs: None,
+                    },
+                ])),
+            }
+        );
+        assert_eq!(
+            type_def(&idl, "Wrapper").ty,
+            IdlTypeDefTy::Struct {
+                fields: Some(EnumFields::Tuple(vec![
+                    IdlType::Generic("T".to_string()),
+                    IdlType::U8
+                ])),
+            }
+        );
+        assert_eq!(
+            type_def(&idl, "Unit").ty,
+            IdlTypeDefTy::Struct { fields: None }
+        );
+        match &type_def(&idl, "Generics").ty {
+            IdlTypeDefTy::Struct {
+                fields: Some(EnumFields::Named(fields)),
+            } => assert_eq!(
+                fields[0].ty,
+                IdlType::DefinedWithTypeArgs {
+                    name: "Pair".to_string(),
+                    args: vec![IdlType::U16],
+                }
+            ),
+            _ => panic!("Generics must be a struct"),
+        }
+    }
+
+    #[test]
+    fn const_generics_unsupported() {
+        let idl = parse_files(
+            "const-generics",
+            &[(
+                "lib.rs",
+                "#[program] pub mod p {} pub struct Buf<const N: usize> { pub data: [u8; 4] }",
+            )],
+        );
+        assert_eq!(
+            idl.unwrap_err().to_string(),
+            "Const generics aren't supported: Buf::N"
+        );
+    }
+
+    #[test]
+    fn zero_copy_types() {
+        let idl = example("zero-copy/programs/zero-copy/src/lib.rs");
+        let foo = type_def(&idl, "Foo");
+        assert_eq!(foo.serialization, IdlSerialization::Bytemuck);
+        assert_eq!(foo.repr, Some(IdlRepr::Packed));
+        let layout = foo.layout.as_ref().unwrap();
+        assert_eq!((layout.size, layout.align), (80, 1));
+        let globals = &idl.state.as_ref().unwrap().strct;
+        assert_eq!(globals.repr, Some(IdlRepr::Packed));
+        assert_eq!(globals.layout.as_ref().unwrap().size, 32 + 250 * 40);
+        assert_eq!(type_def(&idl, "Bar").repr, Some(IdlRepr::Packed));
+        let event_q = type_def(&idl, "EventQ").layout.as_ref().unwrap();
+        assert_eq!(event_q.size, 25000 * 40);
+    }
+
+    fn ix_account<'a>(idl: &'a Idl, ix: &str, name: &str) -> &'a IdlAccount {
+        let ix = idl.instructions.iter().find(|i| i.name == ix).unwrap();
+        ix.accounts
+            .iter()
+            .find_map(|item| match item {
+                IdlAccountItem::IdlAccount(acc) if acc.name == name => Some(acc),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn account_constraints() {
+        let idl = example("misc/programs/misc/src/lib.rs");
+        let acc = ix_account(&idl, "testInitAssociatedAccount", "myAccount");
+        let seeds = vec![
+            IdlSeed::Const {
+                value: b"anchor".to_vec(),
+            },
+            IdlSeed::Account {
+                path: "authority".to_string(),
+            },
+            IdlSeed::Account {
+                path: "state".to_string(),
+            },
+            IdlSeed::Account {
+                path: "data".to_string(),
+            },
+        ];
+        assert_eq!(
+            acc.pda,
+            Some(IdlPda {
+                seeds: seeds.clone(),
+                find_bump: true,
+            })
+        );
+        assert_eq!(
+            acc.init,
+            Some(IdlInit {
+                payer: Some("authority".to_string()),
+                space: None,
+            })
+        );
+        let acc = ix_account(&idl, "testAssociatedAccount", "myAccount");
+        assert_eq!(acc.pda.as_ref().unwrap().seeds, seeds);
+        assert_eq!(acc.init, None);
+
+        let idl = example("chat/programs/chat/src/lib.rs");
+        let acc = ix_account(&idl, "createUser", "user");
+        assert_eq!(acc.init.as_ref().unwrap().space, Some("312".to_string()));
+        let acc = ix_account(&idl, "sendMessage", "user");
+        assert_eq!(acc.relations, vec!["authority".to_string()]);
+
+        let idl = example("multisig/programs/multisig/src/lib.rs");
+        let acc = ix_account(&idl, "executeTransaction", "multisigSigner");
+        assert_eq!(
+            acc.pda,
+            Some(IdlPda {
+                seeds: vec![
+                    IdlSeed::Account {
+                        path: "multisig".to_string(),
+                    },
+                    IdlSeed::Expr {
+                        expr: "&[multisig.nonce]".to_string(),
+                    },
+                ],
+                find_bump: false,
+            })
+        );
+        let acc = ix_account(&idl, "executeTransaction", "transaction");
+        assert_eq!(acc.relations, vec!["multisig".to_string()]);
+
+        let idl = example("ido-pool/programs/ido-pool/src/lib.rs");
+        let acc = ix_account(&idl, "initializePool", "tokenProgram");
+        assert_eq!(
+            acc.constraints,
+            vec!["token_program.key == &token::ID".to_string()]
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let idl = parse_files(
+            "constants",
+            &[(
+                "lib.rs",
+                r#"
+                /// Seed of the vault.
+                #[constant]
+                pub const VAULT_SEED: &[u8] = b"vault";
+
+                #[constant]
+                pub const NAME: &str = "vault\n";
+
+                pub const HOUR: i64 = 60 * 60;
+
+                #[program]
+                pub mod constants {
+                    use super::*;
+
+                    #[constant]
+                    pub const FEE_BPS: u16 = 30;
+
+                    #[constant]
+                    pub const MAX_SUPPLY: u128 = 0xffff_ffff_ffff_ffff_ffff;
+
+                    #[constant]
+                    pub const OFFSET: i64 = -(1_000);
+
+                    #[constant]
+                    pub const RATE: f64 = -0.5 * 2.0;
+
+                    #[constant]
+                    pub const LIMITS: (u8, [u16; 2]) = (1, [2, 3]);
+
+                    #[constant]
+                    pub const DERIVED: u64 = FEE_BPS as u64 * 2;
+
+                    #[constant]
+                    pub const DAY: i64 = 24 * crate::HOUR;
+
+                    #[constant]
+                    pub const MAX: u128 = u128::MAX >> 1 | u128::MAX;
+
+                    #[constant]
+                    pub const MIN: i8 = i8::MIN;
+
+                    #[constant]
+                    pub const PADDING: [u8; 3] = [0; 3];
+
+                    #[constant]
+                    pub const QUOTE: Option<(u8,)> = Some((4,));
+
+                    #[constant]
+                    pub const UNKNOWN: u64 = compute(HOUR);
+
+                    #[constant]
+                    pub const OVERFLOW: u8 = 255 + 1;
+
+                    #[constant]
+                    pub const TRUNCATED: u8 = 256 as u8;
+                }
+                "#,
+            )],
+        )
+        .unwrap();
+        let constants: Vec<(&str, &IdlType, &str)> = idl
+            .constants
+            .iter()
+            .map(|c| (c.name.as_str(), &c.ty, c.value.as_str()))
+            .collect();
+        assert_eq!(
+            constants,
+            vec![
+                ("VAULT_SEED", &IdlType::Bytes, "b\"vault\""),
+                ("NAME", &IdlType::String, "\"vault\\n\""),
+                ("FEE_BPS", &IdlType::U16, "30"),
+                ("MAX_SUPPLY", &IdlType::U128, "1208925819614629174706175"),
+                ("OFFSET", &IdlType::I64, "-1000"),
+                ("RATE", &IdlType::F64, "-1.0"),
+                ("LIMITS", &"(u8, [u16; 2])".parse().unwrap(), "(1, [2, 3])"),
+                ("DERIVED", &IdlType::U64, "60"),
+                ("DAY", &IdlType::I64, "86400"),
+                ("MAX", &IdlType::U128, &u128::MAX.to_string()[..]),
+                ("MIN", &IdlType::I8, "-128"),
+                ("PADDING", &IdlType::ByteArray(3), "[0, 0, 0]"),
+                ("QUOTE", &"Option<(u8,)>".parse().unwrap(), "Some((4,))"),
+                // Left as written.
+                ("UNKNOWN", &IdlType::U64, "compute(HOUR)"),
+                ("OVERFLOW", &IdlType::U8, "255 + 1"),
+                ("TRUNCATED", &IdlType::U8, "256 as u8"),
+            ]
+        );
+        assert_eq!(
+            idl.constants[0].docs,
+            Some(vec!["Seed of the vault.".to_string()])
+        );
+    }
+
+    #[test]
+    fn constants_of_modules() {
+        let idl = parse_files(
+            "module-constants",
+            &[
+                (
+                    "lib.rs",
+                    r#"
+                    mod fees;
+                    mod limits {
+                        pub const MAX: u64 = 10;
+                        pub const RATE: u64 = 2;
+                    }
+
+                    pub const RATE: u64 = 5;
+
+                    #[program]
+                    pub mod modules {
+                        use super::*;
+
+                        #[constant]
+                        pub const ROOT_RATE: u64 = RATE * 2;
+
+                        #[constant]
+                        pub const LIMIT_RATE: u64 = limits::RATE * 2;
+
+                        #[constant]
+                        pub const LIMIT: u64 = crate::limits::MAX;
+
+                        #[constant]
+                        pub const FEE: u64 = fees::FEE;
+                    }
+                    "#,
+                ),
+                (
+                    "fees.rs",
+                    "const RATE: u64 = 1; pub const FEE: u64 = super::RATE + self::RATE;",
+                ),
+            ],
+        )
+        .unwrap();
+        let constants: Vec<(&str, &str)> = idl
+            .constants
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(
+            constants,
+            vec![
+                ("ROOT_RATE", "10"),
+                ("LIMIT_RATE", "4"),
+                ("LIMIT", "10"),
+                ("FEE", "6"),
+            ]
+        );
+    }
+
+    #[test]
+    fn const_values_from_idl() {
+        for value in &[
+            "b\"a\\n\\x00\"",
+            "\"\\u{1f600}\"",
+            "'\\''",
+            "(1, [-2, 3])",
+            "Some(1.5)",
+        ] {
+            let parsed = ConstValue::from_idl(value).unwrap();
+            assert_eq!(ConstValue::from_idl(&parsed.to_source()), Some(parsed));
+        }
+        assert_eq!(
+            ConstValue::from_idl("b\"ab\""),
+            Some(ConstValue::Bytes(b"ab".to_vec()))
+        );
+        assert_eq!(ConstValue::from_idl("compute(HOUR)"), None);
+        assert_eq!(ConstValue::from_idl("HOUR"), None);
+    }
+
+    #[test]
+    fn instruction_returns() {
+        let idl = parse_files(
+            "returns",
+            &[(
+                "lib.rs",
+                r#"
+                #[program]
+                pub mod returns {
+                    use super::*;
+
+                    pub fn nothing(ctx: Context<Empty>) -> ProgramResult {
+                        Ok(())
+                    }
+
+                    pub fn unit(ctx: Context<Empty>) -> Result<()> {
+                        Ok(())
+                    }
+
+                    pub fn amount(ctx: Context<Empty>) -> Result<u64> {
+                        Ok(1)
+                    }
+
+                    pub fn quote(
+                        ctx: Context<Empty>,
+                    ) -> Result<Option<crate::Quote>, ProgramError> {
+                        Ok(None)
+                    }
+                }
+
+                #[derive(Accounts)]
+                pub struct Empty {}
+
+                #[derive(AnchorSerialize, AnchorDeserialize)]
+                pub struct Quote {
+                    pub price: u64,
+                }
+                "#,
+            )],
+        )
+        .unwrap();
+        let returns: Vec<(&str, Option<&IdlType>)> = idl
+            .instructions
+            .iter()
+            .map(|ix| (ix.name.as_str(), ix.returns.as_ref()))
+            .collect();
+        let quote = IdlType::Option(Box::new(IdlType::Defined("Quote".to_string())));
+        assert_eq!(
+            returns,
+            vec![
+                ("nothing", None),
+                ("unit", None),
+                ("amount", Some(&IdlType::U64)),
+                ("quote", Some(&quote)),
+            ]
+        );
+    }
 }
@@ -9,3 +9,206 @@ pub fn tts_to_string<T: quote::ToTokens>(item: T) -> String {
     item.to_tokens(&mut tts);
     tts.to_string()
 }
+
+// Returns the lines of the doc comments, i.e., `#[doc = ".."]` attributes, if
+// any.
+pub fn docs(attrs: &[syn::Attribute]) -> Option<Vec<String>> {
+    let docs: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                lit: syn::Lit::Str(doc),
+                ..
+            })) => Some(doc.value().trim().to_string()),
+            _ => None,
+        })
+        .collect();
+    match docs.is_empty() {
+        true => None,
+        false => Some(docs),
+    }
+}
+
+// Returns the memory representation of a zero copy struct, named as in the
+// IDL, i.e., `"packed"` for `#[zero_copy]`, `#[account(zero_copy)]`, etc.,
+// which add `#[repr(packed)]`. Other structs are Borsh serialized, even with
+// a `#[repr]`, since `#[derive(Pod)]` can't be relied on to be visible.
+pub fn zero_copy_repr(attrs: &[syn::Attribute]) -> Option<&'static str> {
+    let is_zero_copy = attrs.iter().any(|attr| {
+        let ident = &attr.path.segments.last().unwrap().ident;
+        if ident == "zero_copy" {
+            return true;
+        }
+        if ident != "account" && ident != "associated" && ident != "state" {
+            return false;
+        }
+        match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => list.nested.iter().any(|arg| match arg {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => path.is_ident("zero_copy"),
+                _ => false,
+            }),
+            _ => false,
+        }
+    });
+    match is_zero_copy {
+        true => Some("packed"),
+        false => None,
+    }
+}
+
+// Formats the tokens as usually written, e.g., `a.b == &c::D` rather than
+// `a . b == & c :: D`, so that the result doesn't depend on whether the
+// tokens come from the compiler or were parsed from a file.
+pub fn tts_to_source(tts: proc_macro2::TokenStream) -> String {
+    use proc_macro2::{Delimiter, Spacing, TokenTree};
+
+    #[derive(PartialEq)]
+    enum Prev {
+        // Nothing, or a separator, e.g., `,`.
+        Start,
+        // An identifier, literal or group.
+        Operand,
+        // A binary operator, including the space after it.
+        Op,
+        // The first character of an operator, e.g., `=` of `==`.
+        JointOp,
+        // Tokens the next one is joined to, e.g., `.`, `::` or a unary `&`.
+        Joined,
+    }
+
+    let mut src = String::new();
+    let mut prev = Prev::Start;
+    for tt in tts {
+        match tt {
+            TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                if prev == Prev::Operand {
+                    src.push(' ');
+                }
+                src.push_str(&tt.to_string());
+                prev = Prev::Operand;
+            }
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{ ", " }"),
+                    Delimiter::None => ("", ""),
+                };
+                // Calls and indexing are joined to the preceding operand.
+                if prev == Prev::Operand && g.delimiter() == Delimiter::Brace {
+                    src.push(' ');
+                }
+                src.push_str(open);
+                src.push_str(&tts_to_source(g.stream()));
+                src.push_str(close);
+                prev = Prev::Operand;
+            }
+            TokenTree::Punct(p) => {
+                let c = p.as_char();
+                match c {
+                    '.' | ':' => {
+                        src.push(c);
+                        prev = Prev::Joined;
+                    }
+                    ',' | ';' => {
+                        src.push(c);
+                        src.push(' ');
+                        prev = Prev::Start;
+                    }
+                    '?' => {
+                        src.push(c);
+                        prev = Prev::Operand;
+                    }
+                    '&' | '*' | '!' | '-' if prev != Prev::Operand && prev != Prev::JointOp => {
+                        src.push(c);
+                        prev = Prev::Joined;
+                    }
+                    _ => {
+                        if prev == Prev::Operand {
+                            src.push(' ');
+                        }
+                        src.push(c);
+                        match p.spacing() {
+                            Spacing::Joint => prev = Prev::JointOp,
+                            Spacing::Alone => {
+                                src.push(' ');
+                                prev = Prev::Op;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    src.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repr(strct: syn::ItemStruct) -> Option<&'static str> {
+        zero_copy_repr(&strct.attrs)
+    }
+
+    #[test]
+    fn zero_copy_reprs() {
+        assert_eq!(
+            repr(syn::parse_quote! { #[zero_copy] struct S; }),
+            Some("packed")
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[account(zero_copy)] struct S; }),
+            Some("packed")
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[account("ns", zero_copy)] struct S; }),
+            Some("packed")
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[anchor_lang::associated(zero_copy)] struct S; }),
+            Some("packed")
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[state(zero_copy)] struct S; }),
+            Some("packed")
+        );
+    }
+
+    #[test]
+    fn not_zero_copy() {
+        assert_eq!(repr(syn::parse_quote! { #[account] struct S; }), None);
+        // Only the `zero_copy` argument itself makes an account zero copy.
+        assert_eq!(
+            repr(syn::parse_quote! { #[account("zero_copy")] struct S; }),
+            None
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[account(not_zero_copy)] struct S; }),
+            None
+        );
+        assert_eq!(
+            repr(syn::parse_quote! {
+                /// Not zero_copy.
+                #[account]
+                #[derive(Clone)]
+                struct S;
+            }),
+            None
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[event(zero_copy)] struct S; }),
+            None
+        );
+        // A `#[repr]` alone doesn't make a struct zero copy.
+        assert_eq!(
+            repr(syn::parse_quote! { #[derive(Pod)] #[repr(C)] struct S; }),
+            None
+        );
+        assert_eq!(
+            repr(syn::parse_quote! { #[repr(C, packed)] struct S; }),
+            None
+        );
+    }
+}
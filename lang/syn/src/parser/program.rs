@@ -7,7 +7,7 @@ const STATE_STRUCT_ATTRIBUTE: &str = "state";
 // Reserved keyword for the constructor method.
 const CTOR_METHOD_NAME: &str = "new";
 
-pub fn parse(program_mod: syn::ItemMod) -> Program {
+pub fn parse(program_mod: syn::ItemMod, return_data: bool) -> Program {
     let mod_ident = &program_mod.ident;
     let mod_content = &program_mod.content.as_ref().unwrap().1;
 
@@ -265,6 +265,7 @@ pub fn parse(program_mod: syn::ItemMod) -> Program {
                 ident: method.sig.ident.clone(),
                 args,
                 anchor_ident,
+                returns: parse_returns(&method.sig),
             }
         })
         .collect();
@@ -274,6 +275,32 @@ pub fn parse(program_mod: syn::ItemMod) -> Program {
         ixs,
         name: mod_ident.clone(),
         program_mod,
+        return_data,
+    }
+}
+
+// Returns the `T` of a handler returning `Result<T>`. Handlers returning
+// `ProgramResult` or `Result<()>` don't return a value.
+fn parse_returns(sig: &syn::Signature) -> Option<syn::Type> {
+    let ty = match &sig.output {
+        syn::ReturnType::Type(_, ty) => ty,
+        syn::ReturnType::Default => return None,
+    };
+    let segment = match &**ty {
+        syn::Type::Path(p) => p.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Result" {
+        return None;
+    }
+    let generic_args = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args,
+        _ => return None,
+    };
+    match generic_args.args.first()? {
+        syn::GenericArgument::Type(syn::Type::Tuple(t)) if t.elems.is_empty() => None,
+        syn::GenericArgument::Type(ty) => Some(ty.clone()),
+        _ => None,
     }
 }
 
@@ -0,0 +1,85 @@
+//! Instructions returning a value deliver it as the program's return data,
+//! which is kept per thread off-chain.
+
+use anchor_lang::prelude::*;
+use anchor_lang::{return_data, InstructionData, Result};
+
+#[program(return_data)]
+pub mod returns {
+    use super::*;
+
+    pub fn double(_ctx: Context<Empty>, x: u64) -> Result<u64> {
+        Ok(2 * x)
+    }
+
+    pub fn quote(_ctx: Context<Empty>, price: u64) -> Result<Option<Quote>> {
+        Ok(match price {
+            0 => None,
+            price => Some(Quote { price, side: 1 }),
+        })
+    }
+
+    pub fn unit(_ctx: Context<Empty>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Empty {}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, PartialEq)]
+pub struct Quote {
+    pub price: u64,
+    pub side: u8,
+}
+
+fn execute(program_id: &Pubkey, ix: impl InstructionData) -> ProgramResult {
+    entry(program_id, &[], &ix.data())
+}
+
+#[test]
+fn returns_values() {
+    let program_id = Pubkey::new_unique();
+    execute(&program_id, instruction::Double { x: 21 }).unwrap();
+    assert_eq!(
+        return_data::get_return_data(),
+        Some((program_id, 42u64.to_le_bytes().to_vec()))
+    );
+    assert_eq!(return_data::get::<u64>(&program_id), Ok(42));
+
+    execute(&program_id, instruction::Quote { price: 10 }).unwrap();
+    assert_eq!(
+        return_data::get(&program_id),
+        Ok(Some(Quote { price: 10, side: 1 }))
+    );
+    execute(&program_id, instruction::Quote { price: 0 }).unwrap();
+    assert_eq!(return_data::get::<Option<Quote>>(&program_id), Ok(None));
+
+    // The return data was set by another program.
+    assert_eq!(
+        return_data::get::<Option<Quote>>(&Pubkey::new_unique()),
+        Err(ProgramError::IncorrectProgramId)
+    );
+}
+
+#[test]
+fn returns_nothing() {
+    let program_id = Pubkey::new_unique();
+    execute(&program_id, instruction::Unit).unwrap();
+    assert_eq!(return_data::get_return_data(), None);
+    assert_eq!(return_data::get::<()>(&program_id), Ok(()));
+
+    // Setting a value serialized to zero bytes clears the return data.
+    execute(&program_id, instruction::Double { x: 1 }).unwrap();
+    return_data::set(&program_id, &()).unwrap();
+    assert_eq!(return_data::get_return_data(), None);
+}
+
+#[test]
+#[should_panic(expected = "Return data too large")]
+fn return_data_too_large() {
+    return_data::set_return_data(
+        &Pubkey::new_unique(),
+        &[0; return_data::MAX_RETURN_DATA + 1],
+    );
+}
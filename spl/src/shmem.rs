@@ -1,5 +1,9 @@
 //! CPI API for interacting with the SPL shared memory
 //! [program](https://github.com/solana-labs/solana-program-library/tree/master/shared-memory).
+//!
+//! On clusters supporting return data, i.e., Solana 1.9 or later, prefer
+//! returning `Result<T>` from instruction handlers, which delivers `T` to
+//! the caller via `anchor_lang::return_data` with `#[program(return_data)]`.
 
 use anchor_lang::{Accounts, CpiContext};
 use solana_program::account_info::AccountInfo;
@@ -0,0 +1,419 @@
+//! `anchor_test` provides a native Rust harness for integration testing
+//! Anchor programs, without booting a local validator or using Node.
+//!
+//! Programs are executed by `solana_program_test`, either natively, by
+//! providing the program's entrypoint via the `processor!` macro, or as the
+//! compiled BPF binaries built by `anchor build`, which `anchor test --rust`
+//! makes available by setting `BPF_OUT_DIR` to `target/deploy`.
+//!
+//! The `Program` handle mirrors `anchor_client::Program`, so tests are written
+//! the same way as clients, e.g.,
+//!
+//! ```ignore
+//! let harness = HarnessBuilder::new()
+//!     .program("basic_2", program_id, processor!(basic_2::entry))
+//!     .start()?;
+//! let program = harness.program(program_id);
+//! program
+//!     .request()
+//!     .accounts(basic_2::accounts::Create { .. })
+//!     .args(basic_2::instruction::Create { authority })
+//!     .send()?;
+//! let counter: basic_2::Counter = program.account(counter)?;
+//! ```
+
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::system_program;
+use anchor_lang::solana_program::sysvar::rent;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::account::Account;
+use solana_sdk::process_instruction::ProcessInstructionWithContext;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_sdk::transport::TransportError;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::runtime::Runtime;
+
+pub use anchor_lang;
+pub use solana_program_test::{self, processor};
+pub use solana_sdk;
+
+/// HarnessBuilder configures the programs and accounts loaded into the test
+/// environment.
+pub struct HarnessBuilder {
+    program_test: ProgramTest,
+}
+
+impl HarnessBuilder {
+    pub fn new() -> Self {
+        Self {
+            program_test: ProgramTest::default(),
+        }
+    }
+
+    /// Adds a program to the test environment. If `processor` is `None`, or
+    /// `BPF_OUT_DIR` is set, the compiled `<name>.so` binary is loaded instead
+    /// of running the program natively.
+    pub fn program(
+        mut self,
+        name: &str,
+        program_id: Pubkey,
+        processor: Option<ProcessInstructionWithContext>,
+    ) -> Self {
+        self.program_test.add_program(name, program_id, processor);
+        self
+    }
+
+    /// Adds an account to the test environment's genesis.
+    pub fn account(mut self, address: Pubkey, account: Account) -> Self {
+        self.program_test.add_account(address, account);
+        self
+    }
+
+    pub fn start(self) -> Result<Harness, ClientError> {
+        let runtime = Runtime::new()?;
+        let (banks, payer, _recent_hash) = runtime.block_on(self.program_test.start());
+        Ok(Harness {
+            runtime: Arc::new(runtime),
+            banks,
+            payer,
+        })
+    }
+}
+
+impl Default for HarnessBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Harness is a running test environment, from which `Program` clients are
+/// built.
+pub struct Harness {
+    runtime: Arc<Runtime>,
+    banks: BanksClient,
+    payer: Keypair,
+}
+
+impl Harness {
+    /// Returns the funded payer of the test environment.
+    pub fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    pub fn program(&self, program_id: Pubkey) -> Program {
+        Program {
+            program_id,
+            runtime: self.runtime.clone(),
+            banks: self.banks.clone(),
+            payer: Keypair::from_bytes(&self.payer.to_bytes()).unwrap(),
+        }
+    }
+
+    /// Returns the raw account at the given address, if it exists.
+    pub fn get_account(&self, address: Pubkey) -> Result<Option<Account>, ClientError> {
+        let mut banks = self.banks.clone();
+        self.runtime
+            .block_on(banks.get_account(address))
+            .map_err(Into::into)
+    }
+}
+
+/// Program is the client handle to a program in the test environment, with
+/// the same interface as `anchor_client::Program`.
+pub struct Program {
+    program_id: Pubkey,
+    runtime: Arc<Runtime>,
+    banks: BanksClient,
+    payer: Keypair,
+}
+
+impl Program {
+    pub fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    pub fn id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// Returns a request builder.
+    pub fn request(&self) -> RequestBuilder {
+        self.request_builder(RequestNamespace::Global)
+    }
+
+    /// Returns a request builder for program state.
+    pub fn state_request(&self) -> RequestBuilder {
+        self.request_builder(RequestNamespace::State { new: false })
+    }
+
+    fn request_builder(&self, namespace: RequestNamespace) -> RequestBuilder {
+        RequestBuilder {
+            program_id: self.program_id,
+            runtime: self.runtime.clone(),
+            banks: self.banks.clone(),
+            payer: Keypair::from_bytes(&self.payer.to_bytes()).unwrap(),
+            accounts: Vec::new(),
+            instructions: Vec::new(),
+            instruction_data: None,
+            signers: Vec::new(),
+            namespace,
+        }
+    }
+
+    /// Returns the account at the given address.
+    pub fn account<T: AccountDeserialize>(&self, address: Pubkey) -> Result<T, ClientError> {
+        let mut banks = self.banks.clone();
+        let account = self
+            .runtime
+            .block_on(banks.get_account(address))?
+            .ok_or(ClientError::AccountNotFound)?;
+        let mut data: &[u8] = &account.data;
+        T::try_deserialize(&mut data).map_err(Into::into)
+    }
+
+    pub fn state<T: AccountDeserialize>(&self) -> Result<T, ClientError> {
+        self.account(anchor_lang::__private::state::address(&self.program_id))
+    }
+}
+
+#[derive(PartialEq)]
+enum RequestNamespace {
+    Global,
+    State {
+        // True if the request is to the state's new ctor.
+        new: bool,
+    },
+}
+
+/// `RequestBuilder` provides a builder interface to create and process
+/// transactions in the test environment.
+pub struct RequestBuilder<'a> {
+    program_id: Pubkey,
+    runtime: Arc<Runtime>,
+    banks: BanksClient,
+    payer: Keypair,
+    accounts: Vec<AccountMeta>,
+    instructions: Vec<Instruction>,
+    // Serialized instruction data for the target RPC.
+    instruction_data: Option<Vec<u8>>,
+    signers: Vec<&'a dyn Signer>,
+    namespace: RequestNamespace,
+}
+
+impl<'a> RequestBuilder<'a> {
+    pub fn payer(mut self, payer: Keypair) -> Self {
+        self.payer = payer;
+        self
+    }
+
+    pub fn instruction(mut self, ix: Instruction) -> Self {
+        self.instructions.push(ix);
+        self
+    }
+
+    pub fn program(mut self, program_id: Pubkey) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    pub fn accounts(mut self, accounts: impl ToAccountMetas) -> Self {
+        let mut metas = accounts.to_account_metas(None);
+        self.accounts.append(&mut metas);
+        self
+    }
+
+    pub fn args(mut self, args: impl InstructionData) -> Self {
+        self.instruction_data = Some(args.data());
+        self
+    }
+
+    /// Invokes the `#[state]`'s `new` constructor.
+    pub fn new(mut self, args: impl InstructionData) -> Self {
+        assert!(self.namespace == RequestNamespace::State { new: false });
+        self.namespace = RequestNamespace::State { new: true };
+        self.instruction_data = Some(args.data());
+        self
+    }
+
+    pub fn signer(mut self, signer: &'a dyn Signer) -> Self {
+        self.signers.push(signer);
+        self
+    }
+
+    pub fn send(self) -> Result<Signature, ClientError> {
+        let accounts = match self.namespace {
+            RequestNamespace::State { new } => {
+                let mut accounts = match new {
+                    false => vec![AccountMeta::new(
+                        anchor_lang::__private::state::address(&self.program_id),
+                        false,
+                    )],
+                    true => vec![
+                        AccountMeta::new_readonly(self.payer.pubkey(), true),
+                        AccountMeta::new(
+                            anchor_lang::__private::state::address(&self.program_id),
+                            false,
+                        ),
+                        AccountMeta::new_readonly(
+                            Pubkey::find_program_address(&[], &self.program_id).0,
+                            false,
+                        ),
+                        AccountMeta::new_readonly(system_program::ID, false),
+                        AccountMeta::new_readonly(self.program_id, false),
+                        AccountMeta::new_readonly(rent::ID, false),
+                    ],
+                };
+                accounts.extend_from_slice(&self.accounts);
+                accounts
+            }
+            RequestNamespace::Global => self.accounts,
+        };
+        let mut instructions = self.instructions;
+        if let Some(ix_data) = self.instruction_data {
+            instructions.push(Instruction {
+                program_id: self.program_id,
+                data: ix_data,
+                accounts,
+            });
+        }
+
+        let mut signers = self.signers;
+        signers.push(&self.payer);
+
+        let mut banks = self.banks;
+        let recent_hash = self.runtime.block_on(banks.get_recent_blockhash())?;
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.payer.pubkey()),
+            &signers,
+            recent_hash,
+        );
+        let signature = tx.signatures[0];
+        self.runtime.block_on(banks.process_transaction(tx))?;
+        Ok(signature)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("{0}")]
+    ProgramError(#[from] ProgramError),
+    #[error("{0}")]
+    TransportError(#[from] TransportError),
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::borsh;
+    use anchor_lang::solana_program::account_info::AccountInfo;
+    use anchor_lang::solana_program::entrypoint::ProgramResult;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl AccountDeserialize for Counter {
+        fn try_deserialize(buf: &mut &[u8]) -> Result<Self, ProgramError> {
+            Counter::try_deserialize_unchecked(buf)
+        }
+
+        fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self, ProgramError> {
+            AnchorDeserialize::deserialize(buf).map_err(|_| ProgramError::InvalidAccountData)
+        }
+    }
+
+    #[derive(AnchorSerialize)]
+    struct Increment {
+        by: u64,
+    }
+
+    impl InstructionData for Increment {
+        fn data(&self) -> Vec<u8> {
+            self.try_to_vec().unwrap()
+        }
+    }
+
+    struct IncrementAccounts {
+        counter: Pubkey,
+    }
+
+    impl ToAccountMetas for IncrementAccounts {
+        fn to_account_metas(&self, _is_signer: Option<bool>) -> Vec<AccountMeta> {
+            vec![AccountMeta::new(self.counter, false)]
+        }
+    }
+
+    // A mock program, run natively, incrementing the `Counter` owned by it.
+    fn process_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        mut data: &[u8],
+    ) -> ProgramResult {
+        let counter = &accounts[0];
+        if counter.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let by = u64::deserialize(&mut data)?;
+        let mut account = Counter::try_deserialize(&mut &counter.try_borrow_data()?[..])?;
+        account.count += by;
+        account.serialize(&mut &mut counter.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    fn counter_account(owner: Pubkey, count: u64) -> Account {
+        Account {
+            lamports: 1_000_000_000,
+            data: Counter { count }.try_to_vec().unwrap(),
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn mock_program() {
+        let program_id = Pubkey::new_unique();
+        let (counter, unowned) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let harness = HarnessBuilder::new()
+            .program("counter", program_id, processor!(process_instruction))
+            .account(counter, counter_account(program_id, 1))
+            .account(unowned, counter_account(Pubkey::new_unique(), 1))
+            .start()
+            .unwrap();
+        let program = harness.program(program_id);
+
+        program
+            .request()
+            .accounts(IncrementAccounts { counter })
+            .args(Increment { by: 2 })
+            .send()
+            .unwrap();
+        let account: Counter = program.account(counter).unwrap();
+        assert_eq!(account.count, 3);
+
+        // Program errors fail the transaction.
+        assert!(program
+            .request()
+            .accounts(IncrementAccounts { counter: unowned })
+            .args(Increment { by: 2 })
+            .send()
+            .is_err());
+        let account: Counter = program.account(unowned).unwrap();
+        assert_eq!(account.count, 1);
+
+        assert!(harness.get_account(Pubkey::new_unique()).unwrap().is_none());
+        assert!(program.account::<Counter>(Pubkey::new_unique()).is_err());
+    }
+}
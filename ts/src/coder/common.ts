@@ -2,6 +2,7 @@ import { snakeCase } from "snake-case";
 import { sha256 } from "js-sha256";
 import { Idl, IdlField, IdlTypeDef, IdlEnumVariant, IdlType } from "../idl";
 import { IdlError } from "../error";
+import { instantiate } from "./idl";
 
 export function accountSize(
   idl: Idl,
@@ -33,9 +34,16 @@ export function accountSize(
   if (idlAccount.type.fields === undefined) {
     return 0;
   }
-  return idlAccount.type.fields
-    .map((f) => typeSize(idl, f.type))
-    .reduce((a, b) => a + b);
+  return (
+    idlAccount.type.fields
+      // @ts-ignore
+      .map((f: IdlField | IdlType) =>
+        // Tuple struct fields are types.
+        // @ts-ignore
+        typeSize(idl, f.name === undefined ? f : f.type)
+      )
+      .reduce((a: number, b: number) => a + b, 0)
+  );
 }
 
 // Returns the size of the type in bytes. For variable length types, just return
@@ -56,14 +64,22 @@ function typeSize(idl: Idl, ty: IdlType): number {
       return 4;
     case "i32":
       return 4;
+    case "f32":
+      return 4;
     case "u64":
       return 8;
     case "i64":
       return 8;
+    case "f64":
+      return 8;
     case "u128":
       return 16;
     case "i128":
       return 16;
+    case "u256":
+      return 32;
+    case "i256":
+      return 32;
     case "bytes":
       return 1;
     case "string":
@@ -92,6 +108,16 @@ function typeSize(idl: Idl, ty: IdlType): number {
         return accountSize(idl, typeDef);
       }
       // @ts-ignore
+      if (ty.definedWithTypeArgs !== undefined) {
+        // @ts-ignore
+        const { name, args } = ty.definedWithTypeArgs;
+        const filtered = idl.types.filter((t) => t.name === name);
+        if (filtered.length !== 1) {
+          throw new IdlError(`Type not found: ${JSON.stringify(ty)}`);
+        }
+        return accountSize(idl, instantiate(filtered[0], args));
+      }
+      // @ts-ignore
       if (ty.array !== undefined) {
         // @ts-ignore
         let arrayTy = ty.array[0];
@@ -100,6 +126,21 @@ function typeSize(idl: Idl, ty: IdlType): number {
         // @ts-ignore
         return typeSize(idl, arrayTy) * arraySize;
       }
+      // @ts-ignore
+      if (ty.byteArray !== undefined) {
+        // @ts-ignore
+        return ty.byteArray;
+      }
+      // @ts-ignore
+      if (ty.tuple !== undefined) {
+        // @ts-ignore
+        return ty.tuple.reduce((size, t) => size + typeSize(idl, t), 0);
+      }
+      // @ts-ignore
+      if (ty.hashMap !== undefined || ty.btreeMap !== undefined) {
+        // The length of an empty map.
+        return 4;
+      }
       throw new Error(`Invalid type ${JSON.stringify(ty)}`);
   }
 }
@@ -1,5 +1,6 @@
 import camelCase from "camelcase";
-import { Layout } from "buffer-layout";
+import BN from "bn.js";
+import { Layout, f32, f64 } from "buffer-layout";
 import * as borsh from "@project-serum/borsh";
 import { IdlField, IdlTypeDef, IdlEnumVariant, IdlType } from "../idl";
 import { IdlError } from "../error";
@@ -30,18 +31,30 @@ export class IdlCoder {
       case "i32": {
         return borsh.i32(fieldName);
       }
+      case "f32": {
+        return f32(fieldName);
+      }
       case "u64": {
         return borsh.u64(fieldName);
       }
       case "i64": {
         return borsh.i64(fieldName);
       }
+      case "f64": {
+        return f64(fieldName);
+      }
       case "u128": {
         return borsh.u128(fieldName);
       }
       case "i128": {
         return borsh.i128(fieldName);
       }
+      case "u256": {
+        return new BN256Layout(false, fieldName);
+      }
+      case "i256": {
+        return new BN256Layout(true, fieldName);
+      }
       case "bytes": {
         return borsh.vecU8(fieldName);
       }
@@ -91,6 +104,22 @@ export class IdlCoder {
           }
           return IdlCoder.typeDefLayout(filtered[0], types, fieldName);
           // @ts-ignore
+        } else if (field.type.definedWithTypeArgs) {
+          if (types === undefined) {
+            throw new IdlError("User defined types not provided");
+          }
+          // @ts-ignore
+          const { name, args } = field.type.definedWithTypeArgs;
+          const filtered = types.filter((t) => t.name === name);
+          if (filtered.length !== 1) {
+            throw new IdlError(`Type not found: ${JSON.stringify(field)}`);
+          }
+          return IdlCoder.typeDefLayout(
+            instantiate(filtered[0], args),
+            types,
+            fieldName
+          );
+          // @ts-ignore
         } else if (field.type.array) {
           // @ts-ignore
           let arrayTy = field.type.array[0];
@@ -104,6 +133,28 @@ export class IdlCoder {
             types
           );
           return borsh.array(innerLayout, arrayLen, fieldName);
+          // @ts-ignore
+        } else if (field.type.byteArray !== undefined) {
+          // @ts-ignore
+          return borsh.array(borsh.u8(), field.type.byteArray, fieldName);
+          // @ts-ignore
+        } else if (field.type.tuple) {
+          // Decoded as an object keyed by index.
+          // @ts-ignore
+          const fieldLayouts = field.type.tuple.map(
+            (ty: IdlType, idx: number) =>
+              IdlCoder.fieldLayout({ name: `${idx}`, type: ty }, types)
+          );
+          return borsh.struct(fieldLayouts, fieldName);
+          // @ts-ignore
+        } else if (field.type.hashMap || field.type.btreeMap) {
+          // @ts-ignore
+          const [keyTy, valueTy] = field.type.hashMap || field.type.btreeMap;
+          return new MapLayout(
+            IdlCoder.fieldLayout({ name: "key", type: keyTy }, types),
+            IdlCoder.fieldLayout({ name: "value", type: valueTy }, types),
+            fieldName
+          );
         } else {
           throw new Error(`Not yet implemented: ${field}`);
         }
@@ -117,9 +168,19 @@ export class IdlCoder {
     name?: string
   ): Layout {
     if (typeDef.type.kind === "struct") {
-      const fieldLayouts = typeDef.type.fields.map((field) => {
-        const x = IdlCoder.fieldLayout(field, types);
-        return x;
+      // Unit structs have no fields, and tuple struct fields are named by
+      // index.
+      const fields = typeDef.type.fields || [];
+      // @ts-ignore
+      const fieldLayouts = fields.map((field: IdlField | IdlType, idx) => {
+        // @ts-ignore
+        if (field.name === undefined) {
+          return IdlCoder.fieldLayout(
+            { name: `${idx}`, type: field as IdlType },
+            types
+          );
+        }
+        return IdlCoder.fieldLayout(field as IdlField, types);
       });
       return borsh.struct(fieldLayouts, name);
     } else if (typeDef.type.kind === "enum") {
@@ -152,3 +213,84 @@ export class IdlCoder {
     }
   }
 }
+
+// Layout of 256 bit integers, decoded as a `BN`, which
+// `@project-serum/borsh` has no layout for.
+class BN256Layout extends Layout<BN> {
+  constructor(private signed: boolean, property?: string) {
+    super(32, property);
+  }
+
+  decode(b: Buffer, offset = 0): BN {
+    const num = new BN(b.slice(offset, offset + this.span), 10, "le");
+    return this.signed ? num.fromTwos(this.span * 8) : num;
+  }
+
+  encode(src: BN, b: Buffer, offset = 0): number {
+    const num = this.signed ? src.toTwos(this.span * 8) : src;
+    num.toArrayLike(Buffer, "le", this.span).copy(b, offset);
+    return this.span;
+  }
+}
+
+// Layout of `HashMap`s and `BTreeMap`s, decoded as a `Map`. Borsh encodes
+// them as a vector of key value pairs. Maps are encoded in iteration order,
+// so `BTreeMap` keys must be inserted in order.
+class MapLayout<K, V> extends Layout<Map<K, V>> {
+  private entries: Layout<{ key: K; value: V }[]>;
+
+  constructor(key: Layout<K>, value: Layout<V>, property?: string) {
+    super(-1, property);
+    this.entries = borsh.vec(
+      borsh.struct<{ key: K; value: V }>([key, value])
+    );
+  }
+
+  decode(b: Buffer, offset = 0): Map<K, V> {
+    return new Map(
+      this.entries
+        .decode(b, offset)
+        .map(({ key, value }) => [key, value] as [K, V])
+    );
+  }
+
+  encode(src: Map<K, V>, b: Buffer, offset = 0): number {
+    const entries = Array.from(src.entries()).map(([key, value]) => ({
+      key,
+      value,
+    }));
+    return this.entries.encode(entries, b, offset);
+  }
+
+  getSpan(b: Buffer, offset = 0): number {
+    return this.entries.getSpan(b, offset);
+  }
+}
+
+// Substitutes the type arguments for the type parameters of a generic type.
+export function instantiate(typeDef: IdlTypeDef, args: IdlType[]): IdlTypeDef {
+  const generics = typeDef.generics || [];
+  if (generics.length !== args.length) {
+    throw new IdlError(
+      `Expected ${generics.length} type arguments for ${typeDef.name}`
+    );
+  }
+  const substitute = (x: any): any => {
+    if (Array.isArray(x)) {
+      return x.map(substitute);
+    }
+    if (x !== null && typeof x === "object") {
+      if (Object.keys(x).length === 1 && typeof x.generic === "string") {
+        const idx = generics.indexOf(x.generic);
+        if (idx !== -1) {
+          return args[idx];
+        }
+      }
+      return Object.fromEntries(
+        Object.entries(x).map(([k, v]) => [k, substitute(v)])
+      );
+    }
+    return x;
+  };
+  return { ...substitute(typeDef), generics: undefined };
+}
@@ -3,13 +3,17 @@ import * as borsh from "@project-serum/borsh";
 
 export type Idl = {
   version: string;
+  // Version of the IDL format. Absent from IDLs predating it.
+  spec?: string;
   name: string;
+  docs?: string[];
   instructions: IdlInstruction[];
   state?: IdlState;
   accounts?: IdlTypeDef[];
   types?: IdlTypeDef[];
   events?: IdlEvent[];
   errors?: IdlErrorCode[];
+  constants?: IdlConst[];
 };
 
 export type IdlEvent = {
@@ -25,8 +29,10 @@ export type IdlEventField = {
 
 export type IdlInstruction = {
   name: string;
+  docs?: string[];
   accounts: IdlAccountItem[];
   args: IdlField[];
+  returns?: IdlType;
 };
 
 export type IdlState = {
@@ -40,29 +46,74 @@ export type IdlAccountItem = IdlAccount | IdlAccounts;
 
 export type IdlAccount = {
   name: string;
+  docs?: string[];
   isMut: boolean;
   isSigner: boolean;
+  relations?: string[];
+  owner?: string;
+  isExecutable?: boolean;
+  state?: string;
+  pda?: IdlPda;
+  init?: IdlInit;
+  constraints?: string[];
+};
+
+export type IdlPda = {
+  seeds: IdlSeed[];
+  findBump?: boolean;
+};
+
+export type IdlSeed =
+  | { kind: "const"; value: number[] }
+  | { kind: "account"; path: string }
+  | { kind: "expr"; expr: string };
+
+export type IdlInit = {
+  payer?: string;
+  space?: string;
 };
 
 // A nested/recursive version of IdlAccount.
 export type IdlAccounts = {
   name: string;
+  docs?: string[];
   accounts: IdlAccountItem[];
 };
 
 export type IdlField = {
   name: string;
+  docs?: string[];
   type: IdlType;
 };
 
 export type IdlTypeDef = {
   name: string;
+  docs?: string[];
   type: IdlTypeDefTy;
+  generics?: string[];
+  // Defaults to "borsh". Zero copy types are "bytemuck".
+  serialization?: "borsh" | "bytemuck";
+  repr?: "c" | "packed";
+  layout?: IdlLayout;
+};
+
+// Layout of a zero copy type, in bytes.
+export type IdlLayout = {
+  size: number;
+  align: number;
+  fields: IdlFieldLayout[];
+};
+
+export type IdlFieldLayout = {
+  offset: number;
+  size: number;
+  padding: number;
 };
 
 type IdlTypeDefTy = {
   kind: "struct" | "enum";
-  fields?: IdlTypeDefStruct;
+  // Undefined for unit structs.
+  fields?: IdlTypeDefStruct | IdlEnumFieldsTuple;
   variants?: IdlEnumVariant[];
 };
 
@@ -76,17 +127,27 @@ export type IdlType =
   | "i16"
   | "u32"
   | "i32"
+  | "f32"
   | "u64"
   | "i64"
+  | "f64"
   | "u128"
   | "i128"
+  | "u256"
+  | "i256"
   | "bytes"
   | "string"
   | "publicKey"
   | IdlTypeVec
   | IdlTypeOption
   | IdlTypeDefined
-  | IdlTypeArray;
+  | IdlTypeDefinedWithTypeArgs
+  | IdlTypeGeneric
+  | IdlTypeArray
+  | IdlTypeByteArray
+  | IdlTypeTuple
+  | IdlTypeHashMap
+  | IdlTypeBTreeMap;
 
 export type IdlTypeVec = {
   vec: IdlType;
@@ -101,11 +162,40 @@ export type IdlTypeDefined = {
   defined: string;
 };
 
+// Generic user defined type.
+export type IdlTypeDefinedWithTypeArgs = {
+  definedWithTypeArgs: {
+    name: string;
+    args: IdlType[];
+  };
+};
+
+// Type parameter of the enclosing type definition.
+export type IdlTypeGeneric = {
+  generic: string;
+};
+
 // BRADFORD Arrary type
 export type IdlTypeArray = {
   array: IdlType;
 };
 
+export type IdlTypeByteArray = {
+  byteArray: number;
+};
+
+export type IdlTypeTuple = {
+  tuple: IdlType[];
+};
+
+export type IdlTypeHashMap = {
+  hashMap: [IdlType, IdlType];
+};
+
+export type IdlTypeBTreeMap = {
+  btreeMap: [IdlType, IdlType];
+};
+
 export type IdlEnumVariant = {
   name: string;
   fields?: IdlEnumFields;
@@ -123,6 +213,15 @@ type IdlErrorCode = {
   msg?: string;
 };
 
+// A `#[constant]`, with its value as a Rust literal, or as written in the
+// program's source if it couldn't be evaluated.
+export type IdlConst = {
+  name: string;
+  type: IdlType;
+  value: string;
+  docs?: string[];
+};
+
 // Deterministic IDL address as a function of the program id.
 export async function idlAddress(programId: PublicKey): Promise<PublicKey> {
   const base = (await PublicKey.findProgramAddress([], programId))[0];
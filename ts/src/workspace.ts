@@ -63,13 +63,12 @@ const workspace = new Proxy({} as any, {
         fs.readFileSync(path.join(projectRoot, "Anchor.toml"), "utf-8")
       );
       const clusterId = anchorToml.provider.cluster;
-      if (anchorToml.clusters && anchorToml.clusters[clusterId]) {
-        attachWorkspaceOverride(
-          workspaceCache,
-          anchorToml.clusters[clusterId],
-          idlMap
-        );
-      }
+      // `clusters` is the deprecated name for `programs`.
+      [anchorToml.clusters, anchorToml.programs].forEach((programs) => {
+        if (programs && programs[clusterId]) {
+          attachWorkspaceOverride(workspaceCache, programs[clusterId], idlMap);
+        }
+      });
 
       _populatedWorkspace = true;
     }